@@ -0,0 +1,886 @@
+use num_bigint::BigUint;
+use serde_derive::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    time::Duration,
+};
+use tokio::{
+    sync::mpsc,
+    time::{self, Instant},
+};
+
+use crate::{
+    blockchain::{
+        block::Block,
+        consensus::{BftEngine, ValidatorSet},
+        crypto::{self, Signer},
+        encoding::encode_u256,
+        serde_hex,
+    },
+    helpers::{get_current_timestamp, keccak256_bytes},
+    SharedState,
+};
+
+// Commits kept to answer peers that fell behind.
+const KEPT_COMMITS: usize = 256;
+// Messages for later heights kept until the node catches up.
+const MAX_FUTURE_MESSAGES: usize = 1024;
+
+// First byte of what is signed, so a signature over one kind of message
+// cannot be passed off as another.
+const PREVOTE_MESSAGE: u8 = 0;
+const PRECOMMIT_MESSAGE: u8 = 1;
+const PROPOSAL_MESSAGE: u8 = 2;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum VoteKind {
+    Prevote,
+    Precommit,
+}
+
+// Votes are signed by the validator they name, so no peer can vote on
+// another's behalf.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct BftVote {
+    pub kind: VoteKind,
+    pub height: u32,
+    pub round: u32,
+    // `None` votes for nil.
+    #[serde(with = "serde_hex::option_biguint")]
+    pub block_hash: Option<BigUint>,
+    #[serde(with = "serde_hex::biguint")]
+    pub validator: BigUint,
+    #[serde(with = "serde_hex::bytes")]
+    pub signature: Vec<u8>,
+}
+
+impl BftVote {
+    pub fn new(
+        kind: VoteKind,
+        height: u32,
+        round: u32,
+        block_hash: Option<BigUint>,
+        signer: &Signer,
+    ) -> Option<Self> {
+        let vote = BftVote {
+            kind,
+            height,
+            round,
+            block_hash,
+            validator: signer.address(),
+            signature: Vec::new(),
+        };
+        let signature = signer.sign(&vote.hash()?)?;
+
+        Some(BftVote { signature, ..vote })
+    }
+
+    // Hash of everything but the signature, which is what gets signed.
+    pub fn hash(&self) -> Option<BigUint> {
+        let mut bytes = vec![match self.kind {
+            VoteKind::Prevote => PREVOTE_MESSAGE,
+            VoteKind::Precommit => PRECOMMIT_MESSAGE,
+        }];
+
+        bytes.extend_from_slice(&self.height.to_be_bytes());
+        bytes.extend_from_slice(&self.round.to_be_bytes());
+
+        match &self.block_hash {
+            Some(block_hash) => {
+                bytes.push(1);
+                bytes.extend_from_slice(&encode_u256(block_hash).ok()?);
+            }
+            None => bytes.push(0),
+        }
+
+        bytes.extend_from_slice(&encode_u256(&self.validator).ok()?);
+
+        Some(keccak256_bytes(&bytes))
+    }
+
+    // Whether the signature recovers to the validator the vote names.
+    pub fn is_signed(&self) -> bool {
+        self.hash()
+            .and_then(|hash| crypto::recover(&hash, &self.signature))
+            .is_some_and(|signer| signer == self.validator)
+    }
+}
+
+// A block put forward for a round, signed by the proposer it names.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BftProposal {
+    pub height: u32,
+    pub round: u32,
+    // Round in which a re-proposed block got a prevote quorum.
+    pub valid_round: Option<u32>,
+    #[serde(with = "serde_hex::biguint")]
+    pub proposer: BigUint,
+    pub block: Block,
+    #[serde(with = "serde_hex::bytes")]
+    pub signature: Vec<u8>,
+}
+
+impl BftProposal {
+    pub fn new(
+        height: u32,
+        round: u32,
+        valid_round: Option<u32>,
+        block: Block,
+        signer: &Signer,
+    ) -> Option<Self> {
+        let proposal = BftProposal {
+            height,
+            round,
+            valid_round,
+            proposer: signer.address(),
+            block,
+            signature: Vec::new(),
+        };
+        let signature = signer.sign(&proposal.hash()?)?;
+
+        Some(BftProposal {
+            signature,
+            ..proposal
+        })
+    }
+
+    // Hash of everything but the signature, which is what gets signed.
+    pub fn hash(&self) -> Option<BigUint> {
+        let mut bytes = vec![PROPOSAL_MESSAGE];
+
+        bytes.extend_from_slice(&self.height.to_be_bytes());
+        bytes.extend_from_slice(&self.round.to_be_bytes());
+
+        match self.valid_round {
+            Some(valid_round) => {
+                bytes.push(1);
+                bytes.extend_from_slice(&valid_round.to_be_bytes());
+            }
+            None => bytes.push(0),
+        }
+
+        bytes.extend_from_slice(&encode_u256(&self.block.hash()?).ok()?);
+        bytes.extend_from_slice(&encode_u256(&self.proposer).ok()?);
+
+        Some(keccak256_bytes(&bytes))
+    }
+
+    // Whether the signature recovers to the proposer the proposal names.
+    pub fn is_signed(&self) -> bool {
+        self.hash()
+            .and_then(|hash| crypto::recover(&hash, &self.signature))
+            .is_some_and(|signer| signer == self.proposer)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BftMessage {
+    Proposal(BftProposal),
+    Vote(BftVote),
+    // A committed block and the precommits that committed it, so nodes that
+    // missed the rounds can still add it.
+    Commit {
+        block: Block,
+        precommits: Vec<BftVote>,
+    },
+    // Asks peers for the commit at `height`.
+    Sync {
+        height: u32,
+    },
+}
+
+impl BftMessage {
+    // Height the message is about; `Sync` is answered at any height.
+    fn height(&self) -> Option<u32> {
+        match self {
+            BftMessage::Proposal(proposal) => Some(proposal.height),
+            BftMessage::Vote(vote) => Some(vote.height),
+            BftMessage::Commit { block, .. } => Some(block.number()),
+            BftMessage::Sync { .. } => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Step {
+    // Waiting out the pause after a commit.
+    NewHeight,
+    Propose,
+    Prevote,
+    Precommit,
+}
+
+// Rules that fire at most once per round.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Rule {
+    PrevoteTimeout,
+    PrecommitTimeout,
+    UpdateValid,
+}
+
+struct Timeout {
+    deadline: Instant,
+    height: u32,
+    round: u32,
+    step: Step,
+}
+
+// Everything the node knows about the height it is deciding.
+struct HeightState {
+    height: u32,
+    round: u32,
+    step: Step,
+    validators: ValidatorSet,
+    // Block this node precommitted and the round it did so in; it prevotes
+    // nothing else until a later round proves the others moved on.
+    locked: Option<(u32, Block)>,
+    // Latest block seen with a prevote quorum, re-proposed by this node.
+    valid: Option<(u32, Block)>,
+    proposals: HashMap<u32, (Block, Option<u32>)>,
+    // Each validator's first vote of a kind in a round.
+    votes: HashMap<(u32, VoteKind), HashMap<BigUint, BftVote>>,
+    // Validators heard from in each round.
+    senders: HashMap<u32, HashSet<BigUint>>,
+    fired: HashSet<(u32, Rule)>,
+    // Validation results by block hash.
+    checked: HashMap<BigUint, bool>,
+}
+
+impl HeightState {
+    fn new(height: u32, validators: ValidatorSet) -> Self {
+        HeightState {
+            height,
+            round: 0,
+            step: Step::NewHeight,
+            validators,
+            locked: None,
+            valid: None,
+            proposals: HashMap::new(),
+            votes: HashMap::new(),
+            senders: HashMap::new(),
+            fired: HashSet::new(),
+            checked: HashMap::new(),
+        }
+    }
+
+    // More than two thirds of the validators.
+    fn quorum(&self) -> usize {
+        self.validators.len() * 2 / 3 + 1
+    }
+
+    // Enough validators that at least one of them is honest.
+    fn honest_minimum(&self) -> usize {
+        self.validators.len() / 3 + 1
+    }
+
+    fn count(&self, round: u32, kind: VoteKind, block_hash: Option<&BigUint>) -> usize {
+        self.votes.get(&(round, kind)).map_or(0, |votes| {
+            votes
+                .values()
+                .filter(|vote| vote.block_hash.as_ref() == block_hash)
+                .count()
+        })
+    }
+
+    fn count_any(&self, round: u32, kind: VoteKind) -> usize {
+        self.votes.get(&(round, kind)).map_or(0, HashMap::len)
+    }
+
+    fn precommits(&self, round: u32, block_hash: &BigUint) -> Vec<BftVote> {
+        self.votes
+            .get(&(round, VoteKind::Precommit))
+            .into_iter()
+            .flatten()
+            .map(|(_, vote)| vote)
+            .filter(|vote| vote.block_hash.as_ref() == Some(block_hash))
+            .cloned()
+            .collect()
+    }
+
+    // Whether `precommits` hold a quorum of distinct validators for `block`
+    // in a single round, each signed by the validator it names. Precommits
+    // from different rounds do not add up, since two quorums only have to
+    // share an honest validator when they are of the same round.
+    fn is_commit(&self, block: &Block, precommits: &[BftVote]) -> bool {
+        let block_hash = match block.hash() {
+            Some(block_hash) => block_hash,
+            None => return false,
+        };
+
+        let mut signers: HashMap<u32, HashSet<&BigUint>> = HashMap::new();
+
+        for vote in precommits.iter().filter(|vote| {
+            vote.kind == VoteKind::Precommit
+                && vote.height == self.height
+                && vote.block_hash.as_ref() == Some(&block_hash)
+                && self.validators.contains(&vote.validator)
+                && vote.is_signed()
+        }) {
+            signers
+                .entry(vote.round)
+                .or_default()
+                .insert(&vote.validator);
+        }
+
+        signers.values().any(|round| round.len() >= self.quorum())
+    }
+}
+
+// Drives one node through the rounds of the BFT engine: proposing when it is
+// its turn, voting, and committing blocks once the validators agree.
+// Messages go out through `outbound` and come in through `inbound`, which
+// `PeerManager` connects to its peers. Nodes without a `signer` only follow
+// the commits.
+pub struct BftNode {
+    shared_state: SharedState,
+    signer: Option<Signer>,
+    timeout: Duration,
+    outbound: mpsc::UnboundedSender<BftMessage>,
+    inbound: mpsc::UnboundedReceiver<BftMessage>,
+    state: HeightState,
+    timeouts: Vec<Timeout>,
+    future: Vec<BftMessage>,
+    replay: Vec<BftMessage>,
+    // This node's proposal and votes at the current height, sent again every
+    // timeout in case peers missed them.
+    own: Vec<BftMessage>,
+    commits: BTreeMap<u32, (Block, Vec<BftVote>)>,
+    // Height last asked for and when.
+    synced: Option<(u32, Instant)>,
+    // Height whose commit was last sent to a peer behind, and when.
+    helped: Option<(u32, Instant)>,
+}
+
+impl BftNode {
+    pub fn new(
+        shared_state: SharedState,
+        signer: Option<Signer>,
+        timeout: Duration,
+        outbound: mpsc::UnboundedSender<BftMessage>,
+        inbound: mpsc::UnboundedReceiver<BftMessage>,
+    ) -> Self {
+        BftNode {
+            shared_state,
+            signer,
+            timeout,
+            outbound,
+            inbound,
+            state: HeightState::new(0, ValidatorSet::default()),
+            timeouts: Vec::new(),
+            future: Vec::new(),
+            replay: Vec::new(),
+            own: Vec::new(),
+            commits: BTreeMap::new(),
+            synced: None,
+            helped: None,
+        }
+    }
+
+    pub async fn start(&mut self) {
+        self.enter_height(Duration::ZERO).await;
+
+        let mut resend = time::interval(self.timeout);
+        resend.set_missed_tick_behavior(time::MissedTickBehavior::Delay);
+
+        loop {
+            let deadline = self.timeouts.iter().map(|timeout| timeout.deadline).min();
+
+            tokio::select! {
+                message = self.inbound.recv() => match message {
+                    Some(message) => self.handle(message).await,
+                    None => break,
+                },
+                _ = sleep_until(deadline) => self.fire_timeouts().await,
+                _ = resend.tick() => {
+                    for message in &self.own {
+                        self.broadcast(message.clone());
+                    }
+                }
+            }
+
+            self.advance().await;
+        }
+    }
+
+    // Moves on to the child of the current head after `delay`.
+    async fn enter_height(&mut self, delay: Duration) {
+        let (height, validators) = {
+            let chain = &self.shared_state.read().await.blockchain;
+            let head = chain.get_last_block().map_or(0, Block::number);
+
+            (
+                head + 1,
+                chain.validators_at(head).cloned().unwrap_or_default(),
+            )
+        };
+
+        self.state = HeightState::new(height, validators);
+        self.timeouts.clear();
+        self.own.clear();
+        self.schedule(Step::NewHeight, 0, delay);
+        self.replay.append(&mut self.future);
+    }
+
+    // Applies every rule that is ready, handling deferred messages that may
+    // have become current along the way.
+    async fn advance(&mut self) {
+        loop {
+            while self.step().await {}
+
+            if self.replay.is_empty() {
+                break;
+            }
+
+            for message in std::mem::take(&mut self.replay) {
+                self.handle(message).await;
+            }
+        }
+    }
+
+    async fn handle(&mut self, message: BftMessage) {
+        if let Some(height) = message.height() {
+            if height > self.state.height {
+                self.defer(message);
+                return;
+            }
+
+            // Old commits are answers to someone else's `Sync`, and echoing
+            // them would bounce them between nodes forever.
+            if height < self.state.height {
+                if !matches!(message, BftMessage::Commit { .. }) {
+                    self.help(height);
+                }
+
+                return;
+            }
+        }
+
+        match message {
+            BftMessage::Proposal(proposal) => {
+                let expected =
+                    BftEngine::proposer(&self.state.validators, proposal.height, proposal.round);
+
+                // Proposals resent every timeout are dropped before their
+                // signature is checked again.
+                if expected != Some(&proposal.proposer)
+                    || self.state.proposals.contains_key(&proposal.round)
+                    || !proposal.is_signed()
+                {
+                    return;
+                }
+
+                self.state
+                    .senders
+                    .entry(proposal.round)
+                    .or_default()
+                    .insert(proposal.proposer);
+                self.state
+                    .proposals
+                    .insert(proposal.round, (proposal.block, proposal.valid_round));
+            }
+            BftMessage::Vote(vote) => {
+                let counted = self
+                    .state
+                    .votes
+                    .get(&(vote.round, vote.kind))
+                    .is_some_and(|votes| votes.contains_key(&vote.validator));
+
+                if !self.state.validators.contains(&vote.validator) || counted || !vote.is_signed()
+                {
+                    return;
+                }
+
+                self.state
+                    .senders
+                    .entry(vote.round)
+                    .or_default()
+                    .insert(vote.validator.to_owned());
+                self.state
+                    .votes
+                    .entry((vote.round, vote.kind))
+                    .or_default()
+                    .insert(vote.validator.to_owned(), vote);
+            }
+            BftMessage::Commit { block, precommits } => {
+                if self.state.is_commit(&block, &precommits) {
+                    self.commit(block, precommits).await;
+                }
+            }
+            BftMessage::Sync { height } => self.help(height),
+        }
+    }
+
+    // Sends the commit at `height` to a peer still deciding it, at most once
+    // per timeout.
+    fn help(&mut self, height: u32) {
+        let now = Instant::now();
+
+        if let Some((helped, at)) = self.helped {
+            if helped == height && now - at < self.timeout {
+                return;
+            }
+        }
+
+        if let Some((block, precommits)) = self.commits.get(&height) {
+            self.helped = Some((height, now));
+            self.broadcast(BftMessage::Commit {
+                block: block.to_owned(),
+                precommits: precommits.to_owned(),
+            });
+        }
+    }
+
+    // Keeps a message for a later height and asks peers for the commits in
+    // between, at most once per timeout.
+    fn defer(&mut self, message: BftMessage) {
+        if self.future.len() < MAX_FUTURE_MESSAGES {
+            self.future.push(message);
+        }
+
+        let now = Instant::now();
+        let ask = match self.synced {
+            Some((height, at)) => height != self.state.height || now - at >= self.timeout,
+            None => true,
+        };
+
+        if ask {
+            self.synced = Some((self.state.height, now));
+            self.broadcast(BftMessage::Sync {
+                height: self.state.height,
+            });
+        }
+    }
+
+    async fn fire_timeouts(&mut self) {
+        let now = Instant::now();
+        let (due, pending) = std::mem::take(&mut self.timeouts)
+            .into_iter()
+            .partition::<Vec<_>, _>(|timeout| timeout.deadline <= now);
+
+        self.timeouts = pending;
+
+        for timeout in due {
+            if timeout.height != self.state.height || timeout.round != self.state.round {
+                continue;
+            }
+
+            match (timeout.step, self.state.step) {
+                (Step::NewHeight, Step::NewHeight) => self.start_round(0).await,
+                (Step::Propose, Step::Propose) => {
+                    self.vote(VoteKind::Prevote, None);
+                    self.state.step = Step::Prevote;
+                }
+                (Step::Prevote, Step::Prevote) => {
+                    self.vote(VoteKind::Precommit, None);
+                    self.state.step = Step::Precommit;
+                }
+                (Step::Precommit, _) => self.start_round(timeout.round + 1).await,
+                _ => {}
+            }
+        }
+    }
+
+    async fn start_round(&mut self, round: u32) {
+        self.state.round = round;
+        self.state.step = Step::Propose;
+
+        let height = self.state.height;
+        let proposer = self.signer.as_ref().filter(|signer| {
+            BftEngine::proposer(&self.state.validators, height, round) == Some(&signer.address())
+        });
+
+        if let Some(signer) = proposer {
+            let proposal = match self.state.valid.clone() {
+                Some((valid_round, block)) => Some((block, Some(valid_round))),
+                None => self
+                    .shared_state
+                    .read()
+                    .await
+                    .blockchain
+                    .block_template(signer.address(), get_current_timestamp().unwrap())
+                    .and_then(|block| block.with_extra_nonce(round as u64).signed(signer))
+                    .map(|block| (block, None)),
+            };
+
+            if let Some(proposal) = proposal.and_then(|(block, valid_round)| {
+                BftProposal::new(height, round, valid_round, block, signer)
+            }) {
+                let message = BftMessage::Proposal(proposal);
+
+                self.publish(message.clone());
+                self.handle(message).await;
+            }
+        }
+
+        self.schedule(Step::Propose, round, self.round_timeout(round));
+    }
+
+    // Applies the first rule of the algorithm that is ready to fire.
+    // Returns whether anything changed.
+    async fn step(&mut self) -> bool {
+        let round = self.state.round;
+        let step = self.state.step;
+
+        if step == Step::NewHeight {
+            return false;
+        }
+
+        // A block with a precommit quorum in any round is decided.
+        let proposals: Vec<_> = self
+            .state
+            .proposals
+            .iter()
+            .map(|(round, (block, _))| (*round, block.clone()))
+            .collect();
+
+        for (proposal_round, block) in proposals {
+            let block_hash = block.hash();
+
+            if self
+                .state
+                .count(proposal_round, VoteKind::Precommit, block_hash.as_ref())
+                >= self.state.quorum()
+                && self.is_valid(&block).await
+            {
+                let precommits = self
+                    .state
+                    .precommits(proposal_round, block_hash.as_ref().unwrap());
+
+                self.commit(block, precommits).await;
+                return true;
+            }
+        }
+
+        // Enough validators have moved on to a later round for one of them
+        // to be honest.
+        let later = self
+            .state
+            .senders
+            .iter()
+            .filter(|(later, senders)| {
+                **later > round && senders.len() >= self.state.honest_minimum()
+            })
+            .map(|(later, _)| *later)
+            .max();
+
+        if let Some(later) = later {
+            self.start_round(later).await;
+            return true;
+        }
+
+        match step {
+            Step::Propose => {
+                if let Some((block, valid_round)) = self.state.proposals.get(&round).cloned() {
+                    let block_hash = block.hash();
+                    let locked = self.state.locked.as_ref();
+
+                    let acceptable = match valid_round {
+                        None => locked.is_none_or(|(_, locked)| *locked == block),
+                        Some(valid_round)
+                            if valid_round < round
+                                && self.state.count(
+                                    valid_round,
+                                    VoteKind::Prevote,
+                                    block_hash.as_ref(),
+                                ) >= self.state.quorum() =>
+                        {
+                            locked.is_none_or(|(locked_round, locked)| {
+                                *locked_round <= valid_round || *locked == block
+                            })
+                        }
+                        Some(_) => return false,
+                    };
+
+                    let prevote = if acceptable && self.is_valid(&block).await {
+                        block_hash
+                    } else {
+                        None
+                    };
+
+                    self.vote(VoteKind::Prevote, prevote);
+                    self.state.step = Step::Prevote;
+                    return true;
+                }
+            }
+            Step::Prevote => {
+                if let Some(block) = self.polka(round).await {
+                    let block_hash = block.hash();
+
+                    self.state.locked = Some((round, block.clone()));
+                    self.state.valid = Some((round, block));
+                    self.vote(VoteKind::Precommit, block_hash);
+                    self.state.step = Step::Precommit;
+                    return true;
+                }
+
+                if self.state.count(round, VoteKind::Prevote, None) >= self.state.quorum() {
+                    self.vote(VoteKind::Precommit, None);
+                    self.state.step = Step::Precommit;
+                    return true;
+                }
+
+                if self.state.count_any(round, VoteKind::Prevote) >= self.state.quorum()
+                    && self.state.fired.insert((round, Rule::PrevoteTimeout))
+                {
+                    self.schedule(Step::Prevote, round, self.round_timeout(round));
+                    return true;
+                }
+            }
+            Step::Precommit => {
+                if !self.state.fired.contains(&(round, Rule::UpdateValid)) {
+                    if let Some(block) = self.polka(round).await {
+                        self.state.fired.insert((round, Rule::UpdateValid));
+                        self.state.valid = Some((round, block));
+                        return true;
+                    }
+                }
+            }
+            Step::NewHeight => {}
+        }
+
+        if self.state.count_any(round, VoteKind::Precommit) >= self.state.quorum()
+            && self.state.fired.insert((round, Rule::PrecommitTimeout))
+        {
+            self.schedule(Step::Precommit, round, self.round_timeout(round));
+            return true;
+        }
+
+        false
+    }
+
+    // The valid proposal of `round`, if it has a prevote quorum.
+    async fn polka(&mut self, round: u32) -> Option<Block> {
+        let (block, _) = self.state.proposals.get(&round).cloned()?;
+
+        if self
+            .state
+            .count(round, VoteKind::Prevote, block.hash().as_ref())
+            < self.state.quorum()
+        {
+            return None;
+        }
+
+        if self.is_valid(&block).await {
+            Some(block)
+        } else {
+            None
+        }
+    }
+
+    async fn is_valid(&mut self, block: &Block) -> bool {
+        let block_hash = match block.hash() {
+            Some(block_hash) => block_hash,
+            None => return false,
+        };
+
+        if let Some(valid) = self.state.checked.get(&block_hash) {
+            return *valid;
+        }
+
+        let valid = block.number() == self.state.height
+            && self
+                .shared_state
+                .read()
+                .await
+                .blockchain
+                .check_block(block)
+                .is_ok();
+
+        self.state.checked.insert(block_hash, valid);
+        valid
+    }
+
+    async fn commit(&mut self, block: Block, precommits: Vec<BftVote>) {
+        let height = self.state.height;
+
+        match self
+            .shared_state
+            .write()
+            .await
+            .add_committed_block(block.clone())
+        {
+            Ok(_) => println!(
+                "Committed block {} in round {}",
+                height,
+                block.extra_nonce()
+            ),
+            Err(err) => {
+                eprintln!("Error: Committed block {} rejected: {}", height, err);
+                return;
+            }
+        }
+
+        self.broadcast(BftMessage::Commit {
+            block: block.clone(),
+            precommits: precommits.clone(),
+        });
+
+        self.commits.insert(height, (block, precommits));
+
+        while self.commits.len() > KEPT_COMMITS {
+            self.commits.pop_first();
+        }
+
+        self.enter_height(self.timeout).await;
+    }
+
+    // Only validators vote; everyone else just follows the commits.
+    fn vote(&mut self, kind: VoteKind, block_hash: Option<BigUint>) {
+        let vote = match &self.signer {
+            Some(signer) if self.state.validators.contains(&signer.address()) => BftVote::new(
+                kind,
+                self.state.height,
+                self.state.round,
+                block_hash,
+                signer,
+            ),
+            _ => None,
+        };
+        let vote = match vote {
+            Some(vote) => vote,
+            None => return,
+        };
+
+        self.state
+            .votes
+            .entry((vote.round, kind))
+            .or_default()
+            .insert(vote.validator.to_owned(), vote.clone());
+
+        self.publish(BftMessage::Vote(vote));
+    }
+
+    fn publish(&mut self, message: BftMessage) {
+        self.own.push(message.clone());
+        self.broadcast(message);
+    }
+
+    fn broadcast(&self, message: BftMessage) {
+        if self.outbound.send(message).is_err() {
+            eprintln!("Error: BFT transport closed");
+        }
+    }
+
+    // Later rounds wait longer, so slow validators eventually catch up.
+    fn round_timeout(&self, round: u32) -> Duration {
+        self.timeout.saturating_mul(round.saturating_add(1))
+    }
+
+    fn schedule(&mut self, step: Step, round: u32, delay: Duration) {
+        self.timeouts.push(Timeout {
+            deadline: Instant::now() + delay,
+            height: self.state.height,
+            round,
+            step,
+        });
+    }
+}
+
+async fn sleep_until(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => time::sleep_until(deadline).await,
+        None => std::future::pending().await,
+    }
+}
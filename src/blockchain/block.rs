@@ -1,182 +1,779 @@
 extern crate serde_derive;
-use std::str::FromStr;
 
 use serde_derive::{Deserialize, Serialize};
 extern crate num_bigint;
 
-#[path = "../helpers.rs"]
-mod helpers;
-use helpers::{get_current_timestamp, keccak256, sort_characters};
+use crate::helpers::{get_current_timestamp, keccak256_bytes};
 
 use num_bigint::BigUint;
-use num_traits::One;
-
-pub const MAX_U256_NUMBER_TEXT: &str =
-    "115792089237316195423570985008687907853269984665640564039457584007913129639934";
-
-#[derive(Serialize, Deserialize, Debug, Clone)]
+use num_traits::{One, Zero};
+
+use super::chain_spec::ChainSpec;
+use super::consensus::ValidatorSet;
+use super::crypto::{self, Signer};
+use super::encoding::{
+    encode_bytes, encode_count, encode_signature, encode_u256, Decoder, EncodingError,
+};
+use super::error::{
+    BodyError, HeaderError, LinkageError, SealError, SizeError, StakingError, TimestampError,
+    TransactionError, UtxoError, ValidateBlockError,
+};
+use super::ledger::{Ledger, LedgerSpec};
+use super::merkle::{self, ProofStep};
+use super::pow::PowAlgorithm;
+use super::serde_hex;
+use super::staking::{DoubleSign, StakingTransaction};
+use super::target::Target;
+use super::transaction::Transaction;
+use super::utxo::UtxoTransaction;
+
+pub const MAX_BLOCK_SIZE: usize = 1024 * 1024;
+
+// Nonces tried for a single header before a miner has to change the extra
+// nonce or the timestamp.
+pub const NONCE_SEARCH_SPACE: u64 = 1 << 32;
+
+// First byte of each body entry under `transactions_root`, naming the list
+// the entry comes from.
+const TRANSACTION_ENTRY: u8 = 0;
+
+const STAKING_ENTRY: u8 = 1;
+
+const EVIDENCE_ENTRY: u8 = 2;
+
+const UTXO_ENTRY: u8 = 3;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 pub struct BlockHeaders {
     number: u32,
-    difficulty: u32,
+    bits: u32,
     timestamp: u64,
+    extra_nonce: u64,
     parent_hash: BigUint,
     beneficiary: BigUint,
+    // Merkle root of the body's entries.
+    transactions_root: BigUint,
+    // Sparse Merkle root of the ledger once the block is applied.
+    state_root: BigUint,
+    extra_data: Vec<u8>,
 }
 
-pub enum ValidateBlockError {
-    InvalidTargetHash,
-    InvalidDifficulty,
-    InvalidBlockNumber,
-}
-
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 pub struct Block {
     block_headers: BlockHeaders,
     nonce: BigUint,
+    // Producer's signature over the block hash, empty when unsigned. When
+    // present, it must be made with the key of the beneficiary's address.
+    #[serde(with = "serde_hex::bytes", default)]
+    signature: Vec<u8>,
+    #[serde(default)]
+    body: BlockBody,
+}
+
+// Transactions go in any block. Staking transactions and evidence are only
+// accepted by engines that look at them. Every entry is committed to by
+// `transactions_root` in the headers.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct BlockBody {
+    #[serde(default)]
+    pub transactions: Vec<Transaction>,
+    #[serde(default)]
+    pub staking: Vec<StakingTransaction>,
+    #[serde(default)]
+    pub evidence: Vec<DoubleSign>,
+    #[serde(default)]
+    pub utxo: Vec<UtxoTransaction>,
+}
+
+// One entry of a block body, whichever list it is in.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum BodyEntry {
+    Transaction(Transaction),
+    Staking(StakingTransaction),
+    Evidence(Box<DoubleSign>),
+    Utxo(UtxoTransaction),
+}
+
+// A body entry and its path to the `transactions_root` of a block, which is
+// all it takes to show the block includes it given only the headers.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct InclusionProof {
+    pub entry: BodyEntry,
+    pub path: Vec<ProofStep>,
+}
+
+impl BlockHeaders {
+    pub fn encode(&self) -> Result<Vec<u8>, EncodingError> {
+        let mut bytes = Vec::new();
+
+        bytes.extend_from_slice(&self.number.to_be_bytes());
+        bytes.extend_from_slice(&self.bits.to_be_bytes());
+        bytes.extend_from_slice(&self.timestamp.to_be_bytes());
+        bytes.extend_from_slice(&self.extra_nonce.to_be_bytes());
+        bytes.extend_from_slice(&encode_u256(&self.parent_hash)?);
+        bytes.extend_from_slice(&encode_u256(&self.beneficiary)?);
+        bytes.extend_from_slice(&encode_u256(&self.transactions_root)?);
+        bytes.extend_from_slice(&encode_u256(&self.state_root)?);
+        bytes.extend_from_slice(&encode_bytes(&self.extra_data)?);
+
+        Ok(bytes)
+    }
+
+    pub fn number(&self) -> u32 {
+        self.number
+    }
+
+    pub fn extra_nonce(&self) -> u64 {
+        self.extra_nonce
+    }
+
+    pub fn beneficiary(&self) -> &BigUint {
+        &self.beneficiary
+    }
+
+    pub fn transactions_root(&self) -> &BigUint {
+        &self.transactions_root
+    }
+
+    pub fn state_root(&self) -> &BigUint {
+        &self.state_root
+    }
+
+    pub fn extra_data(&self) -> &[u8] {
+        &self.extra_data
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<BlockHeaders, EncodingError> {
+        let mut decoder = Decoder::new(bytes);
+        let headers = BlockHeaders::decode_from(&mut decoder)?;
+        decoder.finish()?;
+
+        Ok(headers)
+    }
+
+    pub(super) fn decode_from(decoder: &mut Decoder) -> Result<BlockHeaders, EncodingError> {
+        Ok(BlockHeaders {
+            number: decoder.read_u32()?,
+            bits: decoder.read_u32()?,
+            timestamp: decoder.read_u64()?,
+            extra_nonce: decoder.read_u64()?,
+            parent_hash: decoder.read_u256()?,
+            beneficiary: decoder.read_u256()?,
+            transactions_root: decoder.read_u256()?,
+            state_root: decoder.read_u256()?,
+            extra_data: decoder.read_bytes()?,
+        })
+    }
+}
+
+impl BlockBody {
+    pub fn is_empty(&self) -> bool {
+        self.transactions.is_empty()
+            && self.staking.is_empty()
+            && self.evidence.is_empty()
+            && self.utxo.is_empty()
+    }
+
+    pub fn encode(&self) -> Result<Vec<u8>, EncodingError> {
+        let mut bytes = encode_count(self.transactions.len())?.to_vec();
+
+        for transaction in &self.transactions {
+            bytes.extend_from_slice(&transaction.encode()?);
+        }
+
+        bytes.extend_from_slice(&encode_count(self.staking.len())?);
+
+        for transaction in &self.staking {
+            bytes.extend_from_slice(&transaction.encode()?);
+        }
+
+        bytes.extend_from_slice(&encode_count(self.evidence.len())?);
+
+        for evidence in &self.evidence {
+            bytes.extend_from_slice(&evidence.encode()?);
+        }
+
+        bytes.extend_from_slice(&encode_count(self.utxo.len())?);
+
+        for transaction in &self.utxo {
+            bytes.extend_from_slice(&transaction.encode()?);
+        }
+
+        Ok(bytes)
+    }
+
+    fn decode_from(decoder: &mut Decoder) -> Result<BlockBody, EncodingError> {
+        let transactions = (0..decoder.read_u16()?)
+            .map(|_| Transaction::decode_from(decoder))
+            .collect::<Result<_, _>>()?;
+        let staking = (0..decoder.read_u16()?)
+            .map(|_| StakingTransaction::decode_from(decoder))
+            .collect::<Result<_, _>>()?;
+        let evidence = (0..decoder.read_u16()?)
+            .map(|_| DoubleSign::decode_from(decoder))
+            .collect::<Result<_, _>>()?;
+        let utxo = (0..decoder.read_u16()?)
+            .map(|_| UtxoTransaction::decode_from(decoder))
+            .collect::<Result<_, _>>()?;
+
+        Ok(BlockBody {
+            transactions,
+            staking,
+            evidence,
+            utxo,
+        })
+    }
+
+    pub fn hash(&self) -> Result<BigUint, EncodingError> {
+        Ok(keccak256_bytes(&self.encode()?))
+    }
+
+    // Merkle leaves of every entry, in the order they are encoded.
+    pub fn leaves(&self) -> Result<Vec<BigUint>, EncodingError> {
+        let mut leaves = Vec::new();
+
+        for transaction in &self.transactions {
+            leaves.push(BodyEntry::leaf(TRANSACTION_ENTRY, &transaction.encode()?));
+        }
+
+        for transaction in &self.staking {
+            leaves.push(BodyEntry::leaf(STAKING_ENTRY, &transaction.encode()?));
+        }
+
+        for evidence in &self.evidence {
+            leaves.push(BodyEntry::leaf(EVIDENCE_ENTRY, &evidence.encode()?));
+        }
+
+        for transaction in &self.utxo {
+            leaves.push(BodyEntry::leaf(UTXO_ENTRY, &transaction.encode()?));
+        }
+
+        Ok(leaves)
+    }
+
+    pub fn transactions_root(&self) -> Result<BigUint, EncodingError> {
+        Ok(merkle::root(&self.leaves()?))
+    }
+
+    // Entry `index`, counting through the lists in the order they are encoded.
+    pub fn entry(&self, index: usize) -> Option<BodyEntry> {
+        let mut index = index;
+
+        if let Some(transaction) = self.transactions.get(index) {
+            return Some(BodyEntry::Transaction(transaction.to_owned()));
+        }
+        index -= self.transactions.len();
+
+        if let Some(transaction) = self.staking.get(index) {
+            return Some(BodyEntry::Staking(transaction.to_owned()));
+        }
+        index -= self.staking.len();
+
+        if let Some(evidence) = self.evidence.get(index) {
+            return Some(BodyEntry::Evidence(Box::new(evidence.to_owned())));
+        }
+        index -= self.evidence.len();
+
+        self.utxo
+            .get(index)
+            .map(|transaction| BodyEntry::Utxo(transaction.to_owned()))
+    }
+}
+
+impl BodyEntry {
+    fn leaf(kind: u8, encoded: &[u8]) -> BigUint {
+        let mut bytes = vec![kind];
+        bytes.extend_from_slice(encoded);
+
+        merkle::leaf_hash(&bytes)
+    }
+
+    pub fn leaf_hash(&self) -> Result<BigUint, EncodingError> {
+        Ok(match self {
+            BodyEntry::Transaction(transaction) => {
+                BodyEntry::leaf(TRANSACTION_ENTRY, &transaction.encode()?)
+            }
+            BodyEntry::Staking(transaction) => {
+                BodyEntry::leaf(STAKING_ENTRY, &transaction.encode()?)
+            }
+            BodyEntry::Evidence(evidence) => BodyEntry::leaf(EVIDENCE_ENTRY, &evidence.encode()?),
+            BodyEntry::Utxo(transaction) => BodyEntry::leaf(UTXO_ENTRY, &transaction.encode()?),
+        })
+    }
 }
 
+impl InclusionProof {
+    // Whether the block with `headers` includes the entry.
+    pub fn verify(&self, headers: &BlockHeaders) -> bool {
+        match self.entry.leaf_hash() {
+            Ok(leaf) => merkle::root_from(&leaf, &self.path) == headers.transactions_root,
+            Err(_) => false,
+        }
+    }
+}
 
 impl Block {
     pub fn new(
         number: u32,
         parent_hash: BigUint,
         beneficiary: BigUint,
-        difficulty: u32,
+        bits: u32,
         timestamp: u64,
+        extra_data: Vec<u8>,
         nonce: BigUint,
     ) -> Self {
         Block {
             block_headers: BlockHeaders {
                 number,
-                difficulty,
+                bits,
                 timestamp,
+                extra_nonce: 0,
                 parent_hash,
                 beneficiary,
+                transactions_root: BigUint::zero(),
+                state_root: BigUint::zero(),
+                extra_data,
             },
             nonce,
+            signature: Vec::new(),
+            body: BlockBody::default(),
         }
     }
 
-    pub fn calculate_block_target_hash(last_block: &Block) -> BigUint {
-        return BigUint::from_str(MAX_U256_NUMBER_TEXT).unwrap()
-            / BigUint::from(last_block.block_headers.difficulty);
+    pub fn encode(&self) -> Result<Vec<u8>, EncodingError> {
+        let mut bytes = self.block_headers.encode()?;
+        bytes.extend_from_slice(&encode_u256(&self.nonce)?);
+        bytes.extend_from_slice(&encode_signature(&self.signature)?);
+        bytes.extend_from_slice(&self.body.encode()?);
+
+        Ok(bytes)
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Block, EncodingError> {
+        let mut decoder = Decoder::new(bytes);
+
+        let block = Block {
+            block_headers: BlockHeaders::decode_from(&mut decoder)?,
+            nonce: decoder.read_u256()?,
+            signature: decoder.read_signature()?,
+            body: BlockBody::decode_from(&mut decoder)?,
+        };
+        decoder.finish()?;
+
+        Ok(block)
+    }
+
+    pub fn hash(&self) -> Option<BigUint> {
+        self.try_hash().ok()
+    }
+
+    pub fn try_hash(&self) -> Result<BigUint, EncodingError> {
+        Ok(keccak256_bytes(&self.block_headers.encode()?))
+    }
+
+    pub fn number(&self) -> u32 {
+        self.block_headers.number
+    }
+
+    pub fn parent_hash(&self) -> &BigUint {
+        &self.block_headers.parent_hash
+    }
+
+    pub fn bits(&self) -> u32 {
+        self.block_headers.bits
+    }
+
+    pub fn target(&self) -> Option<Target> {
+        Target::from_compact(self.block_headers.bits)
+    }
+
+    pub fn difficulty(&self) -> BigUint {
+        self.target()
+            .map(|target| target.difficulty())
+            .unwrap_or_default()
+    }
+
+    pub fn work(&self) -> BigUint {
+        self.target()
+            .map(|target| target.work())
+            .unwrap_or_default()
+    }
+
+    pub fn timestamp(&self) -> u64 {
+        self.block_headers.timestamp
+    }
+
+    pub fn nonce(&self) -> &BigUint {
+        &self.nonce
+    }
+
+    pub fn extra_nonce(&self) -> u64 {
+        self.block_headers.extra_nonce
     }
 
     pub fn get_block_hash(block_headers: &BlockHeaders) -> Option<BigUint> {
-        match serde_json::to_string(block_headers) {
-            Ok(text) => match sort_characters(&text) {
-                Ok(val) => Some(keccak256(&val)),
-                _ => None,
-            },
+        match block_headers.encode() {
+            Ok(bytes) => Some(keccak256_bytes(&bytes)),
             _ => None,
         }
     }
 
-    pub fn mine_block(last_block: &Block, beneficiary: BigUint) -> Option<Block> {
-        let target_hash: BigUint = Block::calculate_block_target_hash(&last_block);
-        let mut under_target_hash;
+    pub fn get_pow_hash(
+        pow: &dyn PowAlgorithm,
+        block_headers: &BlockHeaders,
+        nonce: &BigUint,
+    ) -> Option<BigUint> {
+        pow.hash(&block_headers.encode().ok()?, nonce)
+    }
 
-        let headers_string = serde_json::to_string(&last_block.block_headers.parent_hash).unwrap();
-        let sorted = sort_characters(&headers_string).unwrap();
+    pub fn headers(&self) -> &BlockHeaders {
+        &self.block_headers
+    }
 
-        let mut nonce = BigUint::from(0u64);
+    pub fn beneficiary(&self) -> &BigUint {
+        &self.block_headers.beneficiary
+    }
 
-        loop {
-            let timestamp = get_current_timestamp().unwrap();
+    pub fn signature(&self) -> &[u8] {
+        &self.signature
+    }
 
-            let new_block_headers = BlockHeaders {
-                number: last_block.block_headers.number + 1,
-                difficulty: Block::adjust_difficulty(last_block, timestamp),
-                beneficiary: beneficiary.to_owned(),
-                parent_hash: keccak256(&sorted),
-                timestamp: timestamp,
-            };
+    // Address that signed the block hash, if the block is signed.
+    pub fn signer(&self) -> Option<BigUint> {
+        crypto::recover(&self.hash()?, &self.signature)
+    }
 
-            let hashed = Block::get_block_hash(&new_block_headers).unwrap();
+    pub fn body(&self) -> &BlockBody {
+        &self.body
+    }
 
-            let result = (hashed + &nonce).to_string();
+    pub fn median_time_past(ancestors: &[Block], span: usize) -> u64 {
+        let start = ancestors.len().saturating_sub(span.max(1));
+        let mut timestamps: Vec<u64> = ancestors[start..]
+            .iter()
+            .map(|block| block.timestamp())
+            .collect();
 
-            under_target_hash = keccak256(&result);
+        timestamps.sort_unstable();
+        timestamps.get(timestamps.len() / 2).copied().unwrap_or(0)
+    }
 
-            if under_target_hash <= target_hash {
-                let new_block = Block {
-                    block_headers: new_block_headers,
-                    nonce,
-                };
+    // Unsealed child of the last ancestor, with bits set by the consensus
+    // engine. The timestamp is raised to just after median time past.
+    pub fn new_child(
+        ancestors: &[Block],
+        validators: &ValidatorSet,
+        beneficiary: BigUint,
+        spec: &ChainSpec,
+        timestamp: u64,
+    ) -> Option<Block> {
+        let last_block = ancestors.last()?;
+        let median_time_past = Block::median_time_past(ancestors, spec.timestamp.median_time_span);
+        let timestamp = timestamp.max(median_time_past + 1);
 
-                return Some(new_block);
-            }
+        let difficulty = spec
+            .engine()
+            .difficulty(ancestors, validators, &beneficiary, timestamp);
 
-            nonce += BigUint::from(1u64);
+        Some(Block {
+            block_headers: BlockHeaders {
+                number: last_block.block_headers.number + 1,
+                bits: Target::from_difficulty(&difficulty).to_compact(),
+                beneficiary,
+                parent_hash: last_block.hash()?,
+                timestamp,
+                extra_nonce: 0,
+                transactions_root: BigUint::zero(),
+                state_root: BigUint::zero(),
+                extra_data: Vec::new(),
+            },
+            nonce: BigUint::from(0u64),
+            signature: Vec::new(),
+            body: BlockBody::default(),
+        })
+    }
 
-            if nonce > BigUint::from(500000u32) {
-                break None;
-            }
+    pub fn from_parts(block_headers: BlockHeaders, nonce: BigUint) -> Block {
+        Block {
+            block_headers,
+            nonce,
+            signature: Vec::new(),
+            body: BlockBody::default(),
         }
     }
 
-    pub fn adjust_difficulty(last_block: &Block, timestamp: u64) -> u32 {
-        if timestamp - last_block.block_headers.timestamp > 2 {
-            if last_block.block_headers.difficulty - 1 == 0 {
-                return 1;
-            } else {
-                return last_block.block_headers.difficulty - 1;
-            }
-        } else {
-            return last_block.block_headers.difficulty + 1;
-        }
+    pub fn with_nonce(self, nonce: BigUint) -> Block {
+        Block { nonce, ..self }
+    }
+
+    pub fn with_timestamp(mut self, timestamp: u64) -> Block {
+        self.block_headers.timestamp = timestamp;
+        self
+    }
+
+    pub fn with_extra_data(mut self, extra_data: Vec<u8>) -> Block {
+        self.block_headers.extra_data = extra_data;
+        self
+    }
+
+    pub fn with_extra_nonce(mut self, extra_nonce: u64) -> Block {
+        self.block_headers.extra_nonce = extra_nonce;
+        self
+    }
+
+    // Also sets the transactions root the body needs, which changes the hash.
+    pub fn with_body(mut self, body: BlockBody) -> Block {
+        self.block_headers.transactions_root = body.transactions_root().unwrap_or_default();
+        Block { body, ..self }
+    }
+
+    // Sets the root of the ledger the block leaves behind, which depends on
+    // the body and beneficiary but not on the seal.
+    pub fn with_state_root(mut self, state_root: BigUint) -> Block {
+        self.block_headers.state_root = state_root;
+        self
+    }
+
+    // Proof that the body entry `index`, as counted by `BlockBody::entry`, is
+    // in this block.
+    pub fn proof(&self, index: usize) -> Option<InclusionProof> {
+        Some(InclusionProof {
+            entry: self.body.entry(index)?,
+            path: merkle::proof(&self.body.leaves().ok()?, index)?,
+        })
+    }
+
+    pub fn with_signature(self, signature: Vec<u8>) -> Block {
+        Block { signature, ..self }
+    }
+
+    // Signs the block hash, so the headers must be final.
+    pub fn signed(self, signer: &Signer) -> Option<Block> {
+        let signature = signer.sign(&self.hash()?)?;
+
+        Some(self.with_signature(signature))
     }
 
     pub fn validate_block(
-        last_block: &Block,
+        ancestors: &[Block],
+        validators: &ValidatorSet,
         new_block: &Block,
+        spec: &ChainSpec,
     ) -> Result<bool, ValidateBlockError> {
-        //handle genesis
-        if Block::get_block_hash(&new_block.block_headers).unwrap()
-            == Block::get_block_hash(&Block::genesis().block_headers).unwrap()
-        {
-            return Ok(true);
+        let last_block = ancestors
+            .last()
+            .ok_or_else(|| LinkageError::UnknownParent {
+                parent_hash: new_block.block_headers.parent_hash.to_owned(),
+            })?;
+
+        //handle invalid encoding and size
+        let header_bytes = new_block.block_headers.encode()?;
+        let block_size = new_block
+            .encode()
+            .map_err(|error| BodyError::Encoding { error })?
+            .len();
+
+        if block_size > MAX_BLOCK_SIZE {
+            return Err(SizeError::BlockTooLarge {
+                max: MAX_BLOCK_SIZE,
+                actual: block_size,
+            }
+            .into());
         }
 
         //handle invalid block number
         if new_block.block_headers.number != last_block.block_headers.number + 1 {
-            return Err(ValidateBlockError::InvalidBlockNumber);
+            return Err(HeaderError::InvalidNumber {
+                expected: last_block.block_headers.number + 1,
+                actual: new_block.block_headers.number,
+            }
+            .into());
+        }
+
+        //handle invalid parent hash
+        let last_block_hash = last_block.try_hash()?;
+
+        if new_block.block_headers.parent_hash != last_block_hash {
+            return Err(LinkageError::InvalidParentHash {
+                expected: last_block_hash,
+                actual: new_block.block_headers.parent_hash.to_owned(),
+            }
+            .into());
+        }
+
+        //handle timestamp not after median time past
+        let median_time_past = Block::median_time_past(ancestors, spec.timestamp.median_time_span);
+
+        if new_block.block_headers.timestamp <= median_time_past {
+            return Err(TimestampError::NotAfterMedian {
+                median: median_time_past,
+                actual: new_block.block_headers.timestamp,
+            }
+            .into());
+        }
+
+        //handle timestamp too far in the future
+        let max_timestamp = get_current_timestamp().unwrap_or(0) + spec.timestamp.max_future_drift;
+
+        if new_block.block_headers.timestamp > max_timestamp {
+            return Err(TimestampError::TooFarInFuture {
+                max: max_timestamp,
+                actual: new_block.block_headers.timestamp,
+            }
+            .into());
         }
 
         //handle invalid difficulty
-        if new_block.block_headers.difficulty
-            != Block::adjust_difficulty(last_block, new_block.block_headers.timestamp)
-        {
-            return Err(ValidateBlockError::InvalidDifficulty);
+        let engine = spec.engine();
+        let expected_difficulty = engine.difficulty(
+            ancestors,
+            validators,
+            &new_block.block_headers.beneficiary,
+            new_block.block_headers.timestamp,
+        );
+        let expected_bits = Target::from_difficulty(&expected_difficulty).to_compact();
+
+        if new_block.block_headers.bits != expected_bits {
+            return Err(HeaderError::InvalidBits {
+                expected: expected_bits,
+                actual: new_block.block_headers.bits,
+            }
+            .into());
         }
 
-        //handle invalid target hash
-        let last_block_target_hash = Block::calculate_block_target_hash(&last_block);
+        //handle missing or forged producer signature
+        if new_block.signature.is_empty() {
+            if engine.requires_signature() {
+                return Err(SealError::MissingSignature.into());
+            }
+        } else {
+            let signer = new_block.signer();
+
+            if signer.as_ref() != Some(&new_block.block_headers.beneficiary) {
+                return Err(SealError::InvalidSignature {
+                    beneficiary: new_block.block_headers.beneficiary.to_owned(),
+                    signer,
+                }
+                .into());
+            }
+        }
 
-        let new_block_header_hash = Block::get_block_hash(&new_block.block_headers).unwrap();
-        let nonce = &new_block.nonce;
+        //handle invalid seal
+        let target = new_block.target().ok_or(HeaderError::InvalidBits {
+            expected: expected_bits,
+            actual: new_block.block_headers.bits,
+        })?;
 
-        let stringified = (new_block_header_hash + nonce).to_string();
+        engine.verify_seal(ancestors, validators, new_block, &header_bytes, &target)?;
 
-        let under_target_hash = keccak256(&stringified);
+        //handle body not matching the transactions root
+        let transactions_root = new_block
+            .body
+            .transactions_root()
+            .map_err(|error| BodyError::Encoding { error })?;
 
-        if under_target_hash > last_block_target_hash {
-            return Err(ValidateBlockError::InvalidTargetHash);
+        if new_block.block_headers.transactions_root != transactions_root {
+            return Err(BodyError::InvalidTransactionsRoot {
+                expected: transactions_root,
+                actual: new_block.block_headers.transactions_root.to_owned(),
+            }
+            .into());
         }
 
-        return Ok(true);
+        //handle unsigned transactions and transactions for other chains or ledgers
+        Block::verify_transactions(new_block.body(), spec)?;
+
+        //handle invalid body
+        engine.verify_body(validators, new_block)?;
+
+        Ok(true)
     }
 
-    pub fn genesis() -> Block {
-        Block {
+    pub fn verify_transactions(body: &BlockBody, spec: &ChainSpec) -> Result<(), BodyError> {
+        let chain_id = spec.chain_id;
+        let wrong_ledger = match spec.ledger {
+            LedgerSpec::Account => !body.utxo.is_empty(),
+            LedgerSpec::Utxo => !body.transactions.is_empty(),
+        };
+
+        if wrong_ledger {
+            return Err(BodyError::WrongLedger);
+        }
+
+        for (index, transaction) in body.transactions.iter().enumerate() {
+            let error = if transaction.chain_id != chain_id {
+                TransactionError::WrongChain {
+                    expected: chain_id,
+                    actual: transaction.chain_id,
+                }
+            } else if transaction.sender().is_none() {
+                TransactionError::InvalidSignature
+            } else {
+                continue;
+            };
+
+            return Err(BodyError::Transaction { index, error });
+        }
+
+        for (index, transaction) in body.utxo.iter().enumerate() {
+            let error = if transaction.chain_id != chain_id {
+                UtxoError::WrongChain {
+                    expected: chain_id,
+                    actual: transaction.chain_id,
+                }
+            } else if let Some(input) = transaction.id().ok().and_then(|txid| {
+                transaction
+                    .inputs
+                    .iter()
+                    .position(|input| input.signature.signer(&txid).is_none())
+            }) {
+                UtxoError::InvalidSignature { input }
+            } else {
+                continue;
+            };
+
+            return Err(BodyError::Utxo { index, error });
+        }
+
+        for (index, transaction) in body.staking.iter().enumerate() {
+            let error = if transaction.chain_id != chain_id {
+                StakingError::WrongChain {
+                    expected: chain_id,
+                    actual: transaction.chain_id,
+                }
+            } else if transaction.sender().is_none() {
+                StakingError::InvalidSignature
+            } else {
+                continue;
+            };
+
+            return Err(BodyError::Staking { index, error });
+        }
+
+        Ok(())
+    }
+
+    // Its state root commits to the genesis allocations.
+    pub fn genesis(spec: &ChainSpec) -> Block {
+        let genesis = Block {
             block_headers: BlockHeaders {
                 number: 0,
-                difficulty: 100000,
-                timestamp: get_current_timestamp().unwrap(),
+                bits: Target::from_difficulty(&BigUint::from(spec.genesis.difficulty)).to_compact(),
+                timestamp: spec.genesis.timestamp,
+                extra_nonce: 0,
                 parent_hash: BigUint::one(),
-                beneficiary: BigUint::one(),
+                beneficiary: spec.genesis.beneficiary.to_owned(),
+                transactions_root: BigUint::zero(),
+                state_root: BigUint::zero(),
+                extra_data: spec.genesis.extra_data.to_owned(),
             },
             nonce: BigUint::one(),
-        }
+            signature: Vec::new(),
+            body: BlockBody::default(),
+        };
+        let state_root = Ledger::genesis(spec, &genesis).root();
+
+        genesis.with_state_root(state_root)
     }
 }
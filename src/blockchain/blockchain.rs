@@ -1,37 +1,603 @@
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, HashMap},
+    error::Error,
+    fmt,
+};
+
 use serde_derive::{Deserialize, Serialize};
 
-use super::block::Block;
+use num_bigint::BigUint;
+
+use super::block::{Block, BlockBody};
+use super::chain_spec::{ChainSpec, ChainSpecError};
+use super::consensus::{BalanceChange, Seal, ValidatorSet};
+use super::error::{BodyError, HeaderError, LinkageError, ValidateBlockError};
+use super::ledger::Ledger;
+use super::staking::{DoubleSign, SignedHeader};
+use super::state::WorldState;
+use super::utxo::{BlockUndo, UtxoSet};
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Blockchain {
+    #[serde(skip)]
+    spec: ChainSpec,
+    #[serde(skip)]
+    tree: HashMap<BigUint, TreeEntry>,
+    // Coins as of the canonical head.
+    #[serde(skip)]
+    ledger: Ledger,
     blocks: Vec<Block>,
 }
 
+#[derive(Debug, Clone)]
+struct TreeEntry {
+    block: Block,
+    total_work: BigUint,
+    // Validators in force after this block.
+    validators: ValidatorSet,
+    // What reverting the block needs, as of when it was last connected.
+    undo: BlockUndo,
+}
+
+#[derive(Debug, Default)]
+pub struct ChainUpdate {
+    pub disconnected: Vec<Block>,
+    pub connected: Vec<Block>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct ValidateChainError {
+    pub height: usize,
+    pub reason: ValidateBlockError,
+}
+
+impl fmt::Display for ValidateChainError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "block at height {}: {}", self.height, self.reason)
+    }
+}
+
+impl Error for ValidateChainError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.reason)
+    }
+}
+
 impl Default for Blockchain {
     fn default() -> Self {
-        Blockchain::new()
+        Blockchain::new(ChainSpec::default()).expect("Default chain spec is invalid")
+    }
+}
+
+impl ChainUpdate {
+    pub fn is_reorg(&self) -> bool {
+        !self.disconnected.is_empty()
     }
 }
 
 impl Blockchain {
-    pub fn new() -> Self {
-        Blockchain {
-            blocks: vec![Block::genesis()],
+    // Fails if `spec` does not make a valid genesis block.
+    pub fn new(spec: ChainSpec) -> Result<Self, ChainSpecError> {
+        spec.validate()?;
+
+        let genesis = Block::genesis(&spec);
+        let genesis_hash = genesis.hash().ok_or_else(|| {
+            ChainSpecError::InvalidGenesis("headers do not encode".to_string())
+        })?;
+
+        let engine = spec.engine();
+        let ledger = Ledger::genesis(&spec, &genesis);
+
+        let mut tree = HashMap::new();
+        tree.insert(
+            genesis_hash,
+            TreeEntry {
+                total_work: engine.weight(&genesis),
+                validators: engine.genesis_validators(),
+                block: genesis.clone(),
+                undo: BlockUndo::default(),
+            },
+        );
+
+        Ok(Blockchain {
+            blocks: vec![genesis],
+            tree,
+            ledger,
+            spec,
+        })
+    }
+
+    pub fn spec(&self) -> &ChainSpec {
+        &self.spec
+    }
+
+    pub fn genesis_hash(&self) -> Option<BigUint> {
+        self.blocks.first()?.hash()
+    }
+
+    pub fn add_block(&mut self, new_block: Block) -> Result<ChainUpdate, ValidateBlockError> {
+        let hash = self.check_block(&new_block)?;
+        let parent = &self.tree[new_block.parent_hash()];
+
+        let engine = self.spec.engine();
+        let total_work = &parent.total_work + engine.weight(&new_block);
+        let validators = engine.next_validators(&parent.validators, &new_block);
+        let is_heavier = total_work > self.total_work();
+
+        self.tree.insert(
+            hash.to_owned(),
+            TreeEntry {
+                block: new_block,
+                total_work,
+                validators,
+                undo: BlockUndo::default(),
+            },
+        );
+
+        if !is_heavier {
+            return Ok(ChainUpdate::default());
+        }
+
+        Ok(self.reorg_to(hash))
+    }
+
+    // Validates `new_block` against its parent without adding it, returning
+    // its hash.
+    pub fn check_block(&self, new_block: &Block) -> Result<BigUint, ValidateBlockError> {
+        let hash = new_block.try_hash()?;
+
+        if self.tree.contains_key(&hash) {
+            return Err(LinkageError::DuplicateBlock { hash }.into());
+        }
+
+        let parent =
+            self.tree
+                .get(new_block.parent_hash())
+                .ok_or_else(|| LinkageError::UnknownParent {
+                    parent_hash: new_block.parent_hash().to_owned(),
+                })?;
+
+        // Final chains never fork, so anything but a child of the head is
+        // either stale or conflicting.
+        if self.spec.engine().finalizes() {
+            let head = self.head().map(|head| &head.block);
+
+            if let Some(head_hash) = head.and_then(Block::hash) {
+                if head_hash != *new_block.parent_hash() {
+                    return Err(LinkageError::NotExtendingHead {
+                        head: head_hash,
+                        parent_hash: new_block.parent_hash().to_owned(),
+                    }
+                    .into());
+                }
+            }
+        }
+
+        let ancestors = self.ancestors(new_block.parent_hash(), self.spec.ancestor_window());
+
+        Block::validate_block(&ancestors, &parent.validators, new_block, &self.spec)?;
+
+        let changes = self
+            .spec
+            .engine()
+            .balance_changes(&parent.validators, new_block);
+
+        let mut ledger = self.ledger_at(new_block.parent_hash()).into_owned();
+        ledger.apply_block(
+            new_block,
+            self.spec.reward.subsidy(new_block.number()),
+            &changes,
+        )?;
+
+        let state_root = ledger.root();
+
+        if *new_block.headers().state_root() != state_root {
+            return Err(BodyError::InvalidStateRoot {
+                expected: state_root,
+                actual: new_block.headers().state_root().to_owned(),
+            }
+            .into());
         }
+
+        Ok(hash)
     }
 
-    pub fn add_block(&mut self, new_block: Block) {
-        self.blocks.push(new_block)
+    // Coins as of `hash`, which need not be canonical.
+    fn ledger_at(&self, hash: &BigUint) -> Cow<'_, Ledger> {
+        if self.get_last_block().and_then(Block::hash).as_ref() == Some(hash) {
+            return Cow::Borrowed(&self.ledger);
+        }
+
+        let mut ledger = self.ledger.clone();
+        let mut branch = Vec::new();
+        let mut hash = hash.to_owned();
+
+        while !self.is_canonical(&hash) {
+            let block = &self.tree[&hash].block;
+            hash = block.parent_hash().to_owned();
+            branch.push(block);
+        }
+
+        let fork_height = self.tree[&hash].block.number() as usize;
+
+        for block in self.blocks[fork_height + 1..].iter().rev() {
+            self.revert_block(&mut ledger, block);
+        }
+
+        for block in branch.into_iter().rev() {
+            self.apply_block(&mut ledger, block);
+        }
+
+        Cow::Owned(ledger)
     }
 
-    pub fn get_last_block(&self) -> Option<&Block> {
-        match self.blocks.last() {
-            Some(block) => Some(block),
-            None => None,
+    fn reorg_to(&mut self, tip_hash: BigUint) -> ChainUpdate {
+        let mut connected = Vec::new();
+        let mut hash = tip_hash;
+
+        while !self.is_canonical(&hash) {
+            let block = self.tree[&hash].block.clone();
+            hash = block.parent_hash().to_owned();
+            connected.push(block);
+        }
+
+        connected.reverse();
+
+        let fork_height = self.tree[&hash].block.number() as usize;
+        let disconnected = self.blocks.split_off(fork_height + 1);
+
+        let mut ledger = std::mem::take(&mut self.ledger);
+
+        for block in disconnected.iter().rev() {
+            self.revert_block(&mut ledger, block);
+        }
+
+        for block in &connected {
+            let undo = self.apply_block(&mut ledger, block);
+
+            if let Some(entry) = block.hash().and_then(|hash| self.tree.get_mut(&hash)) {
+                entry.undo = undo;
+            }
+        }
+
+        self.ledger = ledger;
+
+        self.blocks.extend(connected.iter().cloned());
+
+        ChainUpdate {
+            disconnected: disconnected.into_iter().rev().collect(),
+            connected,
+        }
+    }
+
+    // Only ever called with blocks `check_block` accepted on top of `ledger`.
+    fn apply_block(&self, ledger: &mut Ledger, block: &Block) -> BlockUndo {
+        ledger
+            .apply_block(
+                block,
+                self.spec.reward.subsidy(block.number()),
+                &self.balance_changes(block),
+            )
+            .expect("Validated block no longer applies")
+    }
+
+    // Only ever called with canonical blocks, whose undo is current.
+    fn revert_block(&self, ledger: &mut Ledger, block: &Block) {
+        let hash = block.hash().expect("Canonical block no longer hashes");
+
+        ledger.revert_block(
+            block,
+            self.spec.reward.subsidy(block.number()),
+            &self.balance_changes(block),
+            &self.tree[&hash].undo,
+        );
+    }
+
+    fn balance_changes(&self, block: &Block) -> Vec<BalanceChange> {
+        match self.tree.get(block.parent_hash()) {
+            Some(parent) => self
+                .spec
+                .engine()
+                .balance_changes(&parent.validators, block),
+            None => Vec::new(),
+        }
+    }
+
+    // Accounts as of the head, on a chain with the account ledger.
+    pub fn state(&self) -> Option<&WorldState> {
+        self.ledger.accounts()
+    }
+
+    // Unspent outputs as of the head, on a chain with the UTXO ledger.
+    pub fn utxos(&self) -> Option<&UtxoSet> {
+        self.ledger.utxos()
+    }
+
+    pub fn balance(&self, address: &BigUint) -> u64 {
+        self.ledger.balance(address)
+    }
+
+    // Coins in existence once block `height` is applied, whether or not the
+    // canonical chain has reached it yet.
+    pub fn issued_supply(&self, height: u32) -> u64 {
+        self.spec.issued_supply(height)
+    }
+
+    fn is_canonical(&self, hash: &BigUint) -> bool {
+        match self.tree.get(hash) {
+            Some(entry) => match self.blocks.get(entry.block.number() as usize) {
+                Some(block) => block.hash().as_ref() == Some(hash),
+                None => false,
+            },
+            None => false,
+        }
+    }
+
+    fn head(&self) -> Option<&TreeEntry> {
+        self.tree.get(&self.get_last_block()?.hash()?)
+    }
+
+    // Unsealed block extending the current head, ready for a nonce search.
+    pub fn block_template(&self, beneficiary: BigUint, timestamp: u64) -> Option<Block> {
+        let head = self.head()?;
+        let ancestors = self.ancestors(&head.block.hash()?, self.spec.ancestor_window());
+
+        let block = Block::new_child(
+            &ancestors,
+            &head.validators,
+            beneficiary,
+            &self.spec,
+            timestamp,
+        )?;
+
+        self.with_state_root(&head.validators, block)
+    }
+
+    // Sets the state root `block` needs on top of the head, or `None` if it
+    // does not apply there.
+    fn with_state_root(&self, validators: &ValidatorSet, block: Block) -> Option<Block> {
+        let mut ledger = self.ledger.clone();
+        ledger
+            .apply_block(
+                &block,
+                self.spec.reward.subsidy(block.number()),
+                &self.spec.engine().balance_changes(validators, &block),
+            )
+            .ok()?;
+
+        Some(block.with_state_root(ledger.root()))
+    }
+
+    // Asks the consensus engine how to seal a template extending the head,
+    // voting on one of `proposals` and filling the body from `pending` where
+    // the engine supports it.
+    pub fn prepare_seal(
+        &self,
+        beneficiary: BigUint,
+        timestamp: u64,
+        proposals: &BTreeMap<BigUint, bool>,
+        pending: &BlockBody,
+    ) -> Seal {
+        let engine = self.spec.engine();
+
+        let (head, block) = match self.head().zip(self.block_template(beneficiary, timestamp)) {
+            Some(found) => found,
+            None => return Seal::None,
+        };
+        let ancestors = self.ancestors(block.parent_hash(), self.spec.ancestor_window());
+        let block = engine.propose(&head.validators, proposals, block);
+        let block = self.fill_body(&head.validators, block, pending);
+
+        match self.with_state_root(&head.validators, block) {
+            Some(block) => engine.generate_seal(&ancestors, &head.validators, block),
+            None => Seal::None,
+        }
+    }
+
+    // Adds whatever in `pending` is still valid on top of the head, one
+    // item at a time, to the body of `block`.
+    fn fill_body(&self, validators: &ValidatorSet, block: Block, pending: &BlockBody) -> Block {
+        let engine = self.spec.engine();
+        let mut block = block;
+
+        let accepts = |block: &Block| {
+            Block::verify_transactions(block.body(), &self.spec).is_ok()
+                && engine.verify_body(validators, block).is_ok()
+                && self
+                    .ledger
+                    .check_block(
+                        block,
+                        self.spec.reward.subsidy(block.number()),
+                        &engine.balance_changes(validators, block),
+                    )
+                    .is_ok()
+        };
+
+        for transaction in &pending.transactions {
+            let mut body = block.body().clone();
+            body.transactions.push(transaction.to_owned());
+
+            let candidate = block.clone().with_body(body);
+
+            if accepts(&candidate) {
+                block = candidate;
+            }
+        }
+
+        for transaction in &pending.staking {
+            let mut body = block.body().clone();
+            body.staking.push(transaction.to_owned());
+
+            let candidate = block.clone().with_body(body);
+
+            if accepts(&candidate) {
+                block = candidate;
+            }
+        }
+
+        for evidence in &pending.evidence {
+            let mut body = block.body().clone();
+            body.evidence.push(evidence.to_owned());
+
+            let candidate = block.clone().with_body(body);
+
+            if accepts(&candidate) {
+                block = candidate;
+            }
+        }
+
+        for transaction in &pending.utxo {
+            let mut body = block.body().clone();
+            body.utxo.push(transaction.to_owned());
+
+            let candidate = block.clone().with_body(body);
+
+            if accepts(&candidate) {
+                block = candidate;
+            }
+        }
+
+        block
+    }
+
+    // Another signed block by the same beneficiary for the same number and
+    // attempt as `block`, if this node has seen one.
+    pub fn find_double_sign(&self, block: &Block) -> Option<DoubleSign> {
+        let hash = block.hash()?;
+        let signer = block.signer()?;
+
+        self.tree
+            .iter()
+            .filter(|(other, _)| **other != hash)
+            .map(|(_, entry)| &entry.block)
+            .find(|other| {
+                other.number() == block.number()
+                    && other.extra_nonce() == block.extra_nonce()
+                    && other.beneficiary() == block.beneficiary()
+                    && other.signer().as_ref() == Some(&signer)
+            })
+            .map(|other| DoubleSign {
+                first: SignedHeader::of(other),
+                second: SignedHeader::of(block),
+            })
+    }
+
+    // Validators in force after canonical block `number`.
+    pub fn validators_at(&self, number: u32) -> Option<&ValidatorSet> {
+        let hash = self.get_block_by_number(number)?.hash()?;
+
+        self.tree.get(&hash).map(|entry| &entry.validators)
+    }
+
+    // Up to `count` blocks ending with `hash`, oldest first.
+    pub fn ancestors(&self, hash: &BigUint, count: usize) -> Vec<Block> {
+        let mut ancestors = Vec::with_capacity(count);
+        let mut next = self.tree.get(hash);
+
+        while let Some(entry) = next {
+            if ancestors.len() == count {
+                break;
+            }
+
+            ancestors.push(entry.block.clone());
+
+            if entry.block.number() == 0 {
+                break;
+            }
+
+            next = self.tree.get(entry.block.parent_hash());
+        }
+
+        ancestors.reverse();
+        ancestors
+    }
+
+    pub fn get_block(&self, hash: &BigUint) -> Option<&Block> {
+        self.tree.get(hash).map(|entry| &entry.block)
+    }
+
+    pub fn get_block_by_number(&self, number: u32) -> Option<&Block> {
+        self.blocks.get(number as usize)
+    }
+
+    pub fn total_work(&self) -> BigUint {
+        self.get_last_block()
+            .and_then(|block| block.hash())
+            .and_then(|hash| self.tree.get(&hash))
+            .map(|entry| entry.total_work.to_owned())
+            .unwrap_or_default()
+    }
+
+    pub fn validate_chain(&self) -> Result<(), ValidateChainError> {
+        let expected_genesis = Block::genesis(&self.spec);
+
+        match self.blocks.first() {
+            Some(genesis) if *genesis == expected_genesis => {}
+            genesis => {
+                return Err(ValidateChainError {
+                    height: 0,
+                    reason: HeaderError::InvalidGenesis {
+                        expected: expected_genesis.hash(),
+                        actual: genesis.and_then(|genesis| genesis.hash()),
+                    }
+                    .into(),
+                })
+            }
+        }
+
+        let window = self.spec.ancestor_window();
+        let engine = self.spec.engine();
+        let mut validators = engine.genesis_validators();
+        let mut ledger = Ledger::genesis(&self.spec, &self.blocks[0]);
+
+        for height in 1..self.blocks.len() {
+            let start = height.saturating_sub(window);
+            let block = &self.blocks[height];
+
+            if let Err(reason) =
+                Block::validate_block(&self.blocks[start..height], &validators, block, &self.spec)
+            {
+                return Err(ValidateChainError { height, reason });
+            }
+
+            // Replays the ledger, so every header must commit to the state
+            // its block leaves.
+            if let Err(error) = ledger.apply_block(
+                block,
+                self.spec.reward.subsidy(block.number()),
+                &engine.balance_changes(&validators, block),
+            ) {
+                return Err(ValidateChainError {
+                    height,
+                    reason: error.into(),
+                });
+            }
+
+            let state_root = ledger.root();
+
+            if *block.headers().state_root() != state_root {
+                return Err(ValidateChainError {
+                    height,
+                    reason: BodyError::InvalidStateRoot {
+                        expected: state_root,
+                        actual: block.headers().state_root().to_owned(),
+                    }
+                    .into(),
+                });
+            }
+
+            validators = engine.next_validators(&validators, block);
         }
+
+        Ok(())
+    }
+
+    pub fn get_last_block(&self) -> Option<&Block> {
+        self.blocks.last()
     }
 
     pub fn current_block_height(&self) -> usize {
-        return self.blocks.len();
+        self.blocks.len()
     }
 }
@@ -0,0 +1,332 @@
+use std::{collections::BTreeMap, error::Error, fmt, fs, path::Path};
+
+use num_bigint::BigUint;
+use num_traits::Zero;
+use serde_derive::{Deserialize, Serialize};
+
+use super::consensus::{
+    AuthorityEngine, BftEngine, ConsensusEngine, ConsensusSpec, InstantSealEngine, PowEngine,
+    StakeEngine,
+};
+use super::difficulty::DifficultySpec;
+use super::encoding::MAX_BYTES_LENGTH;
+use super::ledger::LedgerSpec;
+use super::pow::PowSpec;
+use super::serde_hex::{self, parse_hex_biguint};
+use super::target::{Target, MAX_TARGET};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ChainSpec {
+    pub name: String,
+    // Signed into every transaction, so it cannot be replayed on chains with
+    // a different id.
+    pub chain_id: u64,
+    pub genesis: GenesisSpec,
+    #[serde(default)]
+    pub consensus: ConsensusSpec,
+    #[serde(default)]
+    pub ledger: LedgerSpec,
+    #[serde(default)]
+    pub difficulty: DifficultySpec,
+    #[serde(default)]
+    pub pow: PowSpec,
+    #[serde(default)]
+    pub timestamp: TimestampSpec,
+    #[serde(default)]
+    pub reward: RewardSpec,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TimestampSpec {
+    // A block must be later than the median timestamp of this many ancestors.
+    pub median_time_span: usize,
+    // How many seconds ahead of the local clock a block may be.
+    pub max_future_drift: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RewardSpec {
+    // Subsidy paid to the beneficiary of block 1.
+    pub initial_subsidy: u64,
+    // The subsidy halves every this many blocks; 0 never halves it.
+    pub halving_interval: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GenesisSpec {
+    pub timestamp: u64,
+    pub difficulty: u32,
+    #[serde(with = "serde_hex::biguint")]
+    pub beneficiary: BigUint,
+    #[serde(with = "serde_hex::bytes", default)]
+    pub extra_data: Vec<u8>,
+    #[serde(default)]
+    pub alloc: BTreeMap<String, u64>,
+}
+
+#[derive(Debug)]
+pub enum ChainSpecError {
+    Io(std::io::Error),
+    Parse(serde_json::Error),
+    InvalidAddress(String),
+    InvalidPow(String),
+    InvalidConsensus(String),
+    InvalidGenesis(String),
+}
+
+impl fmt::Display for ChainSpecError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ChainSpecError::Io(error) => write!(f, "failed to read chain spec: {}", error),
+            ChainSpecError::Parse(error) => write!(f, "failed to parse chain spec: {}", error),
+            ChainSpecError::InvalidAddress(address) => {
+                write!(f, "invalid address {}", address)
+            }
+            ChainSpecError::InvalidPow(reason) => {
+                write!(f, "invalid proof of work parameters: {}", reason)
+            }
+            ChainSpecError::InvalidConsensus(reason) => {
+                write!(f, "invalid consensus parameters: {}", reason)
+            }
+            ChainSpecError::InvalidGenesis(reason) => {
+                write!(f, "invalid genesis block: {}", reason)
+            }
+        }
+    }
+}
+
+impl Error for ChainSpecError {}
+
+impl ChainSpec {
+    pub fn load(path: impl AsRef<Path>) -> Result<ChainSpec, ChainSpecError> {
+        let text = fs::read_to_string(path).map_err(ChainSpecError::Io)?;
+        let spec: ChainSpec = serde_json::from_str(&text).map_err(ChainSpecError::Parse)?;
+
+        spec.validate()?;
+
+        Ok(spec)
+    }
+
+    // Everything a genesis block and an engine are built from must be usable.
+    pub fn validate(&self) -> Result<(), ChainSpecError> {
+        self.allocations()?;
+        self.stakes()?;
+        self.pow.validate().map_err(ChainSpecError::InvalidPow)?;
+        self.validate_consensus()
+            .map_err(ChainSpecError::InvalidConsensus)?;
+        self.validate_genesis()
+            .map_err(ChainSpecError::InvalidGenesis)
+    }
+
+    // Difficulty and proof-of-work settings only apply to the proof-of-work engine.
+    pub fn engine(&self) -> Box<dyn ConsensusEngine> {
+        match &self.consensus {
+            ConsensusSpec::ProofOfWork => Box::new(PowEngine {
+                difficulty: self.difficulty.algorithm(),
+                pow: self.pow.algorithm(),
+            }),
+            ConsensusSpec::Authority { period, signers } => Box::new(AuthorityEngine {
+                period: *period,
+                signers: signers.to_owned(),
+            }),
+            ConsensusSpec::InstantSeal => Box::new(InstantSealEngine),
+            ConsensusSpec::Bft { validators, .. } => Box::new(BftEngine {
+                validators: validators.to_owned(),
+            }),
+            ConsensusSpec::ProofOfStake {
+                period,
+                epoch_length,
+                min_stake,
+                slash_percent,
+                ..
+            } => Box::new(StakeEngine {
+                period: *period,
+                epoch_length: *epoch_length,
+                min_stake: *min_stake,
+                slash_percent: *slash_percent,
+                stakes: self.stakes().unwrap_or_default(),
+            }),
+        }
+    }
+
+    // The genesis headers must encode, and hence hash.
+    fn validate_genesis(&self) -> Result<(), String> {
+        let genesis = &self.genesis;
+
+        if genesis.difficulty == 0 {
+            return Err("difficulty must be at least 1".to_string());
+        }
+
+        if Target::new(&*MAX_TARGET / genesis.difficulty).is_none() {
+            return Err(format!("difficulty {} has no target", genesis.difficulty));
+        }
+
+        if genesis.extra_data.len() > MAX_BYTES_LENGTH {
+            return Err(format!(
+                "extra data is {} bytes, at most {} allowed",
+                genesis.extra_data.len(),
+                MAX_BYTES_LENGTH
+            ));
+        }
+
+        if genesis.beneficiary.bits() > 256 {
+            return Err("beneficiary does not fit in 256 bits".to_string());
+        }
+
+        Ok(())
+    }
+
+    fn validate_consensus(&self) -> Result<(), String> {
+        if let ConsensusSpec::ProofOfStake {
+            epoch_length,
+            slash_percent,
+            ..
+        } = self.consensus
+        {
+            if epoch_length == 0 {
+                return Err("epoch length must be at least 1".to_string());
+            }
+
+            if slash_percent > 100 {
+                return Err(format!("cannot slash {}% of a stake", slash_percent));
+            }
+
+            if self.ledger != LedgerSpec::Account {
+                return Err("stakes are bonded from accounts".to_string());
+            }
+        }
+
+        Ok(())
+    }
+
+    // Number of ancestors, ending with the parent, needed to validate a block.
+    pub fn ancestor_window(&self) -> usize {
+        self.engine()
+            .window()
+            .max(self.timestamp.median_time_span)
+            .max(1)
+    }
+
+    // Sum of the genesis allocations and every subsidy up to and including
+    // block `height`.
+    pub fn issued_supply(&self, height: u32) -> u64 {
+        let allocated = self
+            .genesis
+            .alloc
+            .values()
+            .fold(0u64, |total, balance| total.saturating_add(*balance));
+
+        allocated.saturating_add(self.reward.issued(height))
+    }
+
+    // Genesis validators under proof of stake and what they bond to themselves.
+    pub fn stakes(&self) -> Result<Vec<(BigUint, u64)>, ChainSpecError> {
+        match &self.consensus {
+            ConsensusSpec::ProofOfStake { stakes, .. } => parse_balances(stakes),
+            _ => Ok(Vec::new()),
+        }
+    }
+
+    pub fn allocations(&self) -> Result<Vec<(BigUint, u64)>, ChainSpecError> {
+        parse_balances(&self.genesis.alloc)
+    }
+}
+
+fn parse_balances(balances: &BTreeMap<String, u64>) -> Result<Vec<(BigUint, u64)>, ChainSpecError> {
+    balances
+        .iter()
+        .map(|(address, balance)| match parse_hex_biguint(address) {
+            Some(parsed) if parsed.bits() <= 256 => Ok((parsed, *balance)),
+            _ => Err(ChainSpecError::InvalidAddress(address.to_owned())),
+        })
+        .collect()
+}
+
+impl Default for ChainSpec {
+    fn default() -> Self {
+        ChainSpec {
+            name: "dev".to_string(),
+            chain_id: 1337,
+            consensus: ConsensusSpec::default(),
+            ledger: LedgerSpec::default(),
+            genesis: GenesisSpec {
+                timestamp: 1700000000,
+                difficulty: 100000,
+                beneficiary: BigUint::zero(),
+                extra_data: Vec::new(),
+                alloc: BTreeMap::new(),
+            },
+            difficulty: DifficultySpec::default(),
+            pow: PowSpec::default(),
+            timestamp: TimestampSpec::default(),
+            reward: RewardSpec::default(),
+        }
+    }
+}
+
+impl Default for TimestampSpec {
+    fn default() -> Self {
+        TimestampSpec {
+            median_time_span: 11,
+            max_future_drift: 15,
+        }
+    }
+}
+
+impl Default for RewardSpec {
+    fn default() -> Self {
+        RewardSpec {
+            initial_subsidy: 50,
+            halving_interval: 210000,
+        }
+    }
+}
+
+impl RewardSpec {
+    // Genesis pays nothing; the subsidy reaches zero after 64 halvings.
+    pub fn subsidy(&self, number: u32) -> u64 {
+        if number == 0 {
+            return 0;
+        }
+
+        if self.halving_interval == 0 {
+            return self.initial_subsidy;
+        }
+
+        let halvings = (number - 1) / self.halving_interval;
+
+        self.initial_subsidy.checked_shr(halvings).unwrap_or(0)
+    }
+
+    // Total subsidy of blocks 1..=height, summed one halving epoch at a time.
+    pub fn issued(&self, height: u32) -> u64 {
+        let mut issued = 0u64;
+        let mut number = 1u32;
+
+        while number <= height {
+            let subsidy = self.subsidy(number);
+
+            if subsidy == 0 {
+                break;
+            }
+
+            let epoch_end = match self.halving_interval {
+                0 => height,
+                interval => {
+                    let epoch = (number - 1) / interval;
+                    ((epoch as u64 + 1) * interval as u64).min(height as u64) as u32
+                }
+            };
+
+            let blocks = (epoch_end - number + 1) as u64;
+            issued = issued.saturating_add(subsidy.saturating_mul(blocks));
+
+            number = match epoch_end.checked_add(1) {
+                Some(next) => next,
+                None => break,
+            };
+        }
+
+        issued
+    }
+}
@@ -0,0 +1,890 @@
+use std::collections::BTreeMap;
+
+use num_bigint::BigUint;
+use num_traits::One;
+use serde_derive::{Deserialize, Serialize};
+
+use super::block::Block;
+use super::difficulty::DifficultyAlgorithm;
+use super::encoding::{encode_u256, EncodingError};
+use super::error::{BodyError, ProofOfWorkError, SealError, ValidateBlockError};
+use super::pow::PowAlgorithm;
+use super::serde_hex;
+use super::staking::StakeLedger;
+use super::target::Target;
+
+pub trait ConsensusEngine: Send + Sync {
+    // Number of most recent blocks, ending with the parent, the engine looks at.
+    fn window(&self) -> usize;
+
+    // `validators` is always the set in force after the last ancestor.
+
+    // Difficulty a child of the last ancestor made by `beneficiary` at
+    // `timestamp` must carry in its bits.
+    fn difficulty(
+        &self,
+        ancestors: &[Block],
+        validators: &ValidatorSet,
+        beneficiary: &BigUint,
+        timestamp: u64,
+    ) -> BigUint;
+
+    // Checks the seal of a block whose headers, bits included, are otherwise valid.
+    fn verify_seal(
+        &self,
+        ancestors: &[Block],
+        validators: &ValidatorSet,
+        block: &Block,
+        header_bytes: &[u8],
+        target: &Target,
+    ) -> Result<(), ValidateBlockError>;
+
+    // Checks the body of a block whose seal and transactions are valid.
+    // Engines without staking accept no staking transactions or evidence.
+    fn verify_body(
+        &self,
+        _validators: &ValidatorSet,
+        block: &Block,
+    ) -> Result<(), ValidateBlockError> {
+        let body = block.body();
+
+        if !body.staking.is_empty() || !body.evidence.is_empty() {
+            return Err(BodyError::Unsupported.into());
+        }
+
+        Ok(())
+    }
+
+    // Balance changes the block makes on top of its subsidy, in order. Only
+    // called for blocks with a valid body.
+    fn balance_changes(&self, _validators: &ValidatorSet, _block: &Block) -> Vec<BalanceChange> {
+        Vec::new()
+    }
+
+    // How this node should seal `block`, an unsealed child of the last ancestor.
+    fn generate_seal(&self, ancestors: &[Block], validators: &ValidatorSet, block: Block) -> Seal;
+
+    fn genesis_validators(&self) -> ValidatorSet {
+        ValidatorSet::default()
+    }
+
+    // Set in force after `block`, which was sealed under `validators`.
+    fn next_validators(&self, validators: &ValidatorSet, _block: &Block) -> ValidatorSet {
+        validators.clone()
+    }
+
+    // Puts a vote for one of this node's `proposals`, candidates mapped to
+    // whether to authorize them, into an unsealed block.
+    fn propose(
+        &self,
+        _validators: &ValidatorSet,
+        _proposals: &BTreeMap<BigUint, bool>,
+        block: Block,
+    ) -> Block {
+        block
+    }
+
+    // Weight the block adds to its branch; the heaviest branch is canonical.
+    fn weight(&self, block: &Block) -> BigUint {
+        block.work()
+    }
+
+    // Whether blocks must carry their producer's signature. Signatures are
+    // checked whenever present, so this only makes them mandatory.
+    fn requires_signature(&self) -> bool {
+        false
+    }
+
+    // Whether blocks are only sealed when asked for, instead of continuously.
+    fn seals_on_demand(&self) -> bool {
+        false
+    }
+
+    // Whether blocks are final once added, so the chain only ever extends its
+    // head and blocks must arrive with proof that the validators committed them.
+    fn finalizes(&self) -> bool {
+        false
+    }
+}
+
+#[derive(Serialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct ValidatorSet {
+    #[serde(with = "serde_hex::biguint_vec")]
+    pub validators: Vec<BigUint>,
+    // Votes that have not reached a majority yet.
+    pub votes: Vec<Vote>,
+    // Signers of the most recent blocks, newest last.
+    #[serde(with = "serde_hex::biguint_vec")]
+    pub recent: Vec<BigUint>,
+    // Bonds and proposer seed under proof of stake.
+    #[serde(skip_serializing_if = "StakeLedger::is_empty")]
+    pub stake: StakeLedger,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Vote {
+    #[serde(with = "serde_hex::biguint")]
+    pub signer: BigUint,
+    #[serde(with = "serde_hex::biguint")]
+    pub candidate: BigUint,
+    pub authorize: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BalanceChange {
+    Credit { address: BigUint, amount: u64 },
+    Debit { address: BigUint, amount: u64 },
+}
+
+impl ValidatorSet {
+    pub fn new(validators: Vec<BigUint>) -> Self {
+        ValidatorSet {
+            validators,
+            votes: Vec::new(),
+            recent: Vec::new(),
+            stake: StakeLedger::default(),
+        }
+    }
+
+    pub fn contains(&self, validator: &BigUint) -> bool {
+        self.validators.contains(validator)
+    }
+
+    pub fn position(&self, validator: &BigUint) -> Option<usize> {
+        self.validators
+            .iter()
+            .position(|candidate| candidate == validator)
+    }
+
+    pub fn len(&self) -> usize {
+        self.validators.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.validators.is_empty()
+    }
+
+    // Whether voting `authorize` on `candidate` would change anything. The
+    // last validator cannot be voted out.
+    pub fn is_meaningful(&self, candidate: &BigUint, authorize: bool) -> bool {
+        if authorize {
+            !self.contains(candidate)
+        } else {
+            self.contains(candidate) && self.len() > 1
+        }
+    }
+
+    // Records `vote`, replacing the signer's earlier vote on the same
+    // candidate, and applies the change once more than half of the
+    // validators agree.
+    pub fn cast(&mut self, vote: Vote) {
+        self.votes
+            .retain(|cast| !(cast.signer == vote.signer && cast.candidate == vote.candidate));
+
+        if !self.contains(&vote.signer) || !self.is_meaningful(&vote.candidate, vote.authorize) {
+            return;
+        }
+
+        self.votes.push(vote.to_owned());
+
+        let tally = self
+            .votes
+            .iter()
+            .filter(|cast| cast.candidate == vote.candidate && cast.authorize == vote.authorize)
+            .count();
+
+        if tally <= self.len() / 2 {
+            return;
+        }
+
+        if vote.authorize {
+            self.validators.push(vote.candidate.to_owned());
+        } else {
+            self.validators
+                .retain(|validator| *validator != vote.candidate);
+            self.votes.retain(|cast| cast.signer != vote.candidate);
+        }
+
+        self.votes.retain(|cast| cast.candidate != vote.candidate);
+    }
+}
+
+pub enum Seal {
+    // The block is complete once a nonce meeting its target is found.
+    Work(Block),
+    // The block is complete as is, but may not be published before
+    // `not_before` (unix seconds).
+    Ready { block: Block, not_before: u64 },
+    // This node may not seal on top of these ancestors.
+    None,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(tag = "engine", rename_all = "snake_case")]
+pub enum ConsensusSpec {
+    #[default]
+    ProofOfWork,
+    Authority {
+        // Minimum number of seconds between blocks.
+        period: u64,
+        // Initial signers; later changes are voted on in block headers.
+        #[serde(with = "serde_hex::biguint_vec")]
+        signers: Vec<BigUint>,
+    },
+    InstantSeal,
+    Bft {
+        #[serde(with = "serde_hex::biguint_vec")]
+        validators: Vec<BigUint>,
+        // Length of the first round's propose, prevote and precommit steps,
+        // and the pause after a commit; later rounds wait longer.
+        timeout_ms: u64,
+    },
+    ProofOfStake {
+        // Seconds between a block and the first attempt at its child; every
+        // further attempt comes one period later.
+        period: u64,
+        // Stake changes take effect after the last block of an epoch.
+        epoch_length: u32,
+        // Stake a validator must bond to itself to propose.
+        min_stake: u64,
+        // Percentage of the stake bonded to a double signer that is burned.
+        slash_percent: u8,
+        // Genesis validators and the stake they bond to themselves.
+        stakes: BTreeMap<String, u64>,
+    },
+}
+
+// Difficulty and hash function come from the rest of the chain spec.
+pub struct PowEngine {
+    pub difficulty: Box<dyn DifficultyAlgorithm>,
+    pub pow: Box<dyn PowAlgorithm>,
+}
+
+impl ConsensusEngine for PowEngine {
+    fn window(&self) -> usize {
+        self.difficulty.window()
+    }
+
+    fn difficulty(
+        &self,
+        ancestors: &[Block],
+        _validators: &ValidatorSet,
+        _beneficiary: &BigUint,
+        timestamp: u64,
+    ) -> BigUint {
+        self.difficulty.next_difficulty(ancestors, timestamp)
+    }
+
+    fn verify_seal(
+        &self,
+        _ancestors: &[Block],
+        _validators: &ValidatorSet,
+        block: &Block,
+        header_bytes: &[u8],
+        target: &Target,
+    ) -> Result<(), ValidateBlockError> {
+        let under_target_hash =
+            self.pow
+                .hash(header_bytes, block.nonce())
+                .ok_or(BodyError::Encoding {
+                    error: EncodingError::ValueTooLarge,
+                })?;
+
+        if !target.is_met_by(&under_target_hash) {
+            return Err(ProofOfWorkError::AboveTarget {
+                target: target.value().to_owned(),
+                actual: under_target_hash,
+            }
+            .into());
+        }
+
+        Ok(())
+    }
+
+    fn generate_seal(
+        &self,
+        _ancestors: &[Block],
+        _validators: &ValidatorSet,
+        block: Block,
+    ) -> Seal {
+        Seal::Work(block)
+    }
+}
+
+// Clique-style proof of authority. Signers take turns by block number; the
+// in-turn signer's blocks weigh 2 and anyone else's 1, so the in-turn branch
+// wins forks. A signer may sign at most one of any `signers / 2 + 1`
+// consecutive blocks. The signer is the address the block's signature
+// recovers to, which must also be its beneficiary.
+//
+// A block votes on a signer change when its `extra_data` holds the candidate
+// address and its `extra_nonce` is `VOTE_AUTHORIZE` or `VOTE_DROP`.
+pub struct AuthorityEngine {
+    pub period: u64,
+    pub signers: Vec<BigUint>,
+}
+
+impl AuthorityEngine {
+    const IN_TURN_DIFFICULTY: u32 = 2;
+    const OUT_OF_TURN_DIFFICULTY: u32 = 1;
+
+    pub const VOTE_AUTHORIZE: u64 = u64::MAX;
+    pub const VOTE_DROP: u64 = 0;
+
+    // How many turns after the in-turn signer `signer` comes at `number`.
+    fn distance(validators: &ValidatorSet, signer: &BigUint, number: u32) -> Option<usize> {
+        let position = validators.position(signer)?;
+        let in_turn = number as usize % validators.len();
+
+        Some((position + validators.len() - in_turn) % validators.len())
+    }
+
+    fn recently_signed(validators: &ValidatorSet, signer: &BigUint) -> bool {
+        validators
+            .recent
+            .iter()
+            .rev()
+            .take(validators.len() / 2)
+            .any(|recent| recent == signer)
+    }
+
+    // `Err` when the block carries something that is not a valid vote, or
+    // is not signed by the signer casting it.
+    fn vote(block: &Block) -> Result<Option<Vote>, SealError> {
+        if block.headers().extra_data().is_empty() {
+            return Ok(None);
+        }
+
+        let signer = block.signer().ok_or(SealError::MissingSignature)?;
+
+        let authorize = match block.extra_nonce() {
+            Self::VOTE_AUTHORIZE => true,
+            Self::VOTE_DROP => false,
+            _ => return Err(SealError::InvalidVote),
+        };
+
+        Ok(Some(Vote {
+            signer,
+            candidate: BigUint::from_bytes_be(block.headers().extra_data()),
+            authorize,
+        }))
+    }
+
+    fn with_vote(block: Block, candidate: &BigUint, authorize: bool) -> Block {
+        let extra_nonce = if authorize {
+            Self::VOTE_AUTHORIZE
+        } else {
+            Self::VOTE_DROP
+        };
+
+        block
+            .with_extra_data(candidate.to_bytes_be())
+            .with_extra_nonce(extra_nonce)
+    }
+}
+
+impl ConsensusEngine for AuthorityEngine {
+    fn window(&self) -> usize {
+        1
+    }
+
+    fn difficulty(
+        &self,
+        ancestors: &[Block],
+        validators: &ValidatorSet,
+        beneficiary: &BigUint,
+        _timestamp: u64,
+    ) -> BigUint {
+        let number = ancestors.last().map_or(0, |parent| parent.number() + 1);
+
+        match Self::distance(validators, beneficiary, number) {
+            Some(0) => BigUint::from(Self::IN_TURN_DIFFICULTY),
+            _ => BigUint::from(Self::OUT_OF_TURN_DIFFICULTY),
+        }
+    }
+
+    fn verify_seal(
+        &self,
+        ancestors: &[Block],
+        validators: &ValidatorSet,
+        block: &Block,
+        _header_bytes: &[u8],
+        _target: &Target,
+    ) -> Result<(), ValidateBlockError> {
+        // Only the signature proves who sealed the block; `validate_block`
+        // has already held it to the beneficiary.
+        let signer = &block.signer().ok_or(SealError::MissingSignature)?;
+
+        if !validators.contains(signer) {
+            return Err(SealError::UnauthorizedSigner {
+                signer: signer.to_owned(),
+            }
+            .into());
+        }
+
+        if Self::recently_signed(validators, signer) {
+            return Err(SealError::RecentlySigned {
+                signer: signer.to_owned(),
+            }
+            .into());
+        }
+
+        if let Some(parent) = ancestors.last() {
+            let earliest = parent.timestamp() + self.period;
+
+            if block.timestamp() < earliest {
+                return Err(SealError::TooEarly {
+                    earliest,
+                    actual: block.timestamp(),
+                }
+                .into());
+            }
+        }
+
+        Self::vote(block)?;
+
+        Ok(())
+    }
+
+    // The in-turn signer seals as soon as the period is over; everyone else
+    // waits one more period per turn they are behind, so an offline signer
+    // is covered without everyone racing it.
+    fn generate_seal(&self, ancestors: &[Block], validators: &ValidatorSet, block: Block) -> Seal {
+        let parent = match ancestors.last() {
+            Some(parent) => parent,
+            None => return Seal::None,
+        };
+
+        let distance = match Self::distance(validators, block.beneficiary(), block.number()) {
+            Some(distance) => distance as u64,
+            None => return Seal::None,
+        };
+
+        if Self::recently_signed(validators, block.beneficiary()) {
+            return Seal::None;
+        }
+
+        let timestamp = block.timestamp().max(parent.timestamp() + self.period);
+
+        Seal::Ready {
+            block: block.with_timestamp(timestamp),
+            not_before: timestamp + distance * self.period,
+        }
+    }
+
+    fn genesis_validators(&self) -> ValidatorSet {
+        ValidatorSet::new(self.signers.to_owned())
+    }
+
+    fn next_validators(&self, validators: &ValidatorSet, block: &Block) -> ValidatorSet {
+        let mut next = validators.clone();
+
+        // Blocks only get here with a verified seal, but a tally must never
+        // count a signer that did not sign.
+        let signer = match block.signer() {
+            Some(signer) => signer,
+            None => return next,
+        };
+
+        if let Ok(Some(vote)) = Self::vote(block) {
+            next.cast(vote);
+        }
+
+        next.recent.push(signer);
+
+        let keep = next.len() / 2;
+        let excess = next.recent.len().saturating_sub(keep);
+        next.recent.drain(..excess);
+
+        next
+    }
+
+    fn propose(
+        &self,
+        validators: &ValidatorSet,
+        proposals: &BTreeMap<BigUint, bool>,
+        block: Block,
+    ) -> Block {
+        let proposal = proposals
+            .iter()
+            .find(|(candidate, authorize)| validators.is_meaningful(candidate, **authorize));
+
+        match proposal {
+            Some((candidate, authorize)) => Self::with_vote(block, candidate, *authorize),
+            None => block,
+        }
+    }
+
+    fn requires_signature(&self) -> bool {
+        true
+    }
+}
+
+// Development engine: any block is valid as far as the seal goes, and a new
+// one is sealed immediately whenever one is requested.
+pub struct InstantSealEngine;
+
+impl ConsensusEngine for InstantSealEngine {
+    fn window(&self) -> usize {
+        1
+    }
+
+    fn difficulty(
+        &self,
+        _ancestors: &[Block],
+        _validators: &ValidatorSet,
+        _beneficiary: &BigUint,
+        _timestamp: u64,
+    ) -> BigUint {
+        BigUint::one()
+    }
+
+    fn verify_seal(
+        &self,
+        _ancestors: &[Block],
+        _validators: &ValidatorSet,
+        _block: &Block,
+        _header_bytes: &[u8],
+        _target: &Target,
+    ) -> Result<(), ValidateBlockError> {
+        Ok(())
+    }
+
+    fn generate_seal(
+        &self,
+        _ancestors: &[Block],
+        _validators: &ValidatorSet,
+        block: Block,
+    ) -> Seal {
+        Seal::Ready {
+            block,
+            not_before: 0,
+        }
+    }
+
+    fn seals_on_demand(&self) -> bool {
+        true
+    }
+}
+
+// Tendermint-style BFT. Blocks are agreed on in rounds of proposal, prevote
+// and precommit by `bft::BftNode` and only enter the chain once more than two
+// thirds of the validators precommit them, which makes them final. A block's
+// `extra_nonce` is the round it was proposed in, and its beneficiary is the
+// proposer of that round, who signs it.
+pub struct BftEngine {
+    pub validators: Vec<BigUint>,
+}
+
+impl BftEngine {
+    // Proposers rotate by height and by round within a height.
+    pub fn proposer(validators: &ValidatorSet, height: u32, round: u32) -> Option<&BigUint> {
+        if validators.is_empty() {
+            return None;
+        }
+
+        let turn = (height as usize + round as usize) % validators.len();
+
+        validators.validators.get(turn)
+    }
+}
+
+impl ConsensusEngine for BftEngine {
+    fn window(&self) -> usize {
+        1
+    }
+
+    fn difficulty(
+        &self,
+        _ancestors: &[Block],
+        _validators: &ValidatorSet,
+        _beneficiary: &BigUint,
+        _timestamp: u64,
+    ) -> BigUint {
+        BigUint::one()
+    }
+
+    fn verify_seal(
+        &self,
+        _ancestors: &[Block],
+        validators: &ValidatorSet,
+        block: &Block,
+        _header_bytes: &[u8],
+        _target: &Target,
+    ) -> Result<(), ValidateBlockError> {
+        let expected = u32::try_from(block.extra_nonce())
+            .ok()
+            .and_then(|round| Self::proposer(validators, block.number(), round));
+
+        match expected {
+            Some(expected) if expected == block.beneficiary() => Ok(()),
+            Some(expected) => Err(SealError::WrongProposer {
+                expected: expected.to_owned(),
+                actual: block.beneficiary().to_owned(),
+            }
+            .into()),
+            None => Err(SealError::UnauthorizedSigner {
+                signer: block.beneficiary().to_owned(),
+            }
+            .into()),
+        }
+    }
+
+    // Proposing and committing happen in the BFT rounds, not in the miner.
+    fn generate_seal(
+        &self,
+        _ancestors: &[Block],
+        _validators: &ValidatorSet,
+        _block: Block,
+    ) -> Seal {
+        Seal::None
+    }
+
+    fn genesis_validators(&self) -> ValidatorSet {
+        ValidatorSet::new(self.validators.to_owned())
+    }
+
+    fn requires_signature(&self) -> bool {
+        true
+    }
+
+    fn finalizes(&self) -> bool {
+        true
+    }
+}
+
+// Proof of stake. Accounts bond coins to validators with staking transactions
+// in block bodies, and the proposer of each block is drawn by stake from a
+// seed every epoch mixes its first block into, so anyone can check the draw.
+// A block's `extra_nonce` is the attempt it was proposed at: if the proposer
+// of attempt 0 stays silent, attempt 1 opens a period later, and so on. The
+// proposer is the beneficiary and signs the block hash instead of searching
+// for a nonce. `extra_data` holds the body hash, so the signature covers the
+// body too. Evidence of a proposer signing two blocks for the same attempt
+// slashes everything bonded to them and jails them.
+//
+// Known limitation: the seed can be ground. The proposer of an epoch's first
+// block may try many bodies, timestamps and attempts before publishing, and
+// pick the block whose hash draws the proposers it likes for the rest of the
+// epoch. Mixing in the epoch's seal signatures would not stop this, since a
+// secp256k1 or ed25519 signer can produce as many valid signatures of one
+// message as it wants; an unbiasable seed needs unique signatures or VRF
+// outputs, which neither scheme gives.
+pub struct StakeEngine {
+    pub period: u64,
+    pub epoch_length: u32,
+    pub min_stake: u64,
+    pub slash_percent: u8,
+    pub stakes: Vec<(BigUint, u64)>,
+}
+
+impl StakeEngine {
+    pub const MAX_ATTEMPTS: u32 = 64;
+
+    fn attempt(block: &Block) -> Option<u32> {
+        u32::try_from(block.extra_nonce())
+            .ok()
+            .filter(|attempt| *attempt < Self::MAX_ATTEMPTS)
+    }
+
+    fn earliest(&self, parent: &Block, attempt: u32) -> u64 {
+        parent.timestamp() + self.period * (attempt as u64 + 1)
+    }
+
+    // Ledger after `block` and the balance changes the block makes: bonds
+    // are paid by their delegators, and unbonded coins are paid back when an
+    // epoch ends.
+    fn apply_body(
+        &self,
+        validators: &ValidatorSet,
+        block: &Block,
+    ) -> Result<(StakeLedger, Vec<BalanceChange>), BodyError> {
+        let mut ledger = validators.stake.clone();
+        let mut changes = Vec::new();
+
+        for (index, transaction) in block.body().staking.iter().enumerate() {
+            let (sender, amount) = ledger
+                .apply(transaction)
+                .map_err(|error| BodyError::Staking { index, error })?;
+
+            if amount > 0 {
+                changes.push(BalanceChange::Debit {
+                    address: sender,
+                    amount,
+                });
+            }
+        }
+
+        for (index, evidence) in block.body().evidence.iter().enumerate() {
+            ledger
+                .slash(evidence, self.slash_percent)
+                .map_err(|error| BodyError::Evidence { index, error })?;
+        }
+
+        if block.number().is_multiple_of(self.epoch_length) {
+            let hash = block.hash().unwrap_or_default();
+
+            for bond in ledger.begin_epoch(&hash, self.min_stake) {
+                changes.push(BalanceChange::Credit {
+                    address: bond.delegator,
+                    amount: bond.amount,
+                });
+            }
+        }
+
+        Ok((ledger, changes))
+    }
+}
+
+impl ConsensusEngine for StakeEngine {
+    fn window(&self) -> usize {
+        1
+    }
+
+    fn difficulty(
+        &self,
+        _ancestors: &[Block],
+        _validators: &ValidatorSet,
+        _beneficiary: &BigUint,
+        _timestamp: u64,
+    ) -> BigUint {
+        BigUint::one()
+    }
+
+    fn verify_seal(
+        &self,
+        ancestors: &[Block],
+        validators: &ValidatorSet,
+        block: &Block,
+        _header_bytes: &[u8],
+        _target: &Target,
+    ) -> Result<(), ValidateBlockError> {
+        let attempt = Self::attempt(block);
+        let expected =
+            attempt.and_then(|attempt| validators.stake.proposer(block.number(), attempt));
+
+        match expected {
+            Some(expected) if expected == block.beneficiary() => {}
+            Some(expected) => {
+                return Err(SealError::WrongProposer {
+                    expected: expected.to_owned(),
+                    actual: block.beneficiary().to_owned(),
+                }
+                .into())
+            }
+            None => {
+                return Err(SealError::UnauthorizedSigner {
+                    signer: block.beneficiary().to_owned(),
+                }
+                .into())
+            }
+        }
+
+        let expected = block
+            .body()
+            .hash()
+            .map_err(|error| BodyError::Encoding { error })?;
+        let extra_data = block.headers().extra_data();
+        let actual = (extra_data.len() == 32).then(|| BigUint::from_bytes_be(extra_data));
+
+        if actual.as_ref() != Some(&expected) {
+            return Err(BodyError::InvalidBodyHash { expected, actual }.into());
+        }
+
+        if let Some((parent, attempt)) = ancestors.last().zip(attempt) {
+            let earliest = self.earliest(parent, attempt);
+
+            if block.timestamp() < earliest {
+                return Err(SealError::TooEarly {
+                    earliest,
+                    actual: block.timestamp(),
+                }
+                .into());
+            }
+        }
+
+        Ok(())
+    }
+
+    fn verify_body(
+        &self,
+        validators: &ValidatorSet,
+        block: &Block,
+    ) -> Result<(), ValidateBlockError> {
+        self.apply_body(validators, block)?;
+
+        Ok(())
+    }
+
+    fn balance_changes(&self, validators: &ValidatorSet, block: &Block) -> Vec<BalanceChange> {
+        self.apply_body(validators, block)
+            .map(|(_, changes)| changes)
+            .unwrap_or_default()
+    }
+
+    // Takes the first attempt this node is drawn for, as soon as it opens.
+    // The signature comes last, once the headers are final.
+    fn generate_seal(&self, ancestors: &[Block], validators: &ValidatorSet, block: Block) -> Seal {
+        let parent = match ancestors.last() {
+            Some(parent) => parent,
+            None => return Seal::None,
+        };
+
+        let attempt = (0..Self::MAX_ATTEMPTS).find(|attempt| {
+            validators.stake.proposer(block.number(), *attempt) == Some(block.beneficiary())
+        });
+        let attempt = match attempt {
+            Some(attempt) => attempt,
+            None => return Seal::None,
+        };
+
+        let body_hash = match block.body().hash().ok().map(|hash| encode_u256(&hash)) {
+            Some(Ok(body_hash)) => body_hash,
+            _ => return Seal::None,
+        };
+
+        let timestamp = block.timestamp().max(self.earliest(parent, attempt));
+
+        Seal::Ready {
+            block: block
+                .with_timestamp(timestamp)
+                .with_extra_nonce(attempt as u64)
+                .with_extra_data(body_hash.to_vec()),
+            not_before: timestamp,
+        }
+    }
+
+    // Genesis stakes are active from block 1, drawn from a zero seed.
+    fn genesis_validators(&self) -> ValidatorSet {
+        let stake = StakeLedger::genesis(&self.stakes, self.min_stake, BigUint::default());
+
+        ValidatorSet {
+            validators: stake.validators(),
+            stake,
+            ..ValidatorSet::default()
+        }
+    }
+
+    fn next_validators(&self, validators: &ValidatorSet, block: &Block) -> ValidatorSet {
+        match self.apply_body(validators, block) {
+            Ok((stake, _)) => ValidatorSet {
+                validators: stake.validators(),
+                stake,
+                ..validators.clone()
+            },
+            Err(_) => validators.clone(),
+        }
+    }
+
+    fn requires_signature(&self) -> bool {
+        true
+    }
+
+    // Earlier attempts weigh more, so forks settle on the first proposer
+    // that showed up.
+    fn weight(&self, block: &Block) -> BigUint {
+        let attempt = Self::attempt(block).unwrap_or(Self::MAX_ATTEMPTS - 1);
+
+        BigUint::from(Self::MAX_ATTEMPTS - attempt)
+    }
+}
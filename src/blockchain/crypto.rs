@@ -0,0 +1,125 @@
+use k256::ecdsa::{RecoveryId, Signature, SigningKey, VerifyingKey};
+use num_bigint::BigUint;
+use num_traits::One;
+
+use super::encoding::encode_u256;
+use crate::helpers::keccak256_bytes;
+
+// 64 byte secp256k1 signature followed by the recovery id.
+pub const SIGNATURE_LENGTH: usize = 65;
+
+pub const ED25519_PUBLIC_KEY_LENGTH: usize = 32;
+
+pub const ED25519_SIGNATURE_LENGTH: usize = 64;
+
+const ADDRESS_BITS: usize = 160;
+
+// secp256k1 key making recoverable signatures over 32 byte hashes. As in
+// Ethereum, the address of a key is the last 20 bytes of the keccak256 hash
+// of its uncompressed public key, so signatures prove who owns an address.
+#[derive(Clone)]
+pub struct Signer {
+    key: SigningKey,
+}
+
+impl Signer {
+    pub fn from_bytes(secret: &[u8]) -> Option<Signer> {
+        SigningKey::from_slice(secret)
+            .ok()
+            .map(|key| Signer { key })
+    }
+
+    pub fn from_hex(text: &str) -> Option<Signer> {
+        Signer::from_bytes(&hex::decode(text.strip_prefix("0x").unwrap_or(text)).ok()?)
+    }
+
+    pub fn address(&self) -> BigUint {
+        address_of(self.key.verifying_key())
+    }
+
+    pub fn sign(&self, hash: &BigUint) -> Option<Vec<u8>> {
+        let (signature, recovery_id) = self
+            .key
+            .sign_prehash_recoverable(&encode_u256(hash).ok()?)
+            .ok()?;
+
+        let mut bytes = signature.to_bytes().to_vec();
+        bytes.push(recovery_id.to_byte());
+
+        Some(bytes)
+    }
+}
+
+// Address whose key signed `hash`, if `signature` is well formed.
+pub fn recover(hash: &BigUint, signature: &[u8]) -> Option<BigUint> {
+    if signature.len() != SIGNATURE_LENGTH {
+        return None;
+    }
+
+    let recovery_id = RecoveryId::from_byte(signature[SIGNATURE_LENGTH - 1])?;
+    let signature = Signature::from_slice(&signature[..SIGNATURE_LENGTH - 1]).ok()?;
+    let key = VerifyingKey::recover_from_prehash(&encode_u256(hash).ok()?, &signature, recovery_id)
+        .ok()?;
+
+    Some(address_of(&key))
+}
+
+fn address_of(key: &VerifyingKey) -> BigUint {
+    let point = key.to_encoded_point(false);
+
+    address_from_public_key(&point.as_bytes()[1..])
+}
+
+fn address_from_public_key(public_key: &[u8]) -> BigUint {
+    keccak256_bytes(public_key) % (BigUint::one() << ADDRESS_BITS)
+}
+
+// Address code deployed by `sender` with its transaction of `nonce` lives at.
+pub fn contract_address(sender: &BigUint, nonce: u64) -> Option<BigUint> {
+    let mut bytes = encode_u256(sender).ok()?.to_vec();
+    bytes.extend_from_slice(&nonce.to_be_bytes());
+
+    Some(address_from_public_key(&bytes))
+}
+
+// Ed25519 key signing 32 byte hashes. Ed25519 signatures cannot be recovered
+// from, so the public key travels with them; the address is derived from it
+// the same way as for secp256k1.
+pub struct Ed25519Signer {
+    key: ed25519_dalek::SigningKey,
+}
+
+impl Ed25519Signer {
+    pub fn from_bytes(secret: &[u8]) -> Option<Ed25519Signer> {
+        let secret = secret.try_into().ok()?;
+
+        Some(Ed25519Signer {
+            key: ed25519_dalek::SigningKey::from_bytes(secret),
+        })
+    }
+
+    pub fn public_key(&self) -> [u8; ED25519_PUBLIC_KEY_LENGTH] {
+        self.key.verifying_key().to_bytes()
+    }
+
+    pub fn address(&self) -> BigUint {
+        address_from_public_key(&self.public_key())
+    }
+
+    pub fn sign(&self, hash: &BigUint) -> Option<[u8; ED25519_SIGNATURE_LENGTH]> {
+        use ed25519_dalek::Signer;
+
+        Some(self.key.sign(&encode_u256(hash).ok()?).to_bytes())
+    }
+}
+
+// Address of `public_key`, if it made `signature` over `hash`.
+pub fn verify_ed25519(hash: &BigUint, public_key: &[u8], signature: &[u8]) -> Option<BigUint> {
+    let key = ed25519_dalek::VerifyingKey::from_bytes(public_key.try_into().ok()?).ok()?;
+    let signature = ed25519_dalek::Signature::from_slice(signature).ok()?;
+
+    key.verify_strict(&encode_u256(hash).ok()?, &signature)
+        .ok()?;
+
+    Some(address_from_public_key(public_key))
+}
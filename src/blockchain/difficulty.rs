@@ -0,0 +1,202 @@
+use num_bigint::BigUint;
+use num_traits::One;
+use serde_derive::{Deserialize, Serialize};
+
+use super::block::Block;
+
+pub trait DifficultyAlgorithm: Send + Sync {
+    // Number of most recent blocks, ending with the parent, the algorithm looks at.
+    fn window(&self) -> usize;
+
+    // `ancestors` is ordered oldest first and ends with the parent block. It may
+    // hold more than `window` blocks, and fewer only near genesis.
+    fn next_difficulty(&self, ancestors: &[Block], timestamp: u64) -> BigUint;
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "algorithm", rename_all = "snake_case")]
+pub enum DifficultySpec {
+    Legacy {
+        target_block_time: u64,
+    },
+    Retarget {
+        target_block_time: u64,
+        epoch_length: u32,
+    },
+    Lwma {
+        target_block_time: u64,
+        window: usize,
+    },
+    Homestead {
+        target_block_time: u64,
+    },
+}
+
+impl Default for DifficultySpec {
+    fn default() -> Self {
+        DifficultySpec::Legacy {
+            target_block_time: 2,
+        }
+    }
+}
+
+impl DifficultySpec {
+    pub fn algorithm(&self) -> Box<dyn DifficultyAlgorithm> {
+        match *self {
+            DifficultySpec::Legacy { target_block_time } => {
+                Box::new(LegacyDifficulty { target_block_time })
+            }
+            DifficultySpec::Retarget {
+                target_block_time,
+                epoch_length,
+            } => Box::new(RetargetDifficulty {
+                target_block_time,
+                epoch_length: epoch_length.max(1),
+            }),
+            DifficultySpec::Lwma {
+                target_block_time,
+                window,
+            } => Box::new(LwmaDifficulty {
+                target_block_time,
+                window: window.max(1),
+            }),
+            DifficultySpec::Homestead { target_block_time } => {
+                Box::new(HomesteadDifficulty { target_block_time })
+            }
+        }
+    }
+}
+
+fn tail(ancestors: &[Block], count: usize) -> &[Block] {
+    &ancestors[ancestors.len().saturating_sub(count)..]
+}
+
+// +1 when the block arrives within the target time of its parent, -1 otherwise.
+pub struct LegacyDifficulty {
+    pub target_block_time: u64,
+}
+
+impl DifficultyAlgorithm for LegacyDifficulty {
+    fn window(&self) -> usize {
+        1
+    }
+
+    fn next_difficulty(&self, ancestors: &[Block], timestamp: u64) -> BigUint {
+        let last_block = ancestors.last().unwrap();
+        let difficulty = last_block.difficulty();
+
+        if timestamp.saturating_sub(last_block.timestamp()) > self.target_block_time {
+            if difficulty <= BigUint::one() {
+                BigUint::one()
+            } else {
+                difficulty - 1u32
+            }
+        } else {
+            difficulty + 1u32
+        }
+    }
+}
+
+// Bitcoin-style: keep the difficulty for `epoch_length` blocks, then scale it
+// by how far the epoch missed its expected duration, by at most 4x either way.
+// The epoch is timed from its first block's parent, so that it spans exactly
+// `epoch_length` solve times.
+pub struct RetargetDifficulty {
+    pub target_block_time: u64,
+    pub epoch_length: u32,
+}
+
+impl DifficultyAlgorithm for RetargetDifficulty {
+    fn window(&self) -> usize {
+        self.epoch_length as usize + 1
+    }
+
+    fn next_difficulty(&self, ancestors: &[Block], _timestamp: u64) -> BigUint {
+        let ancestors = tail(ancestors, self.window());
+        let last_block = ancestors.last().unwrap();
+
+        if !(last_block.number() + 1).is_multiple_of(self.epoch_length)
+            || ancestors.len() < self.window()
+        {
+            return last_block.difficulty();
+        }
+
+        let expected = self.target_block_time * self.epoch_length as u64;
+        let actual = last_block
+            .timestamp()
+            .saturating_sub(ancestors[0].timestamp())
+            .clamp(expected / 4, expected * 4)
+            .max(1);
+
+        (last_block.difficulty() * expected / actual).max(BigUint::one())
+    }
+}
+
+// Linearly weighted moving average of solve times over the last `window`
+// blocks, so recent blocks count more than old ones.
+pub struct LwmaDifficulty {
+    pub target_block_time: u64,
+    pub window: usize,
+}
+
+impl DifficultyAlgorithm for LwmaDifficulty {
+    fn window(&self) -> usize {
+        self.window + 1
+    }
+
+    fn next_difficulty(&self, ancestors: &[Block], _timestamp: u64) -> BigUint {
+        let ancestors = tail(ancestors, self.window());
+        let last_block = ancestors.last().unwrap();
+
+        if ancestors.len() < self.window() {
+            return last_block.difficulty();
+        }
+
+        let target = self.target_block_time.max(1) as i64;
+        let mut weighted_solve_times: i64 = 0;
+        let mut difficulty_sum = BigUint::default();
+
+        for (weight, pair) in ancestors.windows(2).enumerate() {
+            let solve_time = pair[1].timestamp() as i64 - pair[0].timestamp() as i64;
+
+            weighted_solve_times += (weight as i64 + 1) * solve_time.clamp(1, 6 * target);
+            difficulty_sum += pair[1].difficulty();
+        }
+
+        let n = self.window as u64;
+        let next =
+            difficulty_sum * target as u64 * (n + 1) / (2 * weighted_solve_times.max(1) as u64);
+
+        next.max(BigUint::one())
+    }
+}
+
+// Ethereum Homestead: parent + parent / 2048 * max(1 - elapsed / target, -99),
+// without the difficulty bomb.
+pub struct HomesteadDifficulty {
+    pub target_block_time: u64,
+}
+
+impl DifficultyAlgorithm for HomesteadDifficulty {
+    fn window(&self) -> usize {
+        1
+    }
+
+    fn next_difficulty(&self, ancestors: &[Block], timestamp: u64) -> BigUint {
+        let last_block = ancestors.last().unwrap();
+
+        let elapsed = timestamp.saturating_sub(last_block.timestamp());
+        let factor = (1 - (elapsed / self.target_block_time.max(1)) as i64).max(-99);
+
+        let parent = last_block.difficulty();
+        let step = (&parent / 2048u32).max(BigUint::one()) * factor.unsigned_abs();
+
+        if factor >= 0 {
+            parent + step
+        } else if parent > step {
+            parent - step
+        } else {
+            BigUint::one()
+        }
+    }
+}
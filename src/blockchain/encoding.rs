@@ -0,0 +1,221 @@
+//! Canonical binary encoding used for hashing, proof of work and networking.
+//!
+//! Integers are big-endian and fixed width; 256-bit values are left-padded
+//! with zeros to 32 bytes. Byte strings are prefixed with a single length
+//! byte. Fields are written in declaration order with no separators.
+//!
+//! `BlockHeaders` (153 + n bytes):
+//!
+//! | offset | size | field               |
+//! |--------|------|---------------------|
+//! | 0      | 4    | `number`            |
+//! | 4      | 4    | `bits`              |
+//! | 8      | 8    | `timestamp`         |
+//! | 16     | 8    | `extra_nonce`       |
+//! | 24     | 32   | `parent_hash`       |
+//! | 56     | 32   | `beneficiary`       |
+//! | 88     | 32   | `transactions_root` |
+//! | 120    | 32   | `state_root`        |
+//! | 152    | 1    | `extra_data` length n (at most 32) |
+//! | 153    | n    | `extra_data`        |
+//!
+//! `Block` is the encoded headers, the 32 byte nonce, the producer's
+//! signature over the block hash as a byte string of at most 65 bytes (empty
+//! when unsigned), and the body: transactions, staking transactions,
+//! double-sign evidence and UTXO transactions, each list preceded by a 2
+//! byte count.
+//!
+//! A transaction is its 8 byte chain id and nonce, the 32 byte recipient, the
+//! 8 byte value and fee, its data with a 2 byte length, and a scheme byte: 0
+//! for unsigned, 1 followed by a secp256k1 signature byte string, or 2
+//! followed by an ed25519 public key and signature, both byte strings.
+//!
+//! A staking transaction is its 8 byte chain id, its action byte (0 bonds, 1
+//! unbonds), the 32 byte validator, the 8 byte amount and nonce, and its
+//! signature. Double-sign evidence is two encoded headers, each followed by
+//! its signature.
+//!
+//! A UTXO transaction is its 8 byte chain id, its inputs as a 2 byte count of
+//! 32 byte transaction ids each followed by a 2 byte output index, its outputs
+//! as a 2 byte count of 32 byte owners each followed by an 8 byte value, and
+//! then the signature of every input in the same form as a transaction's.
+//!
+//! `transactions_root` is the Merkle root (see `merkle`) of the body's
+//! entries in the order above, each leaf being a byte naming its list (0 for
+//! transactions, 1 staking, 2 evidence, 3 UTXO) followed by the entry.
+//!
+//! `state_root` is the sparse Merkle root (see `sparse_merkle`) of the ledger
+//! once the block is applied. On the account ledger each account is keyed by
+//! keccak256 of its 32 byte address, and its value is keccak256 of its 8 byte
+//! balance and nonce and the 32 byte keccak256 of its code. On the UTXO ledger
+//! each unspent output is keyed by keccak256 of its 32 byte transaction id and
+//! 2 byte index, and its value is keccak256 of its 32 byte owner and 8 byte
+//! value.
+//!
+//! Test vectors (headers with `number = 1`, `bits = 2`,
+//! `timestamp = 3`, `extra_nonce = 7`, `parent_hash = 4`, `beneficiary = 5`,
+//! an empty body and so `transactions_root = 0`, `state_root = 0`, empty
+//! `extra_data`, nonce `6`):
+//!
+//! ```text
+//! headers                     = 00000001 00000002 0000000000000003 0000000000000007
+//!                               00..04 (32 bytes) 00..05 (32 bytes) 00..00 (32 bytes)
+//!                               00..00 (32 bytes) 00
+//! nonce                       = 00..06 (32 bytes)
+//! keccak256(headers)          = c6f82f98d8300697028fdfd7898255c510f758e9c2a213736891bf6a7249caff
+//! keccak256(headers || nonce) = 8a2ed7321951fd21d0e4428e244716b4cacdb7532c34fc2e6722880ad6fad46b
+//! ```
+
+use std::{error::Error, fmt};
+
+use num_bigint::BigUint;
+use serde_derive::Serialize;
+
+pub const U256_LENGTH: usize = 32;
+
+pub const MAX_BYTES_LENGTH: usize = 32;
+
+pub const MAX_SIGNATURE_LENGTH: usize = 65;
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum EncodingError {
+    ValueTooLarge,
+    UnexpectedEnd,
+    TrailingBytes,
+}
+
+impl fmt::Display for EncodingError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EncodingError::ValueTooLarge => write!(f, "value does not fit its encoded width"),
+            EncodingError::UnexpectedEnd => write!(f, "unexpected end of input"),
+            EncodingError::TrailingBytes => write!(f, "trailing bytes after input"),
+        }
+    }
+}
+
+impl Error for EncodingError {}
+
+pub fn encode_u256(value: &BigUint) -> Result<[u8; U256_LENGTH], EncodingError> {
+    let bytes = value.to_bytes_be();
+
+    if bytes.len() > U256_LENGTH {
+        return Err(EncodingError::ValueTooLarge);
+    }
+
+    let mut output = [0u8; U256_LENGTH];
+    output[U256_LENGTH - bytes.len()..].copy_from_slice(&bytes);
+
+    Ok(output)
+}
+
+pub fn encode_bytes(value: &[u8]) -> Result<Vec<u8>, EncodingError> {
+    encode_bytes_up_to(value, MAX_BYTES_LENGTH)
+}
+
+pub fn encode_signature(value: &[u8]) -> Result<Vec<u8>, EncodingError> {
+    encode_bytes_up_to(value, MAX_SIGNATURE_LENGTH)
+}
+
+fn encode_bytes_up_to(value: &[u8], max: usize) -> Result<Vec<u8>, EncodingError> {
+    if value.len() > max {
+        return Err(EncodingError::ValueTooLarge);
+    }
+
+    let mut output = Vec::with_capacity(value.len() + 1);
+    output.push(value.len() as u8);
+    output.extend_from_slice(value);
+
+    Ok(output)
+}
+
+// Longer byte strings, such as transaction data, take a 2 byte length.
+pub fn encode_data(value: &[u8]) -> Result<Vec<u8>, EncodingError> {
+    let mut output = encode_count(value.len())?.to_vec();
+    output.extend_from_slice(value);
+
+    Ok(output)
+}
+
+pub fn encode_count(count: usize) -> Result<[u8; 2], EncodingError> {
+    u16::try_from(count)
+        .map(u16::to_be_bytes)
+        .map_err(|_| EncodingError::ValueTooLarge)
+}
+
+pub struct Decoder<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Decoder<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Decoder { bytes, offset: 0 }
+    }
+
+    fn read(&mut self, length: usize) -> Result<&'a [u8], EncodingError> {
+        let end = self.offset + length;
+
+        if end > self.bytes.len() {
+            return Err(EncodingError::UnexpectedEnd);
+        }
+
+        let slice = &self.bytes[self.offset..end];
+        self.offset = end;
+
+        Ok(slice)
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8, EncodingError> {
+        Ok(self.read(1)?[0])
+    }
+
+    pub fn read_u16(&mut self) -> Result<u16, EncodingError> {
+        Ok(u16::from_be_bytes(self.read(2)?.try_into().unwrap()))
+    }
+
+    pub fn read_u32(&mut self) -> Result<u32, EncodingError> {
+        Ok(u32::from_be_bytes(self.read(4)?.try_into().unwrap()))
+    }
+
+    pub fn read_u64(&mut self) -> Result<u64, EncodingError> {
+        Ok(u64::from_be_bytes(self.read(8)?.try_into().unwrap()))
+    }
+
+    pub fn read_u256(&mut self) -> Result<BigUint, EncodingError> {
+        Ok(BigUint::from_bytes_be(self.read(U256_LENGTH)?))
+    }
+
+    pub fn read_bytes(&mut self) -> Result<Vec<u8>, EncodingError> {
+        self.read_bytes_up_to(MAX_BYTES_LENGTH)
+    }
+
+    pub fn read_data(&mut self) -> Result<Vec<u8>, EncodingError> {
+        let length = self.read_u16()? as usize;
+
+        Ok(self.read(length)?.to_vec())
+    }
+
+    pub fn read_signature(&mut self) -> Result<Vec<u8>, EncodingError> {
+        self.read_bytes_up_to(MAX_SIGNATURE_LENGTH)
+    }
+
+    fn read_bytes_up_to(&mut self, max: usize) -> Result<Vec<u8>, EncodingError> {
+        let length = self.read_u8()? as usize;
+
+        if length > max {
+            return Err(EncodingError::ValueTooLarge);
+        }
+
+        Ok(self.read(length)?.to_vec())
+    }
+
+    pub fn finish(self) -> Result<(), EncodingError> {
+        if self.offset != self.bytes.len() {
+            return Err(EncodingError::TrailingBytes);
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,609 @@
+use std::{error::Error, fmt};
+
+use num_bigint::BigUint;
+use serde_derive::Serialize;
+
+use super::encoding::EncodingError;
+use super::serde_hex;
+use super::utxo::OutPoint;
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "kind", content = "error", rename_all = "snake_case")]
+pub enum ValidateBlockError {
+    Header(HeaderError),
+    ProofOfWork(ProofOfWorkError),
+    Seal(SealError),
+    Linkage(LinkageError),
+    Timestamp(TimestampError),
+    Size(SizeError),
+    Body(BodyError),
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum HeaderError {
+    InvalidNumber {
+        expected: u32,
+        actual: u32,
+    },
+    InvalidBits {
+        expected: u32,
+        actual: u32,
+    },
+    InvalidGenesis {
+        #[serde(with = "serde_hex::option_biguint")]
+        expected: Option<BigUint>,
+        #[serde(with = "serde_hex::option_biguint")]
+        actual: Option<BigUint>,
+    },
+    Encoding {
+        error: EncodingError,
+    },
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum ProofOfWorkError {
+    AboveTarget {
+        #[serde(with = "serde_hex::biguint")]
+        target: BigUint,
+        #[serde(with = "serde_hex::biguint")]
+        actual: BigUint,
+    },
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum SealError {
+    UnauthorizedSigner {
+        #[serde(with = "serde_hex::biguint")]
+        signer: BigUint,
+    },
+    RecentlySigned {
+        #[serde(with = "serde_hex::biguint")]
+        signer: BigUint,
+    },
+    TooEarly {
+        earliest: u64,
+        actual: u64,
+    },
+    InvalidVote,
+    WrongProposer {
+        #[serde(with = "serde_hex::biguint")]
+        expected: BigUint,
+        #[serde(with = "serde_hex::biguint")]
+        actual: BigUint,
+    },
+    MissingCommit,
+    MissingSignature,
+    InvalidSignature {
+        #[serde(with = "serde_hex::biguint")]
+        beneficiary: BigUint,
+        #[serde(with = "serde_hex::option_biguint")]
+        signer: Option<BigUint>,
+    },
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum LinkageError {
+    InvalidParentHash {
+        #[serde(with = "serde_hex::biguint")]
+        expected: BigUint,
+        #[serde(with = "serde_hex::biguint")]
+        actual: BigUint,
+    },
+    UnknownParent {
+        #[serde(with = "serde_hex::biguint")]
+        parent_hash: BigUint,
+    },
+    DuplicateBlock {
+        #[serde(with = "serde_hex::biguint")]
+        hash: BigUint,
+    },
+    NotExtendingHead {
+        #[serde(with = "serde_hex::biguint")]
+        head: BigUint,
+        #[serde(with = "serde_hex::biguint")]
+        parent_hash: BigUint,
+    },
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum TimestampError {
+    NotAfterMedian { median: u64, actual: u64 },
+    TooFarInFuture { max: u64, actual: u64 },
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum SizeError {
+    BlockTooLarge { max: usize, actual: usize },
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum BodyError {
+    Encoding {
+        error: EncodingError,
+    },
+    Unsupported,
+    WrongLedger,
+    InvalidTransactionsRoot {
+        #[serde(with = "serde_hex::biguint")]
+        expected: BigUint,
+        #[serde(with = "serde_hex::biguint")]
+        actual: BigUint,
+    },
+    InvalidStateRoot {
+        #[serde(with = "serde_hex::biguint")]
+        expected: BigUint,
+        #[serde(with = "serde_hex::biguint")]
+        actual: BigUint,
+    },
+    InvalidBodyHash {
+        #[serde(with = "serde_hex::biguint")]
+        expected: BigUint,
+        #[serde(with = "serde_hex::option_biguint")]
+        actual: Option<BigUint>,
+    },
+    Transaction {
+        index: usize,
+        error: TransactionError,
+    },
+    Utxo {
+        index: usize,
+        error: UtxoError,
+    },
+    Staking {
+        index: usize,
+        error: StakingError,
+    },
+    Evidence {
+        index: usize,
+        error: EvidenceError,
+    },
+    InsufficientBalance {
+        #[serde(with = "serde_hex::biguint")]
+        address: BigUint,
+        balance: u64,
+        required: u64,
+    },
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum TransactionError {
+    InvalidSignature,
+    WrongChain {
+        expected: u64,
+        actual: u64,
+    },
+    InvalidNonce {
+        expected: u64,
+        actual: u64,
+    },
+    InsufficientBalance {
+        balance: u64,
+        required: u64,
+    },
+    CodeExists {
+        #[serde(with = "serde_hex::biguint")]
+        address: BigUint,
+    },
+    Overflow,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum UtxoError {
+    WrongChain { expected: u64, actual: u64 },
+    NoInputs,
+    InvalidSignature { input: usize },
+    // Never created, or spent by an earlier block.
+    UnknownInput { outpoint: OutPoint },
+    // Spent by an earlier input of the same block.
+    DoubleSpend { outpoint: OutPoint },
+    InsufficientInput { available: u64, required: u64 },
+    Overflow,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum StakingError {
+    InvalidSignature,
+    WrongChain {
+        expected: u64,
+        actual: u64,
+    },
+    InvalidNonce {
+        expected: u64,
+        actual: u64,
+    },
+    ZeroAmount,
+    Jailed {
+        #[serde(with = "serde_hex::biguint")]
+        validator: BigUint,
+    },
+    InsufficientBond {
+        bonded: u64,
+        requested: u64,
+    },
+    Overflow,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum EvidenceError {
+    NotDoubleSign,
+    AlreadyJailed {
+        #[serde(with = "serde_hex::biguint")]
+        offender: BigUint,
+    },
+    NotStaked {
+        #[serde(with = "serde_hex::biguint")]
+        offender: BigUint,
+    },
+}
+
+impl fmt::Display for ValidateBlockError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ValidateBlockError::Header(error) => write!(f, "invalid header: {}", error),
+            ValidateBlockError::ProofOfWork(error) => write!(f, "invalid proof of work: {}", error),
+            ValidateBlockError::Seal(error) => write!(f, "invalid seal: {}", error),
+            ValidateBlockError::Linkage(error) => write!(f, "invalid linkage: {}", error),
+            ValidateBlockError::Timestamp(error) => write!(f, "invalid timestamp: {}", error),
+            ValidateBlockError::Size(error) => write!(f, "invalid size: {}", error),
+            ValidateBlockError::Body(error) => write!(f, "invalid body: {}", error),
+        }
+    }
+}
+
+impl fmt::Display for HeaderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HeaderError::InvalidNumber { expected, actual } => {
+                write!(f, "expected block number {}, got {}", expected, actual)
+            }
+            HeaderError::InvalidBits { expected, actual } => {
+                write!(f, "expected bits 0x{:08x}, got 0x{:08x}", expected, actual)
+            }
+            HeaderError::InvalidGenesis { expected, actual } => write!(
+                f,
+                "expected genesis {}, got {}",
+                format_hash(expected.as_ref()),
+                format_hash(actual.as_ref())
+            ),
+            HeaderError::Encoding { error } => write!(f, "{}", error),
+        }
+    }
+}
+
+impl fmt::Display for ProofOfWorkError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ProofOfWorkError::AboveTarget { target, actual } => {
+                write!(
+                    f,
+                    "hash 0x{:064x} is above target 0x{:064x}",
+                    actual, target
+                )
+            }
+        }
+    }
+}
+
+impl fmt::Display for SealError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SealError::UnauthorizedSigner { signer } => {
+                write!(f, "0x{:x} is not an authorized signer", signer)
+            }
+            SealError::RecentlySigned { signer } => {
+                write!(f, "0x{:x} signed one of the recent blocks", signer)
+            }
+            SealError::TooEarly { earliest, actual } => write!(
+                f,
+                "timestamp {} is before the end of the period at {}",
+                actual, earliest
+            ),
+            SealError::InvalidVote => write!(f, "extra data holds a candidate but no vote"),
+            SealError::WrongProposer { expected, actual } => {
+                write!(f, "proposed by 0x{:x} instead of 0x{:x}", actual, expected)
+            }
+            SealError::MissingCommit => {
+                write!(f, "final blocks are only accepted with a commit")
+            }
+            SealError::MissingSignature => write!(f, "block is not signed by its producer"),
+            SealError::InvalidSignature {
+                beneficiary,
+                signer: Some(signer),
+            } => write!(
+                f,
+                "signed by 0x{:x} instead of the beneficiary 0x{:x}",
+                signer, beneficiary
+            ),
+            SealError::InvalidSignature {
+                beneficiary,
+                signer: None,
+            } => write!(
+                f,
+                "signature does not recover an address for the beneficiary 0x{:x}",
+                beneficiary
+            ),
+        }
+    }
+}
+
+impl fmt::Display for LinkageError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LinkageError::InvalidParentHash { expected, actual } => write!(
+                f,
+                "expected parent 0x{:064x}, got 0x{:064x}",
+                expected, actual
+            ),
+            LinkageError::UnknownParent { parent_hash } => {
+                write!(f, "unknown parent 0x{:064x}", parent_hash)
+            }
+            LinkageError::DuplicateBlock { hash } => {
+                write!(f, "block 0x{:064x} is already known", hash)
+            }
+            LinkageError::NotExtendingHead { head, parent_hash } => write!(
+                f,
+                "parent 0x{:064x} is not the final head 0x{:064x}",
+                parent_hash, head
+            ),
+        }
+    }
+}
+
+impl fmt::Display for TimestampError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TimestampError::NotAfterMedian { median, actual } => write!(
+                f,
+                "timestamp {} is not after median time past {}",
+                actual, median
+            ),
+            TimestampError::TooFarInFuture { max, actual } => {
+                write!(f, "timestamp {} is after the allowed {}", actual, max)
+            }
+        }
+    }
+}
+
+impl fmt::Display for SizeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SizeError::BlockTooLarge { max, actual } => {
+                write!(f, "block is {} bytes, at most {} allowed", actual, max)
+            }
+        }
+    }
+}
+
+impl fmt::Display for BodyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BodyError::Encoding { error } => write!(f, "{}", error),
+            BodyError::Unsupported => {
+                write!(
+                    f,
+                    "the consensus engine takes no staking transactions or evidence"
+                )
+            }
+            BodyError::WrongLedger => {
+                write!(f, "transactions do not fit the chain's ledger model")
+            }
+            BodyError::InvalidTransactionsRoot { expected, actual } => write!(
+                f,
+                "expected transactions root 0x{:064x}, got 0x{:064x}",
+                expected, actual
+            ),
+            BodyError::InvalidStateRoot { expected, actual } => write!(
+                f,
+                "expected state root 0x{:064x}, got 0x{:064x}",
+                expected, actual
+            ),
+            BodyError::InvalidBodyHash { expected, actual } => write!(
+                f,
+                "expected body hash 0x{:064x} in extra data, got {}",
+                expected,
+                format_hash(actual.as_ref())
+            ),
+            BodyError::Transaction { index, error } => {
+                write!(f, "transaction {}: {}", index, error)
+            }
+            BodyError::Utxo { index, error } => {
+                write!(f, "utxo transaction {}: {}", index, error)
+            }
+            BodyError::Staking { index, error } => {
+                write!(f, "staking transaction {}: {}", index, error)
+            }
+            BodyError::Evidence { index, error } => write!(f, "evidence {}: {}", index, error),
+            BodyError::InsufficientBalance {
+                address,
+                balance,
+                required,
+            } => write!(
+                f,
+                "0x{:x} has a balance of {} but needs {}",
+                address, balance, required
+            ),
+        }
+    }
+}
+
+impl fmt::Display for TransactionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TransactionError::InvalidSignature => write!(f, "signature does not verify"),
+            TransactionError::WrongChain { expected, actual } => {
+                write!(f, "signed for chain {} instead of {}", actual, expected)
+            }
+            TransactionError::InvalidNonce { expected, actual } => {
+                write!(f, "expected nonce {}, got {}", expected, actual)
+            }
+            TransactionError::InsufficientBalance { balance, required } => write!(
+                f,
+                "sender has a balance of {} but needs {}",
+                balance, required
+            ),
+            TransactionError::CodeExists { address } => {
+                write!(f, "0x{:x} already holds code", address)
+            }
+            TransactionError::Overflow => write!(f, "a balance would overflow"),
+        }
+    }
+}
+
+impl fmt::Display for UtxoError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            UtxoError::WrongChain { expected, actual } => {
+                write!(f, "signed for chain {} instead of {}", actual, expected)
+            }
+            UtxoError::NoInputs => write!(f, "spends no outputs"),
+            UtxoError::InvalidSignature { input } => {
+                write!(f, "input {} is not signed by the output's owner", input)
+            }
+            UtxoError::UnknownInput { outpoint } => {
+                write!(f, "output {} does not exist or is already spent", outpoint)
+            }
+            UtxoError::DoubleSpend { outpoint } => {
+                write!(
+                    f,
+                    "output {} is spent by an earlier input in the block",
+                    outpoint
+                )
+            }
+            UtxoError::InsufficientInput {
+                available,
+                required,
+            } => write!(f, "inputs hold {} but outputs need {}", available, required),
+            UtxoError::Overflow => write!(f, "a value would overflow"),
+        }
+    }
+}
+
+impl fmt::Display for StakingError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StakingError::InvalidSignature => write!(f, "signature does not recover a sender"),
+            StakingError::WrongChain { expected, actual } => {
+                write!(f, "signed for chain {} instead of {}", actual, expected)
+            }
+            StakingError::InvalidNonce { expected, actual } => {
+                write!(f, "expected nonce {}, got {}", expected, actual)
+            }
+            StakingError::ZeroAmount => write!(f, "amount is zero"),
+            StakingError::Jailed { validator } => {
+                write!(f, "validator 0x{:x} is jailed", validator)
+            }
+            StakingError::InsufficientBond { bonded, requested } => {
+                write!(f, "unbonding {} with only {} bonded", requested, bonded)
+            }
+            StakingError::Overflow => write!(f, "bond would overflow"),
+        }
+    }
+}
+
+impl fmt::Display for EvidenceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EvidenceError::NotDoubleSign => {
+                write!(f, "headers are not two blocks signed by one proposer")
+            }
+            EvidenceError::AlreadyJailed { offender } => {
+                write!(f, "0x{:x} is already jailed", offender)
+            }
+            EvidenceError::NotStaked { offender } => {
+                write!(f, "0x{:x} has no stake to slash", offender)
+            }
+        }
+    }
+}
+
+fn format_hash(hash: Option<&BigUint>) -> String {
+    match hash {
+        Some(hash) => format!("0x{:064x}", hash),
+        None => "none".to_string(),
+    }
+}
+
+impl Error for ValidateBlockError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ValidateBlockError::Header(error) => Some(error),
+            ValidateBlockError::ProofOfWork(error) => Some(error),
+            ValidateBlockError::Seal(error) => Some(error),
+            ValidateBlockError::Linkage(error) => Some(error),
+            ValidateBlockError::Timestamp(error) => Some(error),
+            ValidateBlockError::Size(error) => Some(error),
+            ValidateBlockError::Body(error) => Some(error),
+        }
+    }
+}
+
+impl Error for HeaderError {}
+impl Error for ProofOfWorkError {}
+impl Error for SealError {}
+impl Error for LinkageError {}
+impl Error for TimestampError {}
+impl Error for SizeError {}
+impl Error for BodyError {}
+impl Error for TransactionError {}
+impl Error for UtxoError {}
+impl Error for StakingError {}
+impl Error for EvidenceError {}
+
+impl From<HeaderError> for ValidateBlockError {
+    fn from(error: HeaderError) -> Self {
+        ValidateBlockError::Header(error)
+    }
+}
+
+impl From<ProofOfWorkError> for ValidateBlockError {
+    fn from(error: ProofOfWorkError) -> Self {
+        ValidateBlockError::ProofOfWork(error)
+    }
+}
+
+impl From<SealError> for ValidateBlockError {
+    fn from(error: SealError) -> Self {
+        ValidateBlockError::Seal(error)
+    }
+}
+
+impl From<LinkageError> for ValidateBlockError {
+    fn from(error: LinkageError) -> Self {
+        ValidateBlockError::Linkage(error)
+    }
+}
+
+impl From<TimestampError> for ValidateBlockError {
+    fn from(error: TimestampError) -> Self {
+        ValidateBlockError::Timestamp(error)
+    }
+}
+
+impl From<SizeError> for ValidateBlockError {
+    fn from(error: SizeError) -> Self {
+        ValidateBlockError::Size(error)
+    }
+}
+
+impl From<BodyError> for ValidateBlockError {
+    fn from(error: BodyError) -> Self {
+        ValidateBlockError::Body(error)
+    }
+}
+
+impl From<EncodingError> for ValidateBlockError {
+    fn from(error: EncodingError) -> Self {
+        ValidateBlockError::Header(HeaderError::Encoding { error })
+    }
+}
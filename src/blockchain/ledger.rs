@@ -0,0 +1,116 @@
+use num_bigint::BigUint;
+use serde_derive::{Deserialize, Serialize};
+
+use super::block::Block;
+use super::chain_spec::ChainSpec;
+use super::consensus::BalanceChange;
+use super::error::BodyError;
+use super::state::WorldState;
+use super::utxo::{BlockUndo, UtxoSet};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(tag = "model", rename_all = "snake_case")]
+pub enum LedgerSpec {
+    // Accounts with balances and nonces, spent from by `transactions`.
+    #[default]
+    Account,
+    // Unspent outputs, spent whole by `utxo` transactions.
+    Utxo,
+}
+
+// Where coins are kept, as of some block.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Ledger {
+    Account(WorldState),
+    Utxo(UtxoSet),
+}
+
+impl Default for Ledger {
+    fn default() -> Self {
+        Ledger::Account(WorldState::default())
+    }
+}
+
+impl Ledger {
+    pub fn genesis(spec: &ChainSpec, genesis: &Block) -> Self {
+        let allocations = spec.allocations().expect("Invalid genesis allocation");
+
+        match spec.ledger {
+            LedgerSpec::Account => Ledger::Account(WorldState::new(allocations)),
+            LedgerSpec::Utxo => Ledger::Utxo(UtxoSet::new(genesis, allocations)),
+        }
+    }
+
+    pub fn accounts(&self) -> Option<&WorldState> {
+        match self {
+            Ledger::Account(state) => Some(state),
+            Ledger::Utxo(_) => None,
+        }
+    }
+
+    pub fn utxos(&self) -> Option<&UtxoSet> {
+        match self {
+            Ledger::Account(_) => None,
+            Ledger::Utxo(utxos) => Some(utxos),
+        }
+    }
+
+    pub fn balance(&self, address: &BigUint) -> u64 {
+        match self {
+            Ledger::Account(state) => state.balance(address),
+            Ledger::Utxo(utxos) => utxos.balance(address),
+        }
+    }
+
+    // What the `state_root` of a block leaving the ledger like this must be.
+    pub fn root(&self) -> BigUint {
+        match self {
+            Ledger::Account(state) => state.root(),
+            Ledger::Utxo(utxos) => utxos.root(),
+        }
+    }
+
+    // The consensus engine's balance `changes` only apply to accounts.
+    pub fn check_block(
+        &self,
+        block: &Block,
+        subsidy: u64,
+        changes: &[BalanceChange],
+    ) -> Result<(), BodyError> {
+        match self {
+            Ledger::Account(state) => state.check_block(block, subsidy, changes),
+            Ledger::Utxo(_) if !changes.is_empty() => Err(BodyError::WrongLedger),
+            Ledger::Utxo(utxos) => utxos.check_block(block, subsidy),
+        }
+    }
+
+    // Accounts are reverted from the block alone, so only the UTXO ledger
+    // returns anything to undo it with.
+    pub fn apply_block(
+        &mut self,
+        block: &Block,
+        subsidy: u64,
+        changes: &[BalanceChange],
+    ) -> Result<BlockUndo, BodyError> {
+        match self {
+            Ledger::Account(state) => state
+                .apply_block(block, subsidy, changes)
+                .map(|_| BlockUndo::default()),
+            Ledger::Utxo(_) if !changes.is_empty() => Err(BodyError::WrongLedger),
+            Ledger::Utxo(utxos) => utxos.apply_block(block, subsidy),
+        }
+    }
+
+    pub fn revert_block(
+        &mut self,
+        block: &Block,
+        subsidy: u64,
+        changes: &[BalanceChange],
+        undo: &BlockUndo,
+    ) {
+        match self {
+            Ledger::Account(state) => state.revert_block(block, subsidy, changes),
+            Ledger::Utxo(utxos) => utxos.revert_block(block, undo),
+        }
+    }
+}
@@ -0,0 +1,102 @@
+//! Binary Merkle tree over keccak256.
+//!
+//! Leaves are hashed as `keccak256(0x00 || data)` and inner nodes as
+//! `keccak256(0x01 || left || right)`, so a leaf can never pass for a node.
+//! A node without a sibling moves up a level unchanged instead of being
+//! paired with itself, so no two lists of leaves share a root. The root of
+//! no leaves is zero.
+
+use num_bigint::BigUint;
+use num_traits::Zero;
+use serde_derive::{Deserialize, Serialize};
+
+use super::encoding::encode_u256;
+use super::serde_hex;
+use crate::helpers::keccak256_bytes;
+
+const LEAF_PREFIX: u8 = 0;
+
+const NODE_PREFIX: u8 = 1;
+
+// Sibling hashed with the running node at one level of a proof.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ProofStep {
+    #[serde(with = "serde_hex::biguint")]
+    pub sibling: BigUint,
+    // Whether the sibling goes on the left.
+    pub left: bool,
+}
+
+pub fn leaf_hash(data: &[u8]) -> BigUint {
+    let mut bytes = vec![LEAF_PREFIX];
+    bytes.extend_from_slice(data);
+
+    keccak256_bytes(&bytes)
+}
+
+pub fn node_hash(left: &BigUint, right: &BigUint) -> BigUint {
+    let mut bytes = vec![NODE_PREFIX];
+    bytes.extend_from_slice(&encode_u256(left).expect("Hashes fit in 256 bits"));
+    bytes.extend_from_slice(&encode_u256(right).expect("Hashes fit in 256 bits"));
+
+    keccak256_bytes(&bytes)
+}
+
+fn parent_level(level: &[BigUint]) -> Vec<BigUint> {
+    level
+        .chunks(2)
+        .map(|pair| match pair {
+            [left, right] => node_hash(left, right),
+            [single] => single.to_owned(),
+            _ => unreachable!(),
+        })
+        .collect()
+}
+
+pub fn root(leaves: &[BigUint]) -> BigUint {
+    let mut level = leaves.to_vec();
+
+    while level.len() > 1 {
+        level = parent_level(&level);
+    }
+
+    level.pop().unwrap_or_else(BigUint::zero)
+}
+
+// Path from leaf `index` to the root, or `None` if there is no such leaf.
+pub fn proof(leaves: &[BigUint], index: usize) -> Option<Vec<ProofStep>> {
+    if index >= leaves.len() {
+        return None;
+    }
+
+    let mut path = Vec::new();
+    let mut level = leaves.to_vec();
+    let mut index = index;
+
+    while level.len() > 1 {
+        let sibling = index ^ 1;
+
+        if let Some(hash) = level.get(sibling) {
+            path.push(ProofStep {
+                sibling: hash.to_owned(),
+                left: sibling < index,
+            });
+        }
+
+        level = parent_level(&level);
+        index /= 2;
+    }
+
+    Some(path)
+}
+
+// Root of the tree `leaf` is in, if `path` is its proof.
+pub fn root_from(leaf: &BigUint, path: &[ProofStep]) -> BigUint {
+    path.iter().fold(leaf.to_owned(), |node, step| {
+        if step.left {
+            node_hash(&step.sibling, &node)
+        } else {
+            node_hash(&node, &step.sibling)
+        }
+    })
+}
@@ -1,2 +1,19 @@
 pub mod block;
+#[allow(clippy::module_inception)]
 pub mod blockchain;
+pub mod chain_spec;
+pub mod consensus;
+pub mod crypto;
+pub mod difficulty;
+pub mod encoding;
+pub mod error;
+pub mod ledger;
+pub mod merkle;
+pub mod pow;
+pub mod serde_hex;
+pub mod sparse_merkle;
+pub mod staking;
+pub mod state;
+pub mod target;
+pub mod transaction;
+pub mod utxo;
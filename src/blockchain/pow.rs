@@ -0,0 +1,120 @@
+use num_bigint::BigUint;
+use serde_derive::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use super::encoding::{encode_u256, U256_LENGTH};
+use crate::helpers::keccak256_bytes;
+
+// Every node hashes every block it validates, so scrypt parameters are capped
+// well below what the scrypt crate itself accepts: a hash needs at most
+// 128 MiB, and at most 16 runs of the memory-hard mix.
+pub const MAX_SCRYPT_LOG_N: u8 = 17;
+pub const MAX_SCRYPT_R: u32 = 8;
+pub const MAX_SCRYPT_P: u32 = 16;
+
+pub trait PowAlgorithm: Send + Sync {
+    // Hash compared against the target. `header_bytes` are the encoded headers,
+    // so searches over many nonces encode them only once.
+    fn hash(&self, header_bytes: &[u8], nonce: &BigUint) -> Option<BigUint>;
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(tag = "algorithm", rename_all = "snake_case")]
+pub enum PowSpec {
+    Legacy,
+    #[default]
+    Keccak,
+    Sha256d,
+    Scrypt { log_n: u8, r: u32, p: u32 },
+}
+
+impl PowSpec {
+    pub fn algorithm(&self) -> Box<dyn PowAlgorithm> {
+        match *self {
+            PowSpec::Legacy => Box::new(LegacyPow),
+            PowSpec::Keccak => Box::new(KeccakPow),
+            PowSpec::Sha256d => Box::new(Sha256dPow),
+            PowSpec::Scrypt { log_n, r, p } => Box::new(ScryptPow {
+                params: scrypt::Params::new(log_n, r, p, U256_LENGTH)
+                    .expect("Invalid scrypt parameters"),
+            }),
+        }
+    }
+
+    // Catches parameters `algorithm` would reject when the spec is loaded.
+    pub fn validate(&self) -> Result<(), String> {
+        match *self {
+            PowSpec::Scrypt { log_n, .. } if log_n > MAX_SCRYPT_LOG_N => Err(format!(
+                "scrypt: log_n is {}, at most {} allowed",
+                log_n, MAX_SCRYPT_LOG_N
+            )),
+            PowSpec::Scrypt { r, .. } if r > MAX_SCRYPT_R => Err(format!(
+                "scrypt: r is {}, at most {} allowed",
+                r, MAX_SCRYPT_R
+            )),
+            PowSpec::Scrypt { p, .. } if p > MAX_SCRYPT_P => Err(format!(
+                "scrypt: p is {}, at most {} allowed",
+                p, MAX_SCRYPT_P
+            )),
+            PowSpec::Scrypt { log_n, r, p } => scrypt::Params::new(log_n, r, p, U256_LENGTH)
+                .map(|_| ())
+                .map_err(|err| format!("scrypt: {}", err)),
+            _ => Ok(()),
+        }
+    }
+}
+
+fn with_nonce(header_bytes: &[u8], nonce: &BigUint) -> Option<Vec<u8>> {
+    let mut bytes = header_bytes.to_vec();
+    bytes.extend_from_slice(&encode_u256(nonce).ok()?);
+
+    Some(bytes)
+}
+
+// The original scheme: keccak256 of the decimal string of header hash + nonce.
+pub struct LegacyPow;
+
+impl PowAlgorithm for LegacyPow {
+    fn hash(&self, header_bytes: &[u8], nonce: &BigUint) -> Option<BigUint> {
+        let text = (keccak256_bytes(header_bytes) + nonce).to_string();
+
+        Some(keccak256_bytes(text.as_bytes()))
+    }
+}
+
+// keccak256(header || nonce as 32 bytes).
+pub struct KeccakPow;
+
+impl PowAlgorithm for KeccakPow {
+    fn hash(&self, header_bytes: &[u8], nonce: &BigUint) -> Option<BigUint> {
+        Some(keccak256_bytes(&with_nonce(header_bytes, nonce)?))
+    }
+}
+
+// Bitcoin-style sha256(sha256(header || nonce as 32 bytes)).
+pub struct Sha256dPow;
+
+impl PowAlgorithm for Sha256dPow {
+    fn hash(&self, header_bytes: &[u8], nonce: &BigUint) -> Option<BigUint> {
+        let first = Sha256::digest(with_nonce(header_bytes, nonce)?);
+
+        Some(BigUint::from_bytes_be(&Sha256::digest(first)))
+    }
+}
+
+// Memory-hard scrypt with the sealed header as both password and salt, as in
+// Litecoin. Each hash needs 128 * r * 2^log_n bytes of memory.
+pub struct ScryptPow {
+    params: scrypt::Params,
+}
+
+impl PowAlgorithm for ScryptPow {
+    fn hash(&self, header_bytes: &[u8], nonce: &BigUint) -> Option<BigUint> {
+        let input = with_nonce(header_bytes, nonce)?;
+        let mut output = [0u8; U256_LENGTH];
+
+        scrypt::scrypt(&input, &input, &self.params, &mut output).ok()?;
+
+        Some(BigUint::from_bytes_be(&output))
+    }
+}
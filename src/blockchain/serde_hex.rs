@@ -0,0 +1,109 @@
+use std::fmt;
+
+use num_bigint::BigUint;
+use num_traits::{Num, Zero};
+use serde::{de, Deserializer, Serializer};
+
+pub fn parse_hex_biguint(text: &str) -> Option<BigUint> {
+    let digits = text.strip_prefix("0x").unwrap_or(text);
+
+    if digits.is_empty() {
+        return Some(BigUint::zero());
+    }
+
+    BigUint::from_str_radix(digits, 16).ok()
+}
+
+pub mod biguint {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(value: &BigUint, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&format!("0x{:x}", value))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<BigUint, D::Error> {
+        deserializer.deserialize_str(HexVisitor(parse_hex_biguint))
+    }
+}
+
+pub mod option_biguint {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(
+        value: &Option<BigUint>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match value {
+            Some(value) => biguint::serialize(value, serializer),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<BigUint>, D::Error> {
+        use serde::Deserialize;
+
+        match Option::<String>::deserialize(deserializer)? {
+            Some(text) => parse_hex_biguint(&text).map(Some).ok_or_else(|| {
+                de::Error::invalid_value(de::Unexpected::Str(&text), &"a 0x-prefixed hex string")
+            }),
+            None => Ok(None),
+        }
+    }
+}
+
+pub mod biguint_vec {
+    use serde::{Deserialize, Serialize};
+
+    use super::*;
+
+    pub fn serialize<S: Serializer>(values: &[BigUint], serializer: S) -> Result<S::Ok, S::Error> {
+        values
+            .iter()
+            .map(|value| format!("0x{:x}", value))
+            .collect::<Vec<_>>()
+            .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<BigUint>, D::Error> {
+        Vec::<String>::deserialize(deserializer)?
+            .iter()
+            .map(|text| {
+                parse_hex_biguint(text).ok_or_else(|| {
+                    de::Error::invalid_value(de::Unexpected::Str(text), &"a 0x-prefixed hex string")
+                })
+            })
+            .collect()
+    }
+}
+
+pub mod bytes {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(value: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&format!("0x{}", hex::encode(value)))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        deserializer.deserialize_str(HexVisitor(|text: &str| {
+            hex::decode(text.strip_prefix("0x").unwrap_or(text)).ok()
+        }))
+    }
+}
+
+struct HexVisitor<F>(F);
+
+impl<'de, T, F: Fn(&str) -> Option<T>> de::Visitor<'de> for HexVisitor<F> {
+    type Value = T;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a 0x-prefixed hex string")
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<T, E> {
+        (self.0)(value).ok_or_else(|| E::invalid_value(de::Unexpected::Str(value), &self))
+    }
+}
@@ -0,0 +1,160 @@
+//! Sparse Merkle tree over 256-bit keys.
+//!
+//! Each key picks a path from the root by its bits, most significant first.
+//! The hash of a subtree depends on how many keys fall under it: zero for
+//! none, `merkle::leaf_hash(key || value)` for exactly one, whatever the depth,
+//! and `merkle::node_hash(left, right)` for more. Proofs are therefore only as
+//! long as it takes to tell a key apart from its neighbours, and the same keys
+//! and values always give the same root.
+
+use std::collections::BTreeMap;
+
+use num_bigint::BigUint;
+use num_traits::Zero;
+use serde_derive::{Deserialize, Serialize};
+
+use super::encoding::encode_u256;
+use super::merkle;
+use super::serde_hex;
+
+const KEY_BITS: u64 = 256;
+
+// The only key under the subtree a proof ends at.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Leaf {
+    #[serde(with = "serde_hex::biguint")]
+    pub key: BigUint,
+    #[serde(with = "serde_hex::biguint")]
+    pub value: BigUint,
+}
+
+// Siblings along the path of a key, from the root down, to the first subtree
+// holding at most one key. Shows which value the key has, or that it has
+// none, given only the root.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct SparseProof {
+    #[serde(with = "serde_hex::biguint_vec")]
+    pub siblings: Vec<BigUint>,
+    pub leaf: Option<Leaf>,
+}
+
+// Bit `depth` of `key`, counting from the most significant.
+fn goes_right(key: &BigUint, depth: usize) -> bool {
+    key.bit(KEY_BITS - 1 - depth as u64)
+}
+
+fn leaf_hash(key: &BigUint, value: &BigUint) -> BigUint {
+    let mut bytes = encode_u256(key).expect("Keys fit in 256 bits").to_vec();
+    bytes.extend_from_slice(&encode_u256(value).expect("Hashes fit in 256 bits"));
+
+    merkle::leaf_hash(&bytes)
+}
+
+// Keys and values, sorted by key.
+type Leaves<'a> = [(&'a BigUint, &'a BigUint)];
+
+// Keys sorted ascending share their first `depth` bits, so those going left
+// at `depth` all come before those going right.
+fn split<'a>(leaves: &'a Leaves<'a>, depth: usize) -> (&'a Leaves<'a>, &'a Leaves<'a>) {
+    leaves.split_at(leaves.partition_point(|(key, _)| !goes_right(key, depth)))
+}
+
+fn subtree(leaves: &Leaves, depth: usize) -> BigUint {
+    match leaves {
+        [] => BigUint::zero(),
+        [(key, value)] => leaf_hash(key, value),
+        _ => {
+            let (left, right) = split(leaves, depth);
+
+            merkle::node_hash(&subtree(left, depth + 1), &subtree(right, depth + 1))
+        }
+    }
+}
+
+pub fn root(leaves: &BTreeMap<BigUint, BigUint>) -> BigUint {
+    let leaves: Vec<_> = leaves.iter().collect();
+
+    subtree(&leaves, 0)
+}
+
+pub fn proof(leaves: &BTreeMap<BigUint, BigUint>, key: &BigUint) -> SparseProof {
+    let leaves: Vec<_> = leaves.iter().collect();
+    let mut leaves = &leaves[..];
+    let mut siblings = Vec::new();
+
+    while leaves.len() > 1 {
+        let depth = siblings.len();
+        let (left, right) = split(leaves, depth);
+
+        let (next, sibling) = if goes_right(key, depth) {
+            (right, left)
+        } else {
+            (left, right)
+        };
+
+        siblings.push(subtree(sibling, depth + 1));
+        leaves = next;
+    }
+
+    SparseProof {
+        siblings,
+        leaf: leaves.first().map(|(key, value)| Leaf {
+            key: (*key).to_owned(),
+            value: (*value).to_owned(),
+        }),
+    }
+}
+
+impl SparseProof {
+    // Whether `key` has `value` in the tree with `root`, where `None` means
+    // the key is not in the tree at all.
+    pub fn verify(&self, root: &BigUint, key: &BigUint, value: Option<&BigUint>) -> bool {
+        let depth = self.siblings.len();
+
+        // Hashes only take 256-bit words.
+        let fits = |word: &BigUint| word.bits() <= KEY_BITS;
+
+        if depth >= KEY_BITS as usize
+            || !fits(key)
+            || !value.is_none_or(fits)
+            || !self.siblings.iter().all(fits)
+            || !self
+                .leaf
+                .as_ref()
+                .is_none_or(|leaf| fits(&leaf.key) && fits(&leaf.value))
+        {
+            return false;
+        }
+
+        let node = match (&self.leaf, value) {
+            (Some(leaf), Some(value)) if leaf.key == *key && leaf.value == *value => {
+                leaf_hash(&leaf.key, &leaf.value)
+            }
+            // Another key ending the path shows this one is absent, as long
+            // as the path is really the one this key takes.
+            (Some(leaf), None)
+                if leaf.key != *key
+                    && (0..depth).all(|bit| goes_right(&leaf.key, bit) == goes_right(key, bit)) =>
+            {
+                leaf_hash(&leaf.key, &leaf.value)
+            }
+            (None, None) => BigUint::zero(),
+            _ => return false,
+        };
+
+        let computed =
+            self.siblings
+                .iter()
+                .enumerate()
+                .rev()
+                .fold(node, |node, (depth, sibling)| {
+                    if goes_right(key, depth) {
+                        merkle::node_hash(sibling, &node)
+                    } else {
+                        merkle::node_hash(&node, sibling)
+                    }
+                });
+
+        computed == *root
+    }
+}
@@ -0,0 +1,473 @@
+use std::collections::BTreeMap;
+
+use num_bigint::BigUint;
+use num_traits::ToPrimitive;
+use serde_derive::{Deserialize, Serialize};
+
+use super::block::{Block, BlockHeaders};
+use super::crypto::{self, Signer};
+use super::encoding::{encode_signature, encode_u256, Decoder, EncodingError};
+use super::error::{EvidenceError, StakingError};
+use super::serde_hex;
+use crate::helpers::keccak256_bytes;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum StakeAction {
+    Bond,
+    Unbond,
+}
+
+// Bonds the signer's coins to `validator`, or unbonds them. Bonding to
+// yourself makes you a validator; bonding to anyone else delegates to them.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct StakingTransaction {
+    pub chain_id: u64,
+    pub action: StakeAction,
+    #[serde(with = "serde_hex::biguint")]
+    pub validator: BigUint,
+    pub amount: u64,
+    // Number of staking transactions the signer made before this one.
+    pub nonce: u64,
+    #[serde(with = "serde_hex::bytes", default)]
+    pub signature: Vec<u8>,
+}
+
+// The headers of a sealed block and its producer's signature: enough to tell
+// who signed what without the rest of the block.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct SignedHeader {
+    pub headers: BlockHeaders,
+    #[serde(with = "serde_hex::bytes")]
+    pub signature: Vec<u8>,
+}
+
+// Two different blocks signed by the same proposer for the same number and
+// attempt.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct DoubleSign {
+    pub first: SignedHeader,
+    pub second: SignedHeader,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Bond {
+    #[serde(with = "serde_hex::biguint")]
+    pub delegator: BigUint,
+    #[serde(with = "serde_hex::biguint")]
+    pub validator: BigUint,
+    pub amount: u64,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Stake {
+    #[serde(with = "serde_hex::biguint")]
+    pub validator: BigUint,
+    pub amount: u64,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct StakerNonce {
+    #[serde(with = "serde_hex::biguint")]
+    pub address: BigUint,
+    pub nonce: u64,
+}
+
+// Who has bonded what to whom. Bonds change with every block, but proposers
+// are drawn from `active`, which is only recomputed when an epoch begins.
+#[derive(Serialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct StakeLedger {
+    pub bonds: Vec<Bond>,
+    // Unbonded coins, paid out when the epoch ends and slashable until then.
+    pub unbonding: Vec<Bond>,
+    // Stake of each validator for the current epoch.
+    pub active: Vec<Stake>,
+    #[serde(with = "serde_hex::biguint_vec")]
+    pub jailed: Vec<BigUint>,
+    pub nonces: Vec<StakerNonce>,
+    // Mixed with the hash of every epoch's first block.
+    #[serde(with = "serde_hex::biguint")]
+    pub seed: BigUint,
+}
+
+impl StakingTransaction {
+    pub fn new(
+        chain_id: u64,
+        action: StakeAction,
+        validator: BigUint,
+        amount: u64,
+        nonce: u64,
+    ) -> Self {
+        StakingTransaction {
+            chain_id,
+            action,
+            validator,
+            amount,
+            nonce,
+            signature: Vec::new(),
+        }
+    }
+
+    pub fn signed(self, signer: &Signer) -> Option<Self> {
+        let signature = signer.sign(&self.signing_hash().ok()?)?;
+
+        Some(StakingTransaction { signature, ..self })
+    }
+
+    fn encode_unsigned(&self) -> Result<Vec<u8>, EncodingError> {
+        let mut bytes = self.chain_id.to_be_bytes().to_vec();
+
+        bytes.push(match self.action {
+            StakeAction::Bond => 0,
+            StakeAction::Unbond => 1,
+        });
+
+        bytes.extend_from_slice(&encode_u256(&self.validator)?);
+        bytes.extend_from_slice(&self.amount.to_be_bytes());
+        bytes.extend_from_slice(&self.nonce.to_be_bytes());
+
+        Ok(bytes)
+    }
+
+    pub fn encode(&self) -> Result<Vec<u8>, EncodingError> {
+        let mut bytes = self.encode_unsigned()?;
+        bytes.extend_from_slice(&encode_signature(&self.signature)?);
+
+        Ok(bytes)
+    }
+
+    pub fn decode_from(decoder: &mut Decoder) -> Result<Self, EncodingError> {
+        let chain_id = decoder.read_u64()?;
+        let action = match decoder.read_u8()? {
+            0 => StakeAction::Bond,
+            1 => StakeAction::Unbond,
+            _ => return Err(EncodingError::ValueTooLarge),
+        };
+
+        Ok(StakingTransaction {
+            chain_id,
+            action,
+            validator: decoder.read_u256()?,
+            amount: decoder.read_u64()?,
+            nonce: decoder.read_u64()?,
+            signature: decoder.read_signature()?,
+        })
+    }
+
+    pub fn signing_hash(&self) -> Result<BigUint, EncodingError> {
+        Ok(keccak256_bytes(&self.encode_unsigned()?))
+    }
+
+    pub fn sender(&self) -> Option<BigUint> {
+        crypto::recover(&self.signing_hash().ok()?, &self.signature)
+    }
+}
+
+impl SignedHeader {
+    pub fn of(block: &Block) -> Self {
+        SignedHeader {
+            headers: block.headers().to_owned(),
+            signature: block.signature().to_vec(),
+        }
+    }
+
+    pub fn hash(&self) -> Option<BigUint> {
+        Block::get_block_hash(&self.headers)
+    }
+
+    pub fn encode(&self) -> Result<Vec<u8>, EncodingError> {
+        let mut bytes = self.headers.encode()?;
+        bytes.extend_from_slice(&encode_signature(&self.signature)?);
+
+        Ok(bytes)
+    }
+
+    pub fn decode_from(decoder: &mut Decoder) -> Result<Self, EncodingError> {
+        Ok(SignedHeader {
+            headers: BlockHeaders::decode_from(decoder)?,
+            signature: decoder.read_signature()?,
+        })
+    }
+
+    pub fn signer(&self) -> Option<BigUint> {
+        crypto::recover(&self.hash()?, &self.signature)
+    }
+}
+
+impl DoubleSign {
+    pub fn encode(&self) -> Result<Vec<u8>, EncodingError> {
+        let mut bytes = self.first.encode()?;
+        bytes.extend_from_slice(&self.second.encode()?);
+
+        Ok(bytes)
+    }
+
+    pub fn decode_from(decoder: &mut Decoder) -> Result<Self, EncodingError> {
+        Ok(DoubleSign {
+            first: SignedHeader::decode_from(decoder)?,
+            second: SignedHeader::decode_from(decoder)?,
+        })
+    }
+
+    // The proposer who signed both blocks, if they really are two different
+    // blocks for the same number and attempt.
+    pub fn offender(&self) -> Option<BigUint> {
+        let (first, second) = (&self.first, &self.second);
+
+        let same_slot = first.headers.number() == second.headers.number()
+            && first.headers.extra_nonce() == second.headers.extra_nonce();
+
+        if !same_slot || first.hash()? == second.hash()? {
+            return None;
+        }
+
+        let signer = first.signer()?;
+
+        if second.signer()? != signer || *first.headers.beneficiary() != signer {
+            return None;
+        }
+
+        Some(signer)
+    }
+}
+
+impl StakeLedger {
+    // Genesis stakes are bonded to themselves and active from block 1.
+    pub fn genesis(stakes: &[(BigUint, u64)], min_stake: u64, seed: BigUint) -> Self {
+        let mut ledger = StakeLedger {
+            bonds: stakes
+                .iter()
+                .map(|(validator, amount)| Bond {
+                    delegator: validator.to_owned(),
+                    validator: validator.to_owned(),
+                    amount: *amount,
+                })
+                .collect(),
+            seed,
+            ..StakeLedger::default()
+        };
+
+        ledger.activate(min_stake);
+        ledger
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == StakeLedger::default()
+    }
+
+    pub fn bonded(&self, delegator: &BigUint, validator: &BigUint) -> u64 {
+        self.bonds
+            .iter()
+            .find(|bond| bond.delegator == *delegator && bond.validator == *validator)
+            .map_or(0, |bond| bond.amount)
+    }
+
+    pub fn nonce(&self, address: &BigUint) -> u64 {
+        self.nonces
+            .iter()
+            .find(|nonce| nonce.address == *address)
+            .map_or(0, |nonce| nonce.nonce)
+    }
+
+    pub fn is_jailed(&self, validator: &BigUint) -> bool {
+        self.jailed.contains(validator)
+    }
+
+    // Applies `transaction`, returning its sender and the coins to take from
+    // the sender's balance.
+    pub fn apply(
+        &mut self,
+        transaction: &StakingTransaction,
+    ) -> Result<(BigUint, u64), StakingError> {
+        let sender = transaction.sender().ok_or(StakingError::InvalidSignature)?;
+        let expected = self.nonce(&sender);
+
+        if transaction.nonce != expected {
+            return Err(StakingError::InvalidNonce {
+                expected,
+                actual: transaction.nonce,
+            });
+        }
+
+        if transaction.amount == 0 {
+            return Err(StakingError::ZeroAmount);
+        }
+
+        let validator = &transaction.validator;
+
+        let debit = match transaction.action {
+            // Delegators of a jailed validator may still unbond what is left.
+            StakeAction::Bond if self.is_jailed(validator) => {
+                return Err(StakingError::Jailed {
+                    validator: validator.to_owned(),
+                });
+            }
+            StakeAction::Bond => {
+                match self
+                    .bonds
+                    .iter_mut()
+                    .find(|bond| bond.delegator == sender && bond.validator == *validator)
+                {
+                    Some(bond) => {
+                        bond.amount = bond
+                            .amount
+                            .checked_add(transaction.amount)
+                            .ok_or(StakingError::Overflow)?
+                    }
+                    None => self.bonds.push(Bond {
+                        delegator: sender.to_owned(),
+                        validator: validator.to_owned(),
+                        amount: transaction.amount,
+                    }),
+                }
+
+                transaction.amount
+            }
+            StakeAction::Unbond => {
+                let bonded = self.bonded(&sender, validator);
+
+                if transaction.amount > bonded {
+                    return Err(StakingError::InsufficientBond {
+                        bonded,
+                        requested: transaction.amount,
+                    });
+                }
+
+                for bond in &mut self.bonds {
+                    if bond.delegator == sender && bond.validator == *validator {
+                        bond.amount -= transaction.amount;
+                    }
+                }
+
+                self.bonds.retain(|bond| bond.amount > 0);
+                self.unbonding.push(Bond {
+                    delegator: sender.to_owned(),
+                    validator: validator.to_owned(),
+                    amount: transaction.amount,
+                });
+
+                0
+            }
+        };
+
+        match self.nonces.iter_mut().find(|nonce| nonce.address == sender) {
+            Some(nonce) => nonce.nonce += 1,
+            None => self.nonces.push(StakerNonce {
+                address: sender.to_owned(),
+                nonce: 1,
+            }),
+        }
+
+        Ok((sender, debit))
+    }
+
+    // Burns `percent` of everything bonded or unbonding to the offender and
+    // jails them for good, taking them out of proposer selection at once.
+    pub fn slash(&mut self, evidence: &DoubleSign, percent: u8) -> Result<BigUint, EvidenceError> {
+        let offender = evidence.offender().ok_or(EvidenceError::NotDoubleSign)?;
+
+        if self.is_jailed(&offender) {
+            return Err(EvidenceError::AlreadyJailed { offender });
+        }
+
+        let staked = self
+            .bonds
+            .iter()
+            .chain(&self.unbonding)
+            .any(|bond| bond.validator == offender)
+            || self.active.iter().any(|stake| stake.validator == offender);
+
+        if !staked {
+            return Err(EvidenceError::NotStaked { offender });
+        }
+
+        for bond in self.bonds.iter_mut().chain(self.unbonding.iter_mut()) {
+            if bond.validator == offender {
+                let slashed = (bond.amount as u128 * percent as u128 / 100) as u64;
+                bond.amount -= slashed;
+            }
+        }
+
+        self.bonds.retain(|bond| bond.amount > 0);
+        self.unbonding.retain(|bond| bond.amount > 0);
+        self.jailed.push(offender.to_owned());
+
+        Ok(offender)
+    }
+
+    // Starts a new epoch at `block_hash`: pays out the unbonding coins and
+    // fixes the stake proposers are drawn from until the next one. Whoever
+    // proposes `block_hash` can grind the new seed; see `StakeEngine`.
+    pub fn begin_epoch(&mut self, block_hash: &BigUint, min_stake: u64) -> Vec<Bond> {
+        let mut bytes = encode_u256(&self.seed).unwrap_or_default().to_vec();
+        bytes.extend_from_slice(&encode_u256(block_hash).unwrap_or_default());
+
+        self.seed = keccak256_bytes(&bytes);
+        self.activate(min_stake);
+
+        std::mem::take(&mut self.unbonding)
+    }
+
+    // Validators need `min_stake` bonded to themselves; delegations to them
+    // count on top of that.
+    fn activate(&mut self, min_stake: u64) {
+        let mut totals: BTreeMap<&BigUint, u64> = BTreeMap::new();
+
+        for bond in &self.bonds {
+            let total = totals.entry(&bond.validator).or_default();
+            *total = total.saturating_add(bond.amount);
+        }
+
+        self.active = totals
+            .into_iter()
+            .filter(|(validator, _)| {
+                !self.is_jailed(validator) && self.bonded(validator, validator) >= min_stake
+            })
+            .map(|(validator, amount)| Stake {
+                validator: validator.to_owned(),
+                amount,
+            })
+            .collect();
+    }
+
+    // Proposer of block `number` at `attempt`, drawn with probability
+    // proportional to stake from a hash of the seed, number and attempt that
+    // anyone can recompute.
+    pub fn proposer(&self, number: u32, attempt: u32) -> Option<&BigUint> {
+        let eligible: Vec<&Stake> = self
+            .active
+            .iter()
+            .filter(|stake| !self.is_jailed(&stake.validator))
+            .collect();
+
+        let total: u128 = eligible.iter().map(|stake| stake.amount as u128).sum();
+
+        if total == 0 {
+            return None;
+        }
+
+        let mut bytes = encode_u256(&self.seed).ok()?.to_vec();
+        bytes.extend_from_slice(&number.to_be_bytes());
+        bytes.extend_from_slice(&attempt.to_be_bytes());
+
+        let mut point = (keccak256_bytes(&bytes) % BigUint::from(total)).to_u128()?;
+
+        for stake in eligible {
+            if point < stake.amount as u128 {
+                return Some(&stake.validator);
+            }
+
+            point -= stake.amount as u128;
+        }
+
+        None
+    }
+
+    pub fn validators(&self) -> Vec<BigUint> {
+        self.active
+            .iter()
+            .filter(|stake| !self.is_jailed(&stake.validator))
+            .map(|stake| stake.validator.to_owned())
+            .collect()
+    }
+}
@@ -0,0 +1,352 @@
+use std::collections::BTreeMap;
+
+use num_bigint::BigUint;
+use serde_derive::{Deserialize, Serialize};
+
+use super::block::{Block, BlockHeaders};
+use super::consensus::BalanceChange;
+use super::encoding::encode_u256;
+use super::error::{BodyError, TransactionError};
+use super::serde_hex;
+use super::sparse_merkle::{self, SparseProof};
+use super::transaction::Transaction;
+use crate::helpers::keccak256_bytes;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct Account {
+    pub balance: u64,
+    // Number of transactions the account has sent.
+    pub nonce: u64,
+    #[serde(with = "serde_hex::bytes", default)]
+    pub code: Vec<u8>,
+}
+
+// An account and its path to the `state_root` of a block, or a path showing
+// the block left the account empty.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct AccountProof {
+    #[serde(with = "serde_hex::biguint")]
+    pub address: BigUint,
+    pub account: Option<Account>,
+    pub proof: SparseProof,
+}
+
+// Every account as of some block. Accounts that were never touched, or were
+// emptied again, are left out.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WorldState {
+    accounts: BTreeMap<BigUint, Account>,
+}
+
+// Accounts a block has touched so far, on top of the state it started from.
+struct Overlay<'a> {
+    state: &'a WorldState,
+    touched: BTreeMap<BigUint, Account>,
+}
+
+impl Account {
+    pub fn is_empty(&self) -> bool {
+        self.balance == 0 && self.nonce == 0 && self.code.is_empty()
+    }
+
+    // Balance, nonce and the hash of the code, which keeps the encoding the
+    // same length however much code there is.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = self.balance.to_be_bytes().to_vec();
+        bytes.extend_from_slice(&self.nonce.to_be_bytes());
+        bytes.extend_from_slice(
+            &encode_u256(&keccak256_bytes(&self.code)).expect("Hashes fit in 256 bits"),
+        );
+
+        bytes
+    }
+
+    // Value the account is kept under in the state tree.
+    pub fn hash(&self) -> BigUint {
+        keccak256_bytes(&self.encode())
+    }
+}
+
+impl AccountProof {
+    // Whether the block with `headers` left the address with this account.
+    pub fn verify(&self, headers: &BlockHeaders) -> bool {
+        let key = match encode_u256(&self.address) {
+            Ok(address) => keccak256_bytes(&address),
+            Err(_) => return false,
+        };
+
+        // Empty accounts are left out of the tree, so are proven absent.
+        if self.account.as_ref().is_some_and(Account::is_empty) {
+            return false;
+        }
+
+        let value = self.account.as_ref().map(Account::hash);
+
+        self.proof
+            .verify(headers.state_root(), &key, value.as_ref())
+    }
+}
+
+impl WorldState {
+    pub fn new(allocations: impl IntoIterator<Item = (BigUint, u64)>) -> Self {
+        let mut state = WorldState::default();
+
+        for (address, balance) in allocations {
+            let account = state.accounts.entry(address).or_default();
+            account.balance = account.balance.saturating_add(balance);
+        }
+
+        state.accounts.retain(|_, account| !account.is_empty());
+        state
+    }
+
+    pub fn account(&self, address: &BigUint) -> Option<&Account> {
+        self.accounts.get(address)
+    }
+
+    pub fn accounts(&self) -> impl Iterator<Item = (&BigUint, &Account)> {
+        self.accounts.iter()
+    }
+
+    pub fn balance(&self, address: &BigUint) -> u64 {
+        self.account(address).map_or(0, |account| account.balance)
+    }
+
+    pub fn nonce(&self, address: &BigUint) -> u64 {
+        self.account(address).map_or(0, |account| account.nonce)
+    }
+
+    pub fn code(&self, address: &BigUint) -> &[u8] {
+        self.account(address).map_or(&[], |account| &account.code)
+    }
+
+    // Accounts keyed by the hash of their address, so that no one can pick
+    // addresses that make the paths to them long.
+    fn leaves(&self) -> BTreeMap<BigUint, BigUint> {
+        self.accounts
+            .iter()
+            .map(|(address, account)| (WorldState::key(address), account.hash()))
+            .collect()
+    }
+
+    fn key(address: &BigUint) -> BigUint {
+        keccak256_bytes(&encode_u256(address).expect("Addresses fit in 256 bits"))
+    }
+
+    // Root of the sparse Merkle tree over every account.
+    pub fn root(&self) -> BigUint {
+        sparse_merkle::root(&self.leaves())
+    }
+
+    pub fn proof(&self, address: &BigUint) -> AccountProof {
+        AccountProof {
+            address: address.to_owned(),
+            account: self.account(address).cloned(),
+            proof: sparse_merkle::proof(&self.leaves(), &WorldState::key(address)),
+        }
+    }
+
+    // Whether `block` applies on top of this state, without applying it.
+    pub fn check_block(
+        &self,
+        block: &Block,
+        subsidy: u64,
+        changes: &[BalanceChange],
+    ) -> Result<(), BodyError> {
+        self.transition(block, subsidy, changes).map(|_| ())
+    }
+
+    // Pays `subsidy` to the beneficiary, runs the transactions of `block` in
+    // order and then makes the engine's balance `changes`. Nothing is applied
+    // unless all of it is.
+    pub fn apply_block(
+        &mut self,
+        block: &Block,
+        subsidy: u64,
+        changes: &[BalanceChange],
+    ) -> Result<(), BodyError> {
+        let touched = self.transition(block, subsidy, changes)?;
+
+        for (address, account) in touched {
+            if account.is_empty() {
+                self.accounts.remove(&address);
+            } else {
+                self.accounts.insert(address, account);
+            }
+        }
+
+        Ok(())
+    }
+
+    // Undoes `apply_block` for a block that was applied to this state with
+    // the same `subsidy` and `changes`.
+    pub fn revert_block(&mut self, block: &Block, subsidy: u64, changes: &[BalanceChange]) {
+        for change in changes.iter().rev() {
+            match change {
+                BalanceChange::Credit { address, amount } => {
+                    let account = self.accounts.entry(address.to_owned()).or_default();
+                    account.balance = account.balance.saturating_sub(*amount);
+                }
+                BalanceChange::Debit { address, amount } => {
+                    let account = self.accounts.entry(address.to_owned()).or_default();
+                    account.balance = account.balance.saturating_add(*amount);
+                }
+            }
+        }
+
+        for transaction in block.body().transactions.iter().rev() {
+            let sender = match transaction.sender() {
+                Some(sender) => sender,
+                None => continue,
+            };
+            let fee = self
+                .accounts
+                .entry(block.beneficiary().to_owned())
+                .or_default();
+            fee.balance = fee.balance.saturating_sub(transaction.fee);
+
+            let recipient = match transaction.contract_address(&sender) {
+                Some(address) => {
+                    let contract = self.accounts.entry(address.to_owned()).or_default();
+                    contract.code.clear();
+                    address
+                }
+                None => transaction.recipient.to_owned(),
+            };
+            let recipient = self.accounts.entry(recipient).or_default();
+            recipient.balance = recipient.balance.saturating_sub(transaction.value);
+
+            let sender = self.accounts.entry(sender).or_default();
+            sender.balance = sender
+                .balance
+                .saturating_add(transaction.value)
+                .saturating_add(transaction.fee);
+            sender.nonce = sender.nonce.saturating_sub(1);
+        }
+
+        let beneficiary = self
+            .accounts
+            .entry(block.beneficiary().to_owned())
+            .or_default();
+        beneficiary.balance = beneficiary.balance.saturating_sub(subsidy);
+
+        self.accounts.retain(|_, account| !account.is_empty());
+    }
+
+    // Accounts `block` touches, as they are once it is applied.
+    fn transition(
+        &self,
+        block: &Block,
+        subsidy: u64,
+        changes: &[BalanceChange],
+    ) -> Result<BTreeMap<BigUint, Account>, BodyError> {
+        let mut overlay = Overlay {
+            state: self,
+            touched: BTreeMap::new(),
+        };
+
+        let beneficiary = overlay.account(block.beneficiary());
+        beneficiary.balance = beneficiary.balance.saturating_add(subsidy);
+
+        for (index, transaction) in block.body().transactions.iter().enumerate() {
+            overlay
+                .transact(block.beneficiary(), transaction)
+                .map_err(|error| BodyError::Transaction { index, error })?;
+        }
+
+        for change in changes {
+            match change {
+                BalanceChange::Credit { address, amount } => {
+                    let account = overlay.account(address);
+                    account.balance = account.balance.saturating_add(*amount);
+                }
+                BalanceChange::Debit { address, amount } => {
+                    let account = overlay.account(address);
+
+                    if account.balance < *amount {
+                        return Err(BodyError::InsufficientBalance {
+                            address: address.to_owned(),
+                            balance: account.balance,
+                            required: *amount,
+                        });
+                    }
+
+                    account.balance -= amount;
+                }
+            }
+        }
+
+        Ok(overlay.touched)
+    }
+}
+
+impl Overlay<'_> {
+    fn account(&mut self, address: &BigUint) -> &mut Account {
+        let state = self.state;
+
+        self.touched
+            .entry(address.to_owned())
+            .or_insert_with(|| state.account(address).cloned().unwrap_or_default())
+    }
+
+    // Moves `value` from the sender to the recipient and `fee` to whoever
+    // produced the block.
+    fn transact(
+        &mut self,
+        beneficiary: &BigUint,
+        transaction: &Transaction,
+    ) -> Result<(), TransactionError> {
+        let sender = transaction
+            .sender()
+            .ok_or(TransactionError::InvalidSignature)?;
+        let required = transaction
+            .value
+            .checked_add(transaction.fee)
+            .ok_or(TransactionError::Overflow)?;
+
+        let account = self.account(&sender);
+
+        if transaction.nonce != account.nonce {
+            return Err(TransactionError::InvalidNonce {
+                expected: account.nonce,
+                actual: transaction.nonce,
+            });
+        }
+
+        if account.balance < required {
+            return Err(TransactionError::InsufficientBalance {
+                balance: account.balance,
+                required,
+            });
+        }
+
+        account.balance -= required;
+        account.nonce += 1;
+
+        let recipient = match transaction.contract_address(&sender) {
+            Some(address) => {
+                let contract = self.account(&address);
+
+                if !contract.code.is_empty() {
+                    return Err(TransactionError::CodeExists { address });
+                }
+
+                contract.code = transaction.data.to_owned();
+                contract
+            }
+            None => self.account(&transaction.recipient),
+        };
+        recipient.balance = recipient
+            .balance
+            .checked_add(transaction.value)
+            .ok_or(TransactionError::Overflow)?;
+
+        let beneficiary = self.account(beneficiary);
+        beneficiary.balance = beneficiary
+            .balance
+            .checked_add(transaction.fee)
+            .ok_or(TransactionError::Overflow)?;
+
+        Ok(())
+    }
+}
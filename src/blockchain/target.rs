@@ -0,0 +1,92 @@
+use std::sync::LazyLock;
+
+use num_bigint::BigUint;
+use num_traits::{One, Zero};
+
+use super::encoding::U256_LENGTH;
+
+pub static MAX_TARGET: LazyLock<BigUint> =
+    LazyLock::new(|| BigUint::from_bytes_be(&[0xff; U256_LENGTH]));
+
+static TWO_POW_256: LazyLock<BigUint> = LazyLock::new(|| BigUint::one() << 256);
+
+const COMPACT_SIGN_BIT: u32 = 0x0080_0000;
+const COMPACT_MANTISSA: u32 = 0x007f_ffff;
+
+// A proof-of-work hash is valid when it is at most the target.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Target(BigUint);
+
+impl Target {
+    pub fn new(value: BigUint) -> Option<Target> {
+        if value.is_zero() || value > *MAX_TARGET {
+            return None;
+        }
+
+        Some(Target(value))
+    }
+
+    // Difficulty 1 is the easiest target; difficulty 0 is treated as 1.
+    pub fn from_difficulty(difficulty: &BigUint) -> Target {
+        let difficulty = difficulty.max(&BigUint::one()).to_owned();
+
+        Target((&*MAX_TARGET / difficulty).max(BigUint::one()))
+    }
+
+    pub fn difficulty(&self) -> BigUint {
+        &*MAX_TARGET / &self.0
+    }
+
+    // Expected number of hashes needed to meet the target, used to compare
+    // the cumulative work of competing branches.
+    pub fn work(&self) -> BigUint {
+        &*TWO_POW_256 / (&self.0 + BigUint::one())
+    }
+
+    pub fn is_met_by(&self, hash: &BigUint) -> bool {
+        *hash <= self.0
+    }
+
+    pub fn value(&self) -> &BigUint {
+        &self.0
+    }
+
+    // Bitcoin-style compact form: the high byte is the length of the target in
+    // bytes and the low three bytes are its most significant bytes. Negative,
+    // zero and overflowing encodings are rejected.
+    pub fn from_compact(bits: u32) -> Option<Target> {
+        let size = bits >> 24;
+        let mantissa = bits & COMPACT_MANTISSA;
+
+        if bits & COMPACT_SIGN_BIT != 0 {
+            return None;
+        }
+
+        let value = if size <= 3 {
+            BigUint::from(mantissa >> (8 * (3 - size)))
+        } else {
+            BigUint::from(mantissa) << (8 * (size - 3))
+        };
+
+        Target::new(value)
+    }
+
+    pub fn to_compact(&self) -> u32 {
+        let mut size = self.0.to_bytes_be().len() as u32;
+
+        let mut mantissa = if size <= 3 {
+            let low = self.0.iter_u32_digits().next().unwrap_or(0);
+            low << (8 * (3 - size))
+        } else {
+            let shifted: BigUint = &self.0 >> (8 * (size - 3));
+            shifted.iter_u32_digits().next().unwrap_or(0)
+        };
+
+        if mantissa & COMPACT_SIGN_BIT != 0 {
+            mantissa >>= 8;
+            size += 1;
+        }
+
+        mantissa | (size << 24)
+    }
+}
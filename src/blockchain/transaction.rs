@@ -0,0 +1,200 @@
+use num_bigint::BigUint;
+use num_traits::Zero;
+use serde_derive::{Deserialize, Serialize};
+
+use super::crypto::{self, Ed25519Signer, Signer};
+use super::encoding::{
+    encode_bytes, encode_data, encode_signature, encode_u256, Decoder, EncodingError,
+};
+use super::serde_hex;
+use crate::helpers::keccak256_bytes;
+
+// Value transfer from the signer to `recipient`. The chain id is part of what
+// is signed, so a transaction is only valid on the chain it was made for.
+// Sent to the zero address with data, it deploys the data as code instead.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Transaction {
+    pub chain_id: u64,
+    // Number of transactions the sender made before this one.
+    pub nonce: u64,
+    #[serde(with = "serde_hex::biguint")]
+    pub recipient: BigUint,
+    pub value: u64,
+    pub fee: u64,
+    #[serde(with = "serde_hex::bytes", default)]
+    pub data: Vec<u8>,
+    #[serde(default)]
+    pub signature: TransactionSignature,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(tag = "scheme", rename_all = "snake_case")]
+pub enum TransactionSignature {
+    #[default]
+    None,
+    // Recoverable signature; the sender is the address it recovers to.
+    Secp256k1 {
+        #[serde(with = "serde_hex::bytes")]
+        signature: Vec<u8>,
+    },
+    // The sender is the address of `public_key`.
+    Ed25519 {
+        #[serde(with = "serde_hex::bytes")]
+        public_key: Vec<u8>,
+        #[serde(with = "serde_hex::bytes")]
+        signature: Vec<u8>,
+    },
+}
+
+impl Transaction {
+    pub fn new(
+        chain_id: u64,
+        nonce: u64,
+        recipient: BigUint,
+        value: u64,
+        fee: u64,
+        data: Vec<u8>,
+    ) -> Self {
+        Transaction {
+            chain_id,
+            nonce,
+            recipient,
+            value,
+            fee,
+            data,
+            signature: TransactionSignature::None,
+        }
+    }
+
+    pub fn signed(self, signer: &Signer) -> Option<Self> {
+        let signature = TransactionSignature::secp256k1(signer, &self.signing_hash().ok()?)?;
+
+        Some(Transaction { signature, ..self })
+    }
+
+    pub fn signed_ed25519(self, signer: &Ed25519Signer) -> Option<Self> {
+        let signature = TransactionSignature::ed25519(signer, &self.signing_hash().ok()?)?;
+
+        Some(Transaction { signature, ..self })
+    }
+
+    fn encode_unsigned(&self) -> Result<Vec<u8>, EncodingError> {
+        let mut bytes = Vec::new();
+
+        bytes.extend_from_slice(&self.chain_id.to_be_bytes());
+        bytes.extend_from_slice(&self.nonce.to_be_bytes());
+        bytes.extend_from_slice(&encode_u256(&self.recipient)?);
+        bytes.extend_from_slice(&self.value.to_be_bytes());
+        bytes.extend_from_slice(&self.fee.to_be_bytes());
+        bytes.extend_from_slice(&encode_data(&self.data)?);
+
+        Ok(bytes)
+    }
+
+    pub fn encode(&self) -> Result<Vec<u8>, EncodingError> {
+        let mut bytes = self.encode_unsigned()?;
+        bytes.extend_from_slice(&self.signature.encode()?);
+
+        Ok(bytes)
+    }
+
+    pub fn decode_from(decoder: &mut Decoder) -> Result<Self, EncodingError> {
+        let mut transaction = Transaction::new(
+            decoder.read_u64()?,
+            decoder.read_u64()?,
+            decoder.read_u256()?,
+            decoder.read_u64()?,
+            decoder.read_u64()?,
+            decoder.read_data()?,
+        );
+
+        transaction.signature = TransactionSignature::decode_from(decoder)?;
+
+        Ok(transaction)
+    }
+
+    pub fn hash(&self) -> Option<BigUint> {
+        Some(keccak256_bytes(&self.encode().ok()?))
+    }
+
+    pub fn signing_hash(&self) -> Result<BigUint, EncodingError> {
+        Ok(keccak256_bytes(&self.encode_unsigned()?))
+    }
+
+    // Address the code is deployed to, if the transaction deploys code.
+    pub fn contract_address(&self, sender: &BigUint) -> Option<BigUint> {
+        if !self.recipient.is_zero() || self.data.is_empty() {
+            return None;
+        }
+
+        crypto::contract_address(sender, self.nonce)
+    }
+
+    // Address that signed the transaction, if the signature is valid.
+    pub fn sender(&self) -> Option<BigUint> {
+        self.signature.signer(&self.signing_hash().ok()?)
+    }
+}
+
+impl TransactionSignature {
+    pub fn secp256k1(signer: &Signer, hash: &BigUint) -> Option<Self> {
+        Some(TransactionSignature::Secp256k1 {
+            signature: signer.sign(hash)?,
+        })
+    }
+
+    pub fn ed25519(signer: &Ed25519Signer, hash: &BigUint) -> Option<Self> {
+        Some(TransactionSignature::Ed25519 {
+            public_key: signer.public_key().to_vec(),
+            signature: signer.sign(hash)?.to_vec(),
+        })
+    }
+
+    pub fn encode(&self) -> Result<Vec<u8>, EncodingError> {
+        let mut bytes = Vec::new();
+
+        match self {
+            TransactionSignature::None => bytes.push(0),
+            TransactionSignature::Secp256k1 { signature } => {
+                bytes.push(1);
+                bytes.extend_from_slice(&encode_signature(signature)?);
+            }
+            TransactionSignature::Ed25519 {
+                public_key,
+                signature,
+            } => {
+                bytes.push(2);
+                bytes.extend_from_slice(&encode_bytes(public_key)?);
+                bytes.extend_from_slice(&encode_signature(signature)?);
+            }
+        }
+
+        Ok(bytes)
+    }
+
+    pub fn decode_from(decoder: &mut Decoder) -> Result<Self, EncodingError> {
+        match decoder.read_u8()? {
+            0 => Ok(TransactionSignature::None),
+            1 => Ok(TransactionSignature::Secp256k1 {
+                signature: decoder.read_signature()?,
+            }),
+            2 => Ok(TransactionSignature::Ed25519 {
+                public_key: decoder.read_bytes()?,
+                signature: decoder.read_signature()?,
+            }),
+            _ => Err(EncodingError::ValueTooLarge),
+        }
+    }
+
+    // Address whose key made this signature over `hash`, if it verifies.
+    pub fn signer(&self, hash: &BigUint) -> Option<BigUint> {
+        match self {
+            TransactionSignature::None => None,
+            TransactionSignature::Secp256k1 { signature } => crypto::recover(hash, signature),
+            TransactionSignature::Ed25519 {
+                public_key,
+                signature,
+            } => crypto::verify_ed25519(hash, public_key, signature),
+        }
+    }
+}
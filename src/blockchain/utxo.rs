@@ -0,0 +1,462 @@
+use std::{collections::BTreeMap, fmt};
+
+use num_bigint::BigUint;
+use serde_derive::{Deserialize, Serialize};
+
+use super::block::{Block, BlockHeaders};
+use super::crypto::{Ed25519Signer, Signer};
+use super::encoding::{encode_count, encode_u256, Decoder, EncodingError};
+use super::error::{BodyError, UtxoError};
+use super::serde_hex;
+use super::sparse_merkle::{self, SparseProof};
+use super::transaction::TransactionSignature;
+use crate::helpers::keccak256_bytes;
+
+// Output `index` of the transaction with id `txid`. The subsidy and fees of a
+// block are paid to output 0 of its `coinbase_id`, and genesis allocations
+// are the outputs of the coinbase id of the genesis block.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct OutPoint {
+    #[serde(with = "serde_hex::biguint")]
+    pub txid: BigUint,
+    pub index: u16,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Output {
+    #[serde(with = "serde_hex::biguint")]
+    pub owner: BigUint,
+    pub value: u64,
+}
+
+// Spends `outpoint`, signed by the owner of the output it refers to.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Input {
+    pub outpoint: OutPoint,
+    #[serde(default)]
+    pub signature: TransactionSignature,
+}
+
+// Spends whole outputs into new ones. Whatever the inputs hold beyond the
+// outputs is the fee, paid to the block's beneficiary. Every input signs the
+// transaction id, which covers the chain id, all outpoints and all outputs.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct UtxoTransaction {
+    pub chain_id: u64,
+    pub inputs: Vec<Input>,
+    pub outputs: Vec<Output>,
+}
+
+// An outpoint and its path to the `state_root` of a block, with the output
+// if the block left it unspent.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct OutputProof {
+    pub outpoint: OutPoint,
+    pub output: Option<Output>,
+    pub proof: SparseProof,
+}
+
+// Unspent outputs as of some block.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UtxoSet {
+    unspent: BTreeMap<OutPoint, Output>,
+}
+
+// The outputs a block spent, which its inputs only name. Reverting the block
+// brings them back.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BlockUndo {
+    spent: Vec<(OutPoint, Output)>,
+}
+
+// What applying a block does to the set.
+struct Transition {
+    created: Vec<(OutPoint, Output)>,
+    spent: Vec<OutPoint>,
+}
+
+impl fmt::Display for OutPoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "0x{:064x}:{}", self.txid, self.index)
+    }
+}
+
+impl OutPoint {
+    // Where the output is kept in the state tree.
+    pub fn key(&self) -> Result<BigUint, EncodingError> {
+        let mut bytes = encode_u256(&self.txid)?.to_vec();
+        bytes.extend_from_slice(&self.index.to_be_bytes());
+
+        Ok(keccak256_bytes(&bytes))
+    }
+}
+
+impl Output {
+    pub fn hash(&self) -> Result<BigUint, EncodingError> {
+        let mut bytes = encode_u256(&self.owner)?.to_vec();
+        bytes.extend_from_slice(&self.value.to_be_bytes());
+
+        Ok(keccak256_bytes(&bytes))
+    }
+}
+
+impl OutputProof {
+    // Whether the block with `headers` left the outpoint unspent with this
+    // output, or spent or never created it if there is none.
+    pub fn verify(&self, headers: &BlockHeaders) -> bool {
+        let key = match self.outpoint.key() {
+            Ok(key) => key,
+            Err(_) => return false,
+        };
+        let value = match self.output.as_ref().map(Output::hash).transpose() {
+            Ok(value) => value,
+            Err(_) => return false,
+        };
+
+        self.proof
+            .verify(headers.state_root(), &key, value.as_ref())
+    }
+}
+
+// Transaction id the rewards of `block` are paid under. It cannot be the
+// block hash, which commits to the outputs through the state root, so it is
+// the hash of the parent and number instead, which no other block on the
+// same branch shares.
+pub fn coinbase_id(block: &Block) -> Result<BigUint, EncodingError> {
+    let mut bytes = encode_u256(block.parent_hash())?.to_vec();
+    bytes.extend_from_slice(&block.number().to_be_bytes());
+
+    Ok(keccak256_bytes(&bytes))
+}
+
+impl UtxoTransaction {
+    pub fn new(chain_id: u64, spends: Vec<OutPoint>, outputs: Vec<Output>) -> Self {
+        UtxoTransaction {
+            chain_id,
+            inputs: spends
+                .into_iter()
+                .map(|outpoint| Input {
+                    outpoint,
+                    signature: TransactionSignature::None,
+                })
+                .collect(),
+            outputs,
+        }
+    }
+
+    // Signs every input with `signer`, for spending outputs it alone owns.
+    pub fn signed(self, signer: &Signer) -> Option<Self> {
+        let signature = TransactionSignature::secp256k1(signer, &self.id().ok()?)?;
+
+        Some(self.with_signature(signature))
+    }
+
+    pub fn signed_ed25519(self, signer: &Ed25519Signer) -> Option<Self> {
+        let signature = TransactionSignature::ed25519(signer, &self.id().ok()?)?;
+
+        Some(self.with_signature(signature))
+    }
+
+    fn with_signature(mut self, signature: TransactionSignature) -> Self {
+        for input in &mut self.inputs {
+            input.signature = signature.clone();
+        }
+
+        self
+    }
+
+    fn encode_unsigned(&self) -> Result<Vec<u8>, EncodingError> {
+        let mut bytes = self.chain_id.to_be_bytes().to_vec();
+
+        bytes.extend_from_slice(&encode_count(self.inputs.len())?);
+
+        for input in &self.inputs {
+            bytes.extend_from_slice(&encode_u256(&input.outpoint.txid)?);
+            bytes.extend_from_slice(&input.outpoint.index.to_be_bytes());
+        }
+
+        bytes.extend_from_slice(&encode_count(self.outputs.len())?);
+
+        for output in &self.outputs {
+            bytes.extend_from_slice(&encode_u256(&output.owner)?);
+            bytes.extend_from_slice(&output.value.to_be_bytes());
+        }
+
+        Ok(bytes)
+    }
+
+    pub fn encode(&self) -> Result<Vec<u8>, EncodingError> {
+        let mut bytes = self.encode_unsigned()?;
+
+        for input in &self.inputs {
+            bytes.extend_from_slice(&input.signature.encode()?);
+        }
+
+        Ok(bytes)
+    }
+
+    pub fn decode_from(decoder: &mut Decoder) -> Result<Self, EncodingError> {
+        let chain_id = decoder.read_u64()?;
+        let spends = (0..decoder.read_u16()?)
+            .map(|_| {
+                Ok(OutPoint {
+                    txid: decoder.read_u256()?,
+                    index: decoder.read_u16()?,
+                })
+            })
+            .collect::<Result<_, EncodingError>>()?;
+        let outputs = (0..decoder.read_u16()?)
+            .map(|_| {
+                Ok(Output {
+                    owner: decoder.read_u256()?,
+                    value: decoder.read_u64()?,
+                })
+            })
+            .collect::<Result<_, EncodingError>>()?;
+
+        let mut transaction = UtxoTransaction::new(chain_id, spends, outputs);
+
+        for input in &mut transaction.inputs {
+            input.signature = TransactionSignature::decode_from(decoder)?;
+        }
+
+        Ok(transaction)
+    }
+
+    // Leaves the signatures out, so it is what the inputs sign and cannot be
+    // changed by re-signing.
+    pub fn id(&self) -> Result<BigUint, EncodingError> {
+        Ok(keccak256_bytes(&self.encode_unsigned()?))
+    }
+
+    pub fn outpoint(&self, index: u16) -> Option<OutPoint> {
+        Some(OutPoint {
+            txid: self.id().ok()?,
+            index,
+        })
+    }
+}
+
+impl UtxoSet {
+    pub fn new(genesis: &Block, allocations: impl IntoIterator<Item = (BigUint, u64)>) -> Self {
+        let genesis_id = coinbase_id(genesis).expect("Failed to encode genesis block");
+        let unspent = allocations
+            .into_iter()
+            .zip(0..)
+            .map(|((owner, value), index)| {
+                (
+                    OutPoint {
+                        txid: genesis_id.to_owned(),
+                        index,
+                    },
+                    Output { owner, value },
+                )
+            })
+            .collect();
+
+        UtxoSet { unspent }
+    }
+
+    pub fn get(&self, outpoint: &OutPoint) -> Option<&Output> {
+        self.unspent.get(outpoint)
+    }
+
+    pub fn unspent(&self) -> impl Iterator<Item = (&OutPoint, &Output)> {
+        self.unspent.iter()
+    }
+
+    pub fn outputs_of<'a>(
+        &'a self,
+        owner: &'a BigUint,
+    ) -> impl Iterator<Item = (&'a OutPoint, &'a Output)> {
+        self.unspent
+            .iter()
+            .filter(move |(_, output)| output.owner == *owner)
+    }
+
+    pub fn balance(&self, owner: &BigUint) -> u64 {
+        self.outputs_of(owner).fold(0u64, |total, (_, output)| {
+            total.saturating_add(output.value)
+        })
+    }
+
+    fn leaves(&self) -> BTreeMap<BigUint, BigUint> {
+        self.unspent
+            .iter()
+            .map(|(outpoint, output)| {
+                (
+                    outpoint.key().expect("Unspent outpoints encode"),
+                    output.hash().expect("Unspent outputs encode"),
+                )
+            })
+            .collect()
+    }
+
+    // Root of the sparse Merkle tree over every unspent output.
+    pub fn root(&self) -> BigUint {
+        sparse_merkle::root(&self.leaves())
+    }
+
+    pub fn proof(&self, outpoint: &OutPoint) -> Option<OutputProof> {
+        Some(OutputProof {
+            outpoint: outpoint.to_owned(),
+            output: self.get(outpoint).cloned(),
+            proof: sparse_merkle::proof(&self.leaves(), &outpoint.key().ok()?),
+        })
+    }
+
+    // Whether `block` applies on top of this set, without applying it.
+    pub fn check_block(&self, block: &Block, subsidy: u64) -> Result<(), BodyError> {
+        self.transition(block, subsidy).map(|_| ())
+    }
+
+    // Spends the inputs of every transaction in `block` and adds their
+    // outputs, then pays `subsidy` and the fees to the beneficiary. Nothing is
+    // applied unless all of it is. Returns what `revert_block` needs to undo
+    // the block.
+    pub fn apply_block(&mut self, block: &Block, subsidy: u64) -> Result<BlockUndo, BodyError> {
+        let Transition { created, spent } = self.transition(block, subsidy)?;
+
+        let undo = BlockUndo {
+            spent: spent
+                .iter()
+                .filter_map(|outpoint| Some((outpoint.to_owned(), self.unspent.remove(outpoint)?)))
+                .collect(),
+        };
+
+        // Outputs the block spent itself never enter the set.
+        self.unspent.extend(
+            created
+                .into_iter()
+                .filter(|(outpoint, _)| !spent.contains(outpoint)),
+        );
+
+        Ok(undo)
+    }
+
+    // Undoes `apply_block` for a block that was applied to this set, given
+    // what applying it returned.
+    pub fn revert_block(&mut self, block: &Block, undo: &BlockUndo) {
+        if let Ok(txid) = coinbase_id(block) {
+            self.unspent.remove(&OutPoint { txid, index: 0 });
+        }
+
+        for transaction in &block.body().utxo {
+            if let Ok(txid) = transaction.id() {
+                for index in 0..transaction.outputs.len() as u16 {
+                    self.unspent.remove(&OutPoint {
+                        txid: txid.to_owned(),
+                        index,
+                    });
+                }
+            }
+        }
+
+        self.unspent.extend(undo.spent.iter().cloned());
+    }
+
+    fn transition(&self, block: &Block, subsidy: u64) -> Result<Transition, BodyError> {
+        let mut created = BTreeMap::new();
+        let mut spent = Vec::new();
+        let mut fees = 0u64;
+
+        for (index, transaction) in block.body().utxo.iter().enumerate() {
+            let txid = transaction
+                .id()
+                .map_err(|error| BodyError::Encoding { error })?;
+            let fee = self
+                .spend(&txid, transaction, &mut created, &mut spent)
+                .map_err(|error| BodyError::Utxo { index, error })?;
+
+            fees = fees.checked_add(fee).ok_or(BodyError::Utxo {
+                index,
+                error: UtxoError::Overflow,
+            })?;
+        }
+
+        let mut created: Vec<_> = created.into_iter().collect();
+        let reward = subsidy.saturating_add(fees);
+
+        if reward > 0 {
+            created.push((
+                OutPoint {
+                    txid: coinbase_id(block).map_err(|error| BodyError::Encoding { error })?,
+                    index: 0,
+                },
+                Output {
+                    owner: block.beneficiary().to_owned(),
+                    value: reward,
+                },
+            ));
+        }
+
+        Ok(Transition { created, spent })
+    }
+
+    // Checks `transaction` against the set and what the block did before it,
+    // recording what it spends and creates, and returns its fee.
+    fn spend(
+        &self,
+        txid: &BigUint,
+        transaction: &UtxoTransaction,
+        created: &mut BTreeMap<OutPoint, Output>,
+        spent: &mut Vec<OutPoint>,
+    ) -> Result<u64, UtxoError> {
+        if transaction.inputs.is_empty() {
+            return Err(UtxoError::NoInputs);
+        }
+
+        let mut available = 0u64;
+
+        for (index, input) in transaction.inputs.iter().enumerate() {
+            let outpoint = &input.outpoint;
+
+            if spent.contains(outpoint) {
+                return Err(UtxoError::DoubleSpend {
+                    outpoint: outpoint.to_owned(),
+                });
+            }
+
+            let output = created
+                .get(outpoint)
+                .or_else(|| self.unspent.get(outpoint))
+                .ok_or_else(|| UtxoError::UnknownInput {
+                    outpoint: outpoint.to_owned(),
+                })?;
+
+            if input.signature.signer(txid).as_ref() != Some(&output.owner) {
+                return Err(UtxoError::InvalidSignature { input: index });
+            }
+
+            available = available
+                .checked_add(output.value)
+                .ok_or(UtxoError::Overflow)?;
+            spent.push(outpoint.to_owned());
+        }
+
+        let required = transaction
+            .outputs
+            .iter()
+            .try_fold(0u64, |total, output| total.checked_add(output.value))
+            .ok_or(UtxoError::Overflow)?;
+
+        if available < required {
+            return Err(UtxoError::InsufficientInput {
+                available,
+                required,
+            });
+        }
+
+        for (index, output) in transaction.outputs.iter().enumerate() {
+            created.insert(
+                OutPoint {
+                    txid: txid.to_owned(),
+                    index: index as u16,
+                },
+                output.to_owned(),
+            );
+        }
+
+        Ok(available - required)
+    }
+}
@@ -0,0 +1,136 @@
+use std::{error::Error, fmt, path::PathBuf, str::FromStr};
+
+use num_bigint::BigUint;
+
+use crate::{blockchain::serde_hex::parse_hex_biguint, stratum::DEFAULT_SHARE_DIFFICULTY};
+
+pub const USAGE: &str = "\
+Usage: simple-blockchain [OPTIONS]
+
+Options:
+  --listen <ADDR>              Peer-to-peer address [default: 127.0.0.1:8080]
+  --rpc <ADDR>                 HTTP RPC address [default: 127.0.0.1:3000]
+  --stratum <ADDR>             Mining pool address [default: 127.0.0.1:3333]
+  --spec <PATH>                Chain spec [default: chainspec.json]
+  --threads <N>                Mining threads [default: available cores]
+  --miner <ADDRESS>            Address blocks pay to without a key file
+  --peers <ADDR,...>           Peers to dial at startup
+  --key-file <PATH>            Signing key; blocks pay to its address
+  --share-difficulty <N>       Lowest share difficulty the pool accepts";
+
+// Everything the node takes from its command line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Config {
+    pub listen: String,
+    pub rpc: String,
+    pub stratum: String,
+    pub spec: PathBuf,
+    // Defaults to one thread per core.
+    pub threads: Option<usize>,
+    pub miner: Option<BigUint>,
+    pub peers: Vec<String>,
+    // Holds the hex key, which stays off the command line.
+    pub key_file: Option<PathBuf>,
+    pub share_difficulty: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigError {
+    UnknownFlag(String),
+    MissingValue(String),
+    InvalidValue { flag: String, value: String },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::UnknownFlag(flag) => write!(f, "unknown flag {}", flag),
+            ConfigError::MissingValue(flag) => write!(f, "{} needs a value", flag),
+            ConfigError::InvalidValue { flag, value } => {
+                write!(f, "invalid value {:?} for {}", value, flag)
+            }
+        }
+    }
+}
+
+impl Error for ConfigError {}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            listen: "127.0.0.1:8080".to_string(),
+            rpc: "127.0.0.1:3000".to_string(),
+            stratum: "127.0.0.1:3333".to_string(),
+            spec: PathBuf::from("chainspec.json"),
+            threads: None,
+            miner: None,
+            peers: Vec::new(),
+            key_file: None,
+            share_difficulty: DEFAULT_SHARE_DIFFICULTY,
+        }
+    }
+}
+
+impl Config {
+    // `args` excludes the program name. Flags take their value either as the
+    // next argument or after `=`, and a repeated flag overrides the earlier one.
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Config, ConfigError> {
+        let mut config = Config::default();
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            if !arg.starts_with("--") {
+                return Err(ConfigError::UnknownFlag(arg));
+            }
+
+            let (flag, value) = match arg.split_once('=') {
+                Some((flag, value)) => (flag.to_string(), value.to_string()),
+                None => match args.next() {
+                    Some(value) if !value.starts_with("--") => (arg, value),
+                    _ => return Err(ConfigError::MissingValue(arg)),
+                },
+            };
+
+            match flag.as_str() {
+                "--listen" => config.listen = value,
+                "--rpc" => config.rpc = value,
+                "--stratum" => config.stratum = value,
+                "--spec" => config.spec = PathBuf::from(value),
+                "--threads" => config.threads = Some(parse(&flag, &value)?),
+                "--miner" => {
+                    config.miner =
+                        Some(parse_hex_biguint(&value).ok_or_else(|| invalid(&flag, &value))?)
+                }
+                "--peers" => {
+                    config.peers = value
+                        .split(',')
+                        .filter(|peer| !peer.is_empty())
+                        .map(str::to_string)
+                        .collect()
+                }
+                "--key-file" => config.key_file = Some(PathBuf::from(value)),
+                "--share-difficulty" => {
+                    config.share_difficulty = parse(&flag, &value)?;
+
+                    if config.share_difficulty == 0 {
+                        return Err(invalid(&flag, &value));
+                    }
+                }
+                _ => return Err(ConfigError::UnknownFlag(flag)),
+            }
+        }
+
+        Ok(config)
+    }
+}
+
+fn parse<T: FromStr>(flag: &str, value: &str) -> Result<T, ConfigError> {
+    value.parse().map_err(|_| invalid(flag, value))
+}
+
+fn invalid(flag: &str, value: &str) -> ConfigError {
+    ConfigError::InvalidValue {
+        flag: flag.to_string(),
+        value: value.to_string(),
+    }
+}
@@ -0,0 +1,208 @@
+use num_bigint::BigUint;
+use std::{collections::BTreeMap, sync::Arc};
+use tokio::sync::{watch, Notify, RwLock};
+
+pub mod bft;
+pub mod blockchain;
+use blockchain::{
+    block::{Block, BlockBody},
+    blockchain::{Blockchain, ChainUpdate},
+    error::{SealError, ValidateBlockError},
+    staking::{DoubleSign, StakingTransaction},
+    transaction::Transaction,
+    utxo::UtxoTransaction,
+};
+
+pub mod config;
+
+pub mod helpers;
+pub mod metrics;
+use metrics::Metrics;
+
+pub mod miner;
+pub mod peer;
+pub mod rpc;
+pub mod stratum;
+
+pub type SharedState = Arc<RwLock<AppState>>;
+
+#[derive(Debug)]
+pub struct AppState {
+    blockchain: Blockchain,
+    tip: watch::Sender<Option<BigUint>>,
+    metrics: Arc<Metrics>,
+    seal_requests: Arc<Notify>,
+    // Validator changes this node votes for, candidate to whether to add it.
+    proposals: BTreeMap<BigUint, bool>,
+    // Transactions, staking transactions and double-sign evidence waiting
+    // for a block.
+    pending: BlockBody,
+}
+
+impl Default for AppState {
+    fn default() -> Self {
+        AppState::new(Blockchain::default())
+    }
+}
+
+impl AppState {
+    pub fn new(blockchain: Blockchain) -> Self {
+        let (tip, _) = watch::channel(blockchain.get_last_block().and_then(Block::hash));
+
+        AppState {
+            blockchain,
+            tip,
+            metrics: Arc::new(Metrics::default()),
+            seal_requests: Arc::new(Notify::new()),
+            proposals: BTreeMap::new(),
+            pending: BlockBody::default(),
+        }
+    }
+
+    pub fn blockchain(&self) -> &Blockchain {
+        &self.blockchain
+    }
+
+    // Every block entering the chain goes through here so that subscribers
+    // see head changes no matter where the block came from. Under an engine
+    // with finality only `add_committed_block` is allowed to add blocks.
+    pub fn add_block(&mut self, new_block: Block) -> Result<ChainUpdate, ValidateBlockError> {
+        if self.blockchain.spec().engine().finalizes() {
+            return Err(SealError::MissingCommit.into());
+        }
+
+        self.add_committed_block(new_block)
+    }
+
+    // Adds a block the validators are known to have committed.
+    pub fn add_committed_block(
+        &mut self,
+        new_block: Block,
+    ) -> Result<ChainUpdate, ValidateBlockError> {
+        let double_sign = self.blockchain.find_double_sign(&new_block);
+        let update = self.blockchain.add_block(new_block)?;
+
+        if let Some(evidence) = double_sign {
+            self.submit_evidence(evidence);
+        }
+
+        if update.is_reorg() {
+            self.metrics.record_reorg();
+        }
+
+        if !update.connected.is_empty() {
+            self.prune_pending();
+            self.tip
+                .send_replace(self.blockchain.get_last_block().and_then(Block::hash));
+        }
+
+        Ok(update)
+    }
+
+    pub fn subscribe_tip(&self) -> watch::Receiver<Option<BigUint>> {
+        self.tip.subscribe()
+    }
+
+    pub fn metrics(&self) -> Arc<Metrics> {
+        Arc::clone(&self.metrics)
+    }
+
+    // Wakes the miner of an engine that only seals on demand.
+    pub fn request_seal(&self) {
+        self.seal_requests.notify_one();
+    }
+
+    pub fn seal_requests(&self) -> Arc<Notify> {
+        Arc::clone(&self.seal_requests)
+    }
+
+    pub fn propose(&mut self, candidate: BigUint, authorize: bool) {
+        self.proposals.insert(candidate, authorize);
+    }
+
+    pub fn discard(&mut self, candidate: &BigUint) {
+        self.proposals.remove(candidate);
+    }
+
+    pub fn proposals(&self) -> &BTreeMap<BigUint, bool> {
+        &self.proposals
+    }
+
+    pub fn submit_transaction(&mut self, transaction: Transaction) {
+        if !self.pending.transactions.contains(&transaction) {
+            self.pending.transactions.push(transaction);
+        }
+    }
+
+    pub fn submit_utxo(&mut self, transaction: UtxoTransaction) {
+        if !self.pending.utxo.contains(&transaction) {
+            self.pending.utxo.push(transaction);
+        }
+    }
+
+    pub fn submit_staking(&mut self, transaction: StakingTransaction) {
+        if !self.pending.staking.contains(&transaction) {
+            self.pending.staking.push(transaction);
+        }
+    }
+
+    pub fn submit_evidence(&mut self, evidence: DoubleSign) {
+        let offender = evidence.offender();
+
+        if offender.is_some()
+            && !self
+                .pending
+                .evidence
+                .iter()
+                .any(|pending| pending.offender() == offender)
+        {
+            self.pending.evidence.push(evidence);
+        }
+    }
+
+    pub fn pending(&self) -> &BlockBody {
+        &self.pending
+    }
+
+    // Drops what the new head has already included or made invalid.
+    fn prune_pending(&mut self) {
+        match self.blockchain.state() {
+            Some(state) => self.pending.transactions.retain(|transaction| {
+                transaction
+                    .sender()
+                    .is_some_and(|sender| transaction.nonce >= state.nonce(&sender))
+            }),
+            None => self.pending.transactions.clear(),
+        }
+
+        match self.blockchain.utxos() {
+            Some(utxos) => self.pending.utxo.retain(|transaction| {
+                transaction
+                    .inputs
+                    .iter()
+                    .all(|input| utxos.get(&input.outpoint).is_some())
+            }),
+            None => self.pending.utxo.clear(),
+        }
+
+        let stake = match self
+            .blockchain
+            .get_last_block()
+            .and_then(|head| self.blockchain.validators_at(head.number()))
+        {
+            Some(validators) => &validators.stake,
+            None => return,
+        };
+
+        self.pending.staking.retain(|transaction| {
+            transaction
+                .sender()
+                .is_some_and(|sender| transaction.nonce >= stake.nonce(&sender))
+        });
+        self.pending.evidence.retain(|evidence| {
+            evidence
+                .offender()
+                .is_some_and(|offender| !stake.is_jailed(&offender))
+        });
+    }
+}
@@ -1,31 +1,99 @@
-use std::sync::Arc;
+use std::{env, fs, process, sync::Arc, thread, time::Duration};
 use tokio::sync::RwLock;
 
-mod blockchain;
-use blockchain::blockchain::Blockchain;
+use simple_blockchain::{
+    bft::BftNode,
+    blockchain::{
+        blockchain::Blockchain, chain_spec::ChainSpec, consensus::ConsensusSpec, crypto::Signer,
+    },
+    config::{Config, USAGE},
+    miner::Miner,
+    peer::PeerManager,
+    rpc::Rpc,
+    stratum::StratumServer,
+    AppState, SharedState,
+};
 
-mod miner;
-use miner::Miner;
+#[tokio::main]
+async fn main() {
+    let config = match Config::parse(env::args().skip(1)) {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("{}\n\n{}", err, USAGE);
+            process::exit(2);
+        }
+    };
 
-mod rpc;
-use rpc::Rpc;
+    let spec = ChainSpec::load(&config.spec).expect("Failed to load chain spec");
+    let bft_timeout = match &spec.consensus {
+        ConsensusSpec::Bft { timeout_ms, .. } => Some(Duration::from_millis(*timeout_ms)),
+        _ => None,
+    };
+    let blockchain = Blockchain::new(spec).expect("Invalid chain spec");
 
-mod peer;
+    println!(
+        "Genesis hash: 0x{:064x}",
+        blockchain
+            .genesis_hash()
+            .expect("Failed to hash genesis block")
+    );
 
-type SharedState = Arc<RwLock<AppState>>;
+    let shared_state: SharedState = Arc::new(RwLock::new(AppState::new(blockchain)));
 
-#[derive(Default, Debug)]
-struct AppState {
-    blockchain: Blockchain,
-}
+    let mining_threads = config
+        .threads
+        .unwrap_or_else(|| thread::available_parallelism().map_or(1, |threads| threads.get()));
 
-#[tokio::main]
-async fn main() {
-    let shared_state: Arc<RwLock<AppState>> = SharedState::default();
+    // Blocks are signed with the key in the key file, if given, and paid to
+    // its address. The key itself stays off the command line.
+    let signer = config.key_file.as_ref().map(|path| {
+        let key = fs::read_to_string(path).expect("Failed to read key file");
+        Signer::from_hex(key.trim()).expect("Invalid signing key")
+    });
+
+    let beneficiary = match &signer {
+        Some(signer) => signer.address(),
+        None => config.miner.clone().unwrap_or_default(),
+    };
+
+    let mut miner = Miner::new(
+        Arc::clone(&shared_state),
+        mining_threads,
+        beneficiary.to_owned(),
+    );
 
-    let mut miner = Miner::new(Arc::clone(&shared_state));
-    let mut peer_manager = peer::PeerManager::new(Arc::clone(&shared_state));
-    let mut rpc = Rpc::new(Arc::clone(&shared_state));
+    if let Some(signer) = &signer {
+        miner = miner.with_signer(signer.clone());
+    }
+
+    let mut peer_manager = PeerManager::new(
+        Arc::clone(&shared_state),
+        config.listen.to_owned(),
+        config.peers.to_owned(),
+    );
+
+    // BFT validators propose and vote with the signing key.
+    if let Some(timeout) = bft_timeout {
+        let (outbound, inbound) = peer_manager.consensus_channels();
+        let mut node = BftNode::new(
+            Arc::clone(&shared_state),
+            signer,
+            timeout,
+            outbound,
+            inbound,
+        );
+
+        tokio::spawn(async move {
+            node.start().await;
+        });
+    }
+
+    let mut rpc = Rpc::new(Arc::clone(&shared_state), config.rpc.to_owned());
+    let mut stratum = StratumServer::new(
+        Arc::clone(&shared_state),
+        config.share_difficulty,
+        beneficiary,
+    );
 
     tokio::spawn(async move {
         miner.start().await;
@@ -34,11 +102,15 @@ async fn main() {
     tokio::spawn(async move {
         rpc.start().await;
     });
-    
+
     tokio::spawn(async move {
         peer_manager.start().await;
     });
 
+    tokio::spawn(async move {
+        stratum.start(&config.stratum).await;
+    });
+
     tokio::signal::ctrl_c()
         .await
         .expect("Failed to listen for Ctrl+C");
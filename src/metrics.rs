@@ -0,0 +1,40 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use serde_derive::Serialize;
+
+#[derive(Debug, Default)]
+pub struct Metrics {
+    blocks_mined: AtomicU64,
+    stale_work: AtomicU64,
+    reorgs: AtomicU64,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct MetricsSnapshot {
+    pub blocks_mined: u64,
+    pub stale_work: u64,
+    pub reorgs: u64,
+}
+
+impl Metrics {
+    pub fn record_block_mined(&self) {
+        self.blocks_mined.fetch_add(1, Ordering::Relaxed);
+    }
+
+    // Mining work abandoned because the chain tip moved underneath it.
+    pub fn record_stale_work(&self) {
+        self.stale_work.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_reorg(&self) {
+        self.reorgs.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        MetricsSnapshot {
+            blocks_mined: self.blocks_mined.load(Ordering::Relaxed),
+            stale_work: self.stale_work.load(Ordering::Relaxed),
+            reorgs: self.reorgs.load(Ordering::Relaxed),
+        }
+    }
+}
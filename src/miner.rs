@@ -1,17 +1,42 @@
 use num_bigint::BigUint;
-use std::sync::Arc;
-use tokio::sync::RwLock;
+use std::{sync::Arc, time::Duration};
+use tokio::{sync::RwLock, time};
 
-use crate::{blockchain::block::Block, AppState};
+use crate::{
+    blockchain::{consensus::Seal, crypto::Signer},
+    helpers::get_current_timestamp,
+    AppState,
+};
+
+pub mod engine;
+use engine::MiningEngine;
+
+// How long to work on one template before rebuilding it with a fresh
+// timestamp, which may also lower the difficulty.
+const TIMESTAMP_ROLL_INTERVAL: Duration = Duration::from_secs(1);
 
 pub struct Miner {
     shared_state: Arc<RwLock<AppState>>,
+    engine: MiningEngine,
+    beneficiary: BigUint,
+    // Signs every sealed block; proof of stake needs it.
+    signer: Option<Signer>,
 }
 
 impl Miner {
-    pub fn new(shared_state: Arc<RwLock<AppState>>) -> Self {
+    pub fn new(shared_state: Arc<RwLock<AppState>>, threads: usize, beneficiary: BigUint) -> Self {
         Miner {
             shared_state,
+            engine: MiningEngine::new(threads),
+            beneficiary,
+            signer: None,
+        }
+    }
+
+    pub fn with_signer(self, signer: Signer) -> Self {
+        Miner {
+            signer: Some(signer),
+            ..self
         }
     }
 
@@ -24,26 +49,91 @@ impl Miner {
     async fn mine(&mut self, state_clone: Arc<RwLock<AppState>>) -> Option<()> {
         let state = Arc::clone(&state_clone);
 
-        let block = {
-            let blockchain = &state.read().await.blockchain;
-            blockchain.get_last_block().unwrap().clone()
+        let (on_demand, seal_requests) = {
+            let app_state = state.read().await;
+
+            (
+                app_state.blockchain.spec().engine().seals_on_demand(),
+                app_state.seal_requests(),
+            )
+        };
+
+        if on_demand {
+            seal_requests.notified().await;
+        }
+
+        let (seal, pow, mut tip, metrics) = {
+            let app_state = state.read().await;
+
+            (
+                app_state.blockchain.prepare_seal(
+                    self.beneficiary.to_owned(),
+                    get_current_timestamp().unwrap(),
+                    app_state.proposals(),
+                    app_state.pending(),
+                ),
+                app_state.blockchain.spec().pow.algorithm(),
+                app_state.subscribe_tip(),
+                app_state.metrics(),
+            )
         };
 
-        match Block::mine_block(&block, BigUint::from(0u32)) {
-            Some(new_block) => match Block::validate_block(&block, &new_block) {
-                Ok(_) => {
-                    println!("{:#?}", &new_block);
+        let result = match seal {
+            Seal::Work(candidate) => {
+                let mut job = self.engine.start(candidate, pow);
 
-                    let editable = &mut state.write().await.blockchain;
-                    editable.add_block(new_block.clone());
+                tokio::select! {
+                    result = job.result() => result,
+                    _ = tip.changed() => {
+                        job.cancel();
+                        metrics.record_stale_work();
+                        return None;
+                    }
+                    _ = time::sleep(TIMESTAMP_ROLL_INTERVAL) => {
+                        job.cancel();
+                        return None;
+                    }
+                }
+            }
+            Seal::Ready { block, not_before } => {
+                let now = get_current_timestamp().unwrap();
+                let delay = Duration::from_secs(not_before.saturating_sub(now));
 
-                    Some(())
+                tokio::select! {
+                    _ = time::sleep(delay) => Some(block),
+                    _ = tip.changed() => {
+                        metrics.record_stale_work();
+                        return None;
+                    }
                 }
-                Err(_) => {
-                    println!("Error: Validation failed");
-                    None
+            }
+            Seal::None => {
+                let _ = tip.changed().await;
+                return None;
+            }
+        };
+
+        let result = match (result, &self.signer) {
+            (Some(new_block), Some(signer)) => new_block.signed(signer),
+            (result, _) => result,
+        };
+
+        match result {
+            Some(new_block) => {
+                let editable = &mut state.write().await;
+
+                match editable.add_block(new_block.clone()) {
+                    Ok(_) => {
+                        metrics.record_block_mined();
+                        println!("{:#?}", &new_block);
+                        Some(())
+                    }
+                    Err(err) => {
+                        println!("Error: Validation failed: {}", err);
+                        None
+                    }
                 }
-            },
+            }
             None => {
                 println!("Error: Mining failed");
                 None
@@ -0,0 +1,114 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+};
+
+use num_bigint::BigUint;
+use tokio::sync::oneshot;
+
+use crate::blockchain::{
+    block::{Block, NONCE_SEARCH_SPACE},
+    pow::PowAlgorithm,
+};
+
+pub struct MiningEngine {
+    threads: usize,
+}
+
+pub struct MiningJob {
+    stop: Arc<AtomicBool>,
+    result: oneshot::Receiver<Block>,
+}
+
+impl MiningEngine {
+    pub fn new(threads: usize) -> Self {
+        MiningEngine {
+            threads: threads.max(1),
+        }
+    }
+
+    // Searches `block` on dedicated OS threads. Thread `i` owns extra nonces
+    // `i`, `i + threads`, `i + 2 * threads` and so on, and tries the whole
+    // `NONCE_SEARCH_SPACE` under each of them, so threads never overlap and
+    // the search does not stop at a fixed nonce count.
+    pub fn start(&self, block: Block, pow: Box<dyn PowAlgorithm>) -> MiningJob {
+        let stop = Arc::new(AtomicBool::new(false));
+        let (tx, rx) = oneshot::channel();
+        let tx = Arc::new(Mutex::new(Some(tx)));
+
+        let target = match block.target() {
+            Some(target) => Arc::new(target),
+            None => {
+                return MiningJob { stop, result: rx };
+            }
+        };
+
+        let pow: Arc<dyn PowAlgorithm> = Arc::from(pow);
+
+        for start in 0..self.threads as u64 {
+            let block = block.clone();
+            let pow = Arc::clone(&pow);
+            let target = Arc::clone(&target);
+            let stop = Arc::clone(&stop);
+            let tx = Arc::clone(&tx);
+            let step = self.threads as u64;
+
+            thread::spawn(move || {
+                let mut extra_nonce = block.extra_nonce().wrapping_add(start);
+
+                loop {
+                    let candidate = block.clone().with_extra_nonce(extra_nonce);
+                    let header_bytes = match candidate.headers().encode() {
+                        Ok(header_bytes) => header_bytes,
+                        Err(_) => return,
+                    };
+
+                    for nonce in 0..NONCE_SEARCH_SPACE {
+                        if stop.load(Ordering::Relaxed) {
+                            return;
+                        }
+
+                        let nonce = BigUint::from(nonce);
+
+                        if let Some(hash) = pow.hash(&header_bytes, &nonce) {
+                            if target.is_met_by(&hash) && !stop.swap(true, Ordering::Relaxed) {
+                                if let Some(tx) = tx.lock().unwrap().take() {
+                                    let _ = tx.send(candidate.with_nonce(nonce));
+                                }
+                                return;
+                            }
+                        }
+                    }
+
+                    extra_nonce = match extra_nonce.checked_add(step) {
+                        Some(extra_nonce) => extra_nonce,
+                        None => return,
+                    };
+                }
+            });
+        }
+
+        MiningJob { stop, result: rx }
+    }
+}
+
+impl MiningJob {
+    pub fn cancel(&self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+
+    // Resolves with the sealed block, or `None` when the job was cancelled or
+    // the extra nonce space ran out.
+    pub async fn result(&mut self) -> Option<Block> {
+        (&mut self.result).await.ok()
+    }
+}
+
+impl Drop for MiningJob {
+    fn drop(&mut self) {
+        self.cancel();
+    }
+}
@@ -1,25 +1,56 @@
-use crate::AppState;
+use crate::{bft::BftMessage, blockchain::block::Block, AppState};
 use futures_util::{SinkExt, StreamExt};
 use std::net::SocketAddr;
-use std::{env, sync::Arc, time::Duration};
+use std::{sync::Arc, time::Duration};
 use tokio::{
+    io::{AsyncRead, AsyncWrite},
     net::{TcpListener, TcpStream},
-    sync::{broadcast, RwLock},
-    time::sleep,
+    sync::{broadcast, mpsc, RwLock},
+    time,
 };
-use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{tungstenite::Message, WebSocketStream};
+
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(1);
 
 pub struct PeerManager {
     shared_state: Arc<RwLock<AppState>>,
+    addr: String,
+    // Dialed at startup, so nodes can form a mesh.
+    peers: Vec<String>,
+    // Consensus messages from the local BFT node, sent to every peer as text.
+    consensus_outbound: Option<mpsc::UnboundedReceiver<BftMessage>>,
+    // Where consensus messages from peers are delivered.
+    consensus_inbound: Option<mpsc::UnboundedSender<BftMessage>>,
 }
 
 impl PeerManager {
-    pub fn new(shared_state: Arc<RwLock<AppState>>) -> Self {
+    pub fn new(shared_state: Arc<RwLock<AppState>>, addr: String, peers: Vec<String>) -> Self {
         PeerManager {
-            shared_state: shared_state,
+            shared_state,
+            addr,
+            peers,
+            consensus_outbound: None,
+            consensus_inbound: None,
         }
     }
 
+    // Channels connecting a `BftNode` to the peers: what it sends and what
+    // it receives.
+    pub fn consensus_channels(
+        &mut self,
+    ) -> (
+        mpsc::UnboundedSender<BftMessage>,
+        mpsc::UnboundedReceiver<BftMessage>,
+    ) {
+        let (outbound, sent) = mpsc::unbounded_channel();
+        let (received, inbound) = mpsc::unbounded_channel();
+
+        self.consensus_outbound = Some(sent);
+        self.consensus_inbound = Some(received);
+
+        (outbound, inbound)
+    }
+
     pub async fn start(&mut self) {
         loop {
             self.init().await;
@@ -27,72 +58,126 @@ impl PeerManager {
     }
 
     async fn init(&mut self) {
-        let addr = env::args()
-            .nth(1)
-            .unwrap_or_else(|| "127.0.0.1:8080".to_string());
-
         let (tx, _) = broadcast::channel::<Message>(10);
 
         let ws_tx = Arc::new(tx);
 
         // Create the event loop and TCP listener we'll accept connections on.
-        let try_socket = TcpListener::bind(&addr).await;
+        let try_socket = TcpListener::bind(&self.addr).await;
         let listener = try_socket.expect("Failed to bind");
-        println!("Listening on: {}", addr);
+        println!("Listening on: {}", self.addr);
 
         tokio::spawn(PeerManager::accept_connections(
             listener,
+            Arc::clone(&self.shared_state),
             Arc::clone(&ws_tx),
+            self.consensus_inbound.clone(),
         ));
 
         tokio::spawn(PeerManager::manage_peers(
             Arc::clone(&self.shared_state),
             Arc::clone(&ws_tx),
         ));
-    }
-
-    async fn manage_peers(state_clone: Arc<RwLock<AppState>>, tx: Arc<broadcast::Sender<Message>>) {
-        let mut last_block_index = 1;
 
-        loop {
-            let amount = {
-                let blockchain = &state_clone.read().await.blockchain;
+        if let Some(sent) = self.consensus_outbound.take() {
+            tokio::spawn(PeerManager::relay_consensus(sent, Arc::clone(&ws_tx)));
+        }
 
-                blockchain.current_block_height()
-            };
+        for peer in &self.peers {
+            tokio::spawn(PeerManager::connect_peer(
+                peer.to_owned(),
+                Arc::clone(&self.shared_state),
+                Arc::clone(&ws_tx),
+                self.consensus_inbound.clone(),
+            ));
+        }
+    }
 
-            if (amount > last_block_index) {
-                let last_block = {
-                    let chain = &state_clone.read().await.blockchain;
+    async fn relay_consensus(
+        mut sent: mpsc::UnboundedReceiver<BftMessage>,
+        tx: Arc<broadcast::Sender<Message>>,
+    ) {
+        while let Some(message) = sent.recv().await {
+            match serde_json::to_string(&message) {
+                Ok(text) => {
+                    // Nobody is connected yet; the message is just lost.
+                    let _ = tx.send(Message::Text(text));
+                }
+                Err(err) => eprintln!("Error encoding consensus message: {}", err),
+            }
+        }
+    }
 
-                    Message::Text((serde_json::to_string(&chain.clone().get_last_block()).unwrap()))
-                };
+    async fn manage_peers(state_clone: Arc<RwLock<AppState>>, tx: Arc<broadcast::Sender<Message>>) {
+        let mut tip = state_clone.read().await.subscribe_tip();
 
-                last_block_index += 1;
+        while tip.changed().await.is_ok() {
+            let last_block = {
+                let chain = &state_clone.read().await.blockchain;
 
-                if let Err(err) = tx.send(last_block) {
-                    eprintln!("Error sending mined block to WebSocket clients: {}", err);
+                // Final blocks travel as consensus commits instead.
+                if chain.spec().engine().finalizes() {
+                    continue;
                 }
-            }
 
-            sleep(Duration::from_millis(500)).await;
+                Message::Binary(chain.get_last_block().unwrap().encode().unwrap())
+            };
+
+            if let Err(err) = tx.send(last_block) {
+                eprintln!("Error sending mined block to WebSocket clients: {}", err);
+            }
         }
     }
 
-    async fn accept_connections(listener: TcpListener, ws_tx: Arc<broadcast::Sender<Message>>) {
+    async fn accept_connections(
+        listener: TcpListener,
+        state_clone: Arc<RwLock<AppState>>,
+        ws_tx: Arc<broadcast::Sender<Message>>,
+        consensus: Option<mpsc::UnboundedSender<BftMessage>>,
+    ) {
         while let Ok((stream, addr)) = listener.accept().await {
             tokio::spawn(PeerManager::handle_connection(
                 stream,
                 addr,
+                Arc::clone(&state_clone),
                 Arc::clone(&ws_tx),
+                consensus.clone(),
             ));
         }
     }
 
+    async fn connect_peer(
+        peer: String,
+        state_clone: Arc<RwLock<AppState>>,
+        ws_tx: Arc<broadcast::Sender<Message>>,
+        consensus: Option<mpsc::UnboundedSender<BftMessage>>,
+    ) {
+        loop {
+            match tokio_tungstenite::connect_async(format!("ws://{}", peer)).await {
+                Ok((ws_stream, _)) => {
+                    println!("Connected to peer: {}", peer);
+
+                    PeerManager::handle_socket(
+                        ws_stream,
+                        Arc::clone(&state_clone),
+                        Arc::clone(&ws_tx),
+                        consensus.clone(),
+                    )
+                    .await;
+                }
+                Err(err) => eprintln!("Error connecting to peer {}: {}", peer, err),
+            }
+
+            time::sleep(RECONNECT_INTERVAL).await;
+        }
+    }
+
     async fn handle_connection(
         stream: TcpStream,
         addr: SocketAddr,
+        state_clone: Arc<RwLock<AppState>>,
         ws_tx: Arc<broadcast::Sender<Message>>,
+        consensus: Option<mpsc::UnboundedSender<BftMessage>>,
     ) {
         println!("Incoming TCP connection from: {:?}", addr);
 
@@ -100,21 +185,53 @@ impl PeerManager {
             .await
             .expect("Error during WebSocket handshake");
 
+        PeerManager::handle_socket(ws_stream, state_clone, ws_tx, consensus).await;
+    }
+
+    async fn handle_socket<S>(
+        ws_stream: WebSocketStream<S>,
+        state_clone: Arc<RwLock<AppState>>,
+        ws_tx: Arc<broadcast::Sender<Message>>,
+        consensus: Option<mpsc::UnboundedSender<BftMessage>>,
+    ) where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
         let (mut write, mut read) = ws_stream.split();
 
         let mut receiver = ws_tx.subscribe();
-        while let Ok(message) = receiver.recv().await {
-            // Wysłanie nowej wiadomości do klienta WebSocket
-            if let Err(err) = write.send(message).await {
-                eprintln!("Error sending message to WebSocket client: {:?}", err);
-                break;
-            }
-        }
 
-        // Prosta logika obsługi komunikatów WebSocket
-        while let Some(Ok(msg)) = read.next().await {
+        loop {
+            let msg = tokio::select! {
+                message = receiver.recv() => {
+                    match message {
+                        Ok(message) => {
+                            // Wysłanie nowej wiadomości do klienta WebSocket
+                            if let Err(err) = write.send(message).await {
+                                eprintln!("Error sending message to WebSocket client: {:?}", err);
+                                break;
+                            }
+                        }
+                        Err(broadcast::error::RecvError::Lagged(_)) => {}
+                        Err(broadcast::error::RecvError::Closed) => break,
+                    }
+                    continue;
+                }
+                msg = read.next() => match msg {
+                    Some(Ok(msg)) => msg,
+                    _ => break,
+                },
+            };
+
+            // Prosta logika obsługi komunikatów WebSocket
             match msg {
                 Message::Text(text) => {
+                    if let (Some(consensus), Ok(message)) =
+                        (&consensus, serde_json::from_str::<BftMessage>(&text))
+                    {
+                        let _ = consensus.send(message);
+                        continue;
+                    }
+
                     // Obsługa wiadomości tekstowych
                     println!("Received text message: {}", text);
 
@@ -127,7 +244,17 @@ impl PeerManager {
                 }
                 Message::Binary(data) => {
                     // Obsługa wiadomości binarnych
-                    println!("Received binary message with length: {}", data.len());
+                    match Block::decode(&data) {
+                        Ok(block) => match state_clone.write().await.add_block(block) {
+                            Ok(update) => println!(
+                                "Received block: {} connected, {} disconnected",
+                                update.connected.len(),
+                                update.disconnected.len()
+                            ),
+                            Err(err) => eprintln!("Rejected received block: {}", err),
+                        },
+                        Err(err) => eprintln!("Error decoding received block: {}", err),
+                    }
                 }
                 Message::Ping(ping_data) => {
                     // Obsługa wiadomości Ping
@@ -1,17 +1,191 @@
-use crate::{AppState, SharedState};
-use axum::{extract::State, routing::get, Router};
-use std::{env, sync::Arc};
+use crate::{
+    blockchain::{
+        block::{Block, BlockBody, BlockHeaders, InclusionProof},
+        consensus::ValidatorSet,
+        error::ValidateBlockError,
+        serde_hex,
+        staking::StakingTransaction,
+        state::{Account, AccountProof},
+        transaction::Transaction,
+        utxo::{OutPoint, Output, OutputProof, UtxoTransaction},
+    },
+    helpers::get_current_timestamp,
+    AppState, SharedState,
+};
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    routing::{get, post},
+    Json, Router,
+};
+use num_bigint::BigUint;
+use serde_derive::{Deserialize, Serialize};
+use std::sync::Arc;
 use tokio::{net::TcpListener, sync::RwLock};
 
 pub struct Rpc {
     shared_state: Arc<RwLock<AppState>>,
+    addr: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct TemplateRequest {
+    #[serde(with = "serde_hex::biguint", default)]
+    pub beneficiary: BigUint,
+}
+
+// Everything an external miner needs: grind `nonce` until the chain's
+// proof-of-work hash of header and nonce is <= target, then submit both. Once the
+// nonces run out, change `extra_nonce` at byte 16 of the header and start over.
+#[derive(Serialize, Debug)]
+pub struct BlockTemplate {
+    pub number: u32,
+    #[serde(with = "serde_hex::biguint")]
+    pub parent_hash: BigUint,
+    pub timestamp: u64,
+    pub bits: u32,
+    #[serde(with = "serde_hex::biguint")]
+    pub target: BigUint,
+    #[serde(with = "serde_hex::biguint")]
+    pub beneficiary: BigUint,
+    #[serde(with = "serde_hex::bytes")]
+    pub header: Vec<u8>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct WorkSubmission {
+    #[serde(with = "serde_hex::bytes")]
+    pub header: Vec<u8>,
+    #[serde(with = "serde_hex::biguint")]
+    pub nonce: BigUint,
+}
+
+#[derive(Serialize, Debug)]
+pub struct SubmitResult {
+    #[serde(with = "serde_hex::biguint")]
+    pub hash: BigUint,
+    pub number: u32,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct BalanceRequest {
+    #[serde(with = "serde_hex::biguint")]
+    pub address: BigUint,
+}
+
+#[derive(Serialize, Debug)]
+pub struct BalanceResult {
+    #[serde(with = "serde_hex::biguint")]
+    pub address: BigUint,
+    pub balance: u64,
+}
+
+#[derive(Serialize, Debug)]
+pub struct AccountResult {
+    #[serde(with = "serde_hex::biguint")]
+    pub address: BigUint,
+    #[serde(flatten)]
+    pub account: Account,
+}
+
+#[derive(Serialize, Debug)]
+pub struct UnspentOutput {
+    pub outpoint: OutPoint,
+    #[serde(flatten)]
+    pub output: Output,
+}
+
+// Defaults to the current head when no height is given.
+#[derive(Deserialize, Debug)]
+pub struct HeightQuery {
+    pub height: Option<u32>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct SupplyResult {
+    pub height: u32,
+    pub supply: u64,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ValidatorsResult {
+    pub height: u32,
+    #[serde(flatten)]
+    pub validators: ValidatorSet,
+}
+
+// Entry `index` of the block at `height`, counting through the body's lists
+// in the order they are encoded.
+#[derive(Deserialize, Debug)]
+pub struct ProofRequest {
+    pub height: u32,
+    pub index: usize,
+}
+
+// `proof` verifies against `headers`, whose hash is `hash`.
+#[derive(Serialize, Debug)]
+pub struct ProofResult {
+    #[serde(with = "serde_hex::biguint")]
+    pub hash: BigUint,
+    pub headers: BlockHeaders,
+    pub proof: InclusionProof,
+}
+
+// `proof` verifies against `headers`, those of the head, whose hash is `hash`.
+#[derive(Serialize, Debug)]
+pub struct StateProofResult<P> {
+    #[serde(with = "serde_hex::biguint")]
+    pub hash: BigUint,
+    pub headers: BlockHeaders,
+    pub proof: P,
+}
+
+// `signature` is the producer's signature over `hash`, and `signer` the
+// address it recovers to, which the chain only accepts if it equals
+// `beneficiary`. Both are absent for unsigned blocks.
+#[derive(Serialize, Debug)]
+pub struct ProducerResult {
+    pub height: u32,
+    #[serde(with = "serde_hex::biguint")]
+    pub hash: BigUint,
+    #[serde(with = "serde_hex::biguint")]
+    pub beneficiary: BigUint,
+    #[serde(with = "serde_hex::bytes")]
+    pub signature: Vec<u8>,
+    #[serde(with = "serde_hex::option_biguint")]
+    pub signer: Option<BigUint>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Proposal {
+    #[serde(with = "serde_hex::biguint")]
+    pub address: BigUint,
+    pub authorize: bool,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Discard {
+    #[serde(with = "serde_hex::biguint")]
+    pub address: BigUint,
+}
+
+impl BlockTemplate {
+    pub fn from_block(block: &Block) -> Option<Self> {
+        Some(BlockTemplate {
+            number: block.number(),
+            parent_hash: block.parent_hash().to_owned(),
+            timestamp: block.timestamp(),
+            bits: block.bits(),
+            target: block.target()?.value().to_owned(),
+            beneficiary: block.beneficiary().to_owned(),
+            header: block.headers().encode().ok()?,
+        })
+    }
 }
 
 impl Rpc {
-    pub fn new(shared_state: Arc<RwLock<AppState>>) -> Self {
-        Rpc {
-            shared_state: shared_state,
-        }
+    pub fn new(shared_state: Arc<RwLock<AppState>>, addr: String) -> Self {
+        Rpc { shared_state, addr }
     }
 
     pub async fn start(&mut self) {
@@ -23,17 +197,337 @@ impl Rpc {
     async fn init(&mut self) {
         let app = Router::new()
             .route("/", get(Rpc::root))
+            .route("/genesis", get(Rpc::genesis))
+            .route("/metrics", get(Rpc::metrics))
+            .route("/template", get(Rpc::template))
+            .route("/submit", post(Rpc::submit))
+            .route("/seal", post(Rpc::seal))
+            .route("/balance", get(Rpc::balance))
+            .route("/account", get(Rpc::account))
+            .route("/utxos", get(Rpc::utxos))
+            .route("/supply", get(Rpc::supply))
+            .route("/validators", get(Rpc::validators))
+            .route("/producer", get(Rpc::producer))
+            .route("/proof", get(Rpc::proof))
+            .route("/account_proof", get(Rpc::account_proof))
+            .route("/output_proof", get(Rpc::output_proof))
+            .route("/propose", post(Rpc::propose))
+            .route("/discard", post(Rpc::discard))
+            .route("/transaction", post(Rpc::transaction))
+            .route("/utxo", post(Rpc::utxo))
+            .route("/stake", post(Rpc::stake))
             .with_state(Arc::clone(&self.shared_state));
 
-        let addr = env::args()
-            .nth(2)
-            .unwrap_or_else(|| "127.0.0.1:3000".to_string());
-
-        let listener = TcpListener::bind(&addr).await.unwrap();
+        let listener = TcpListener::bind(&self.addr).await.unwrap();
         axum::serve(listener, app).await.unwrap();
     }
 
     async fn root(State(state): State<SharedState>) -> String {
         serde_json::to_string(&state.read().await.blockchain).unwrap()
     }
+
+    async fn genesis(State(state): State<SharedState>) -> String {
+        match state.read().await.blockchain.genesis_hash() {
+            Some(hash) => format!("0x{:064x}", hash),
+            None => String::new(),
+        }
+    }
+
+    async fn metrics(State(state): State<SharedState>) -> String {
+        serde_json::to_string(&state.read().await.metrics().snapshot()).unwrap()
+    }
+
+    async fn template(
+        State(state): State<SharedState>,
+        Query(request): Query<TemplateRequest>,
+    ) -> Result<Json<BlockTemplate>, StatusCode> {
+        state
+            .read()
+            .await
+            .blockchain
+            .block_template(request.beneficiary, get_current_timestamp().unwrap())
+            .as_ref()
+            .and_then(BlockTemplate::from_block)
+            .map(Json)
+            .ok_or(StatusCode::INTERNAL_SERVER_ERROR)
+    }
+
+    async fn submit(
+        State(state): State<SharedState>,
+        Json(submission): Json<WorkSubmission>,
+    ) -> Result<Json<SubmitResult>, (StatusCode, Json<ValidateBlockError>)> {
+        let rejected = |err: ValidateBlockError| (StatusCode::BAD_REQUEST, Json(err));
+
+        let headers =
+            BlockHeaders::decode(&submission.header).map_err(|err| rejected(err.into()))?;
+        let block = Block::from_parts(headers, submission.nonce);
+        let hash = block.try_hash().map_err(|err| rejected(err.into()))?;
+        let number = block.number();
+
+        state.write().await.add_block(block).map_err(rejected)?;
+
+        Ok(Json(SubmitResult { hash, number }))
+    }
+
+    // Only has an effect with an engine that seals on demand.
+    async fn seal(State(state): State<SharedState>) -> StatusCode {
+        state.read().await.request_seal();
+        StatusCode::ACCEPTED
+    }
+
+    async fn balance(
+        State(state): State<SharedState>,
+        Query(request): Query<BalanceRequest>,
+    ) -> Json<BalanceResult> {
+        let balance = state.read().await.blockchain.balance(&request.address);
+
+        Json(BalanceResult {
+            address: request.address,
+            balance,
+        })
+    }
+
+    // Not found on a chain with the UTXO ledger.
+    async fn account(
+        State(state): State<SharedState>,
+        Query(request): Query<BalanceRequest>,
+    ) -> Result<Json<AccountResult>, StatusCode> {
+        let account = state
+            .read()
+            .await
+            .blockchain
+            .state()
+            .ok_or(StatusCode::NOT_FOUND)?
+            .account(&request.address)
+            .cloned()
+            .unwrap_or_default();
+
+        Ok(Json(AccountResult {
+            address: request.address,
+            account,
+        }))
+    }
+
+    // Not found on a chain with the account ledger.
+    async fn utxos(
+        State(state): State<SharedState>,
+        Query(request): Query<BalanceRequest>,
+    ) -> Result<Json<Vec<UnspentOutput>>, StatusCode> {
+        let state = state.read().await;
+        let utxos = state.blockchain.utxos().ok_or(StatusCode::NOT_FOUND)?;
+
+        Ok(Json(
+            utxos
+                .outputs_of(&request.address)
+                .map(|(outpoint, output)| UnspentOutput {
+                    outpoint: outpoint.to_owned(),
+                    output: output.to_owned(),
+                })
+                .collect(),
+        ))
+    }
+
+    async fn supply(
+        State(state): State<SharedState>,
+        Query(request): Query<HeightQuery>,
+    ) -> Json<SupplyResult> {
+        let state = state.read().await;
+
+        let height = request.height.unwrap_or_else(|| {
+            state
+                .blockchain
+                .get_last_block()
+                .map_or(0, |block| block.number())
+        });
+
+        Json(SupplyResult {
+            height,
+            supply: state.blockchain.issued_supply(height),
+        })
+    }
+
+    async fn validators(
+        State(state): State<SharedState>,
+        Query(request): Query<HeightQuery>,
+    ) -> Result<Json<ValidatorsResult>, StatusCode> {
+        let state = state.read().await;
+
+        let height = request.height.unwrap_or_else(|| {
+            state
+                .blockchain
+                .get_last_block()
+                .map_or(0, |block| block.number())
+        });
+
+        state
+            .blockchain
+            .validators_at(height)
+            .map(|validators| {
+                Json(ValidatorsResult {
+                    height,
+                    validators: validators.to_owned(),
+                })
+            })
+            .ok_or(StatusCode::NOT_FOUND)
+    }
+
+    async fn producer(
+        State(state): State<SharedState>,
+        Query(request): Query<HeightQuery>,
+    ) -> Result<Json<ProducerResult>, StatusCode> {
+        let state = state.read().await;
+
+        let block = match request.height {
+            Some(height) => state.blockchain.get_block_by_number(height),
+            None => state.blockchain.get_last_block(),
+        }
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+        Ok(Json(ProducerResult {
+            height: block.number(),
+            hash: block.hash().ok_or(StatusCode::INTERNAL_SERVER_ERROR)?,
+            beneficiary: block.beneficiary().to_owned(),
+            signature: block.signature().to_vec(),
+            signer: block.signer(),
+        }))
+    }
+
+    async fn proof(
+        State(state): State<SharedState>,
+        Query(request): Query<ProofRequest>,
+    ) -> Result<Json<ProofResult>, StatusCode> {
+        let state = state.read().await;
+        let block = state
+            .blockchain
+            .get_block_by_number(request.height)
+            .ok_or(StatusCode::NOT_FOUND)?;
+
+        Ok(Json(ProofResult {
+            hash: block.hash().ok_or(StatusCode::INTERNAL_SERVER_ERROR)?,
+            headers: block.headers().to_owned(),
+            proof: block.proof(request.index).ok_or(StatusCode::NOT_FOUND)?,
+        }))
+    }
+
+    // Not found on a chain with the UTXO ledger.
+    async fn account_proof(
+        State(state): State<SharedState>,
+        Query(request): Query<BalanceRequest>,
+    ) -> Result<Json<StateProofResult<AccountProof>>, StatusCode> {
+        let state = state.read().await;
+        let proof = state
+            .blockchain
+            .state()
+            .ok_or(StatusCode::NOT_FOUND)?
+            .proof(&request.address);
+        let head = state
+            .blockchain
+            .get_last_block()
+            .ok_or(StatusCode::NOT_FOUND)?;
+
+        Ok(Json(StateProofResult {
+            hash: head.hash().ok_or(StatusCode::INTERNAL_SERVER_ERROR)?,
+            headers: head.headers().to_owned(),
+            proof,
+        }))
+    }
+
+    // Not found on a chain with the account ledger.
+    async fn output_proof(
+        State(state): State<SharedState>,
+        Query(outpoint): Query<OutPoint>,
+    ) -> Result<Json<StateProofResult<OutputProof>>, StatusCode> {
+        let state = state.read().await;
+        let proof = state
+            .blockchain
+            .utxos()
+            .ok_or(StatusCode::NOT_FOUND)?
+            .proof(&outpoint)
+            .ok_or(StatusCode::BAD_REQUEST)?;
+        let head = state
+            .blockchain
+            .get_last_block()
+            .ok_or(StatusCode::NOT_FOUND)?;
+
+        Ok(Json(StateProofResult {
+            hash: head.hash().ok_or(StatusCode::INTERNAL_SERVER_ERROR)?,
+            headers: head.headers().to_owned(),
+            proof,
+        }))
+    }
+
+    // Votes on `address` in every block this node seals until discarded.
+    async fn propose(
+        State(state): State<SharedState>,
+        Json(proposal): Json<Proposal>,
+    ) -> StatusCode {
+        state
+            .write()
+            .await
+            .propose(proposal.address, proposal.authorize);
+        StatusCode::ACCEPTED
+    }
+
+    async fn discard(State(state): State<SharedState>, Json(discard): Json<Discard>) -> StatusCode {
+        state.write().await.discard(&discard.address);
+        StatusCode::ACCEPTED
+    }
+
+    // Queues a signed transaction for this chain for the blocks this node
+    // seals.
+    async fn transaction(
+        State(state): State<SharedState>,
+        Json(transaction): Json<Transaction>,
+    ) -> StatusCode {
+        let mut state = state.write().await;
+        let body = BlockBody {
+            transactions: vec![transaction.to_owned()],
+            ..BlockBody::default()
+        };
+
+        if Block::verify_transactions(&body, state.blockchain.spec()).is_err() {
+            return StatusCode::BAD_REQUEST;
+        }
+
+        state.submit_transaction(transaction);
+        StatusCode::ACCEPTED
+    }
+
+    // Queues a signed UTXO transaction for the blocks this node produces.
+    async fn utxo(
+        State(state): State<SharedState>,
+        Json(transaction): Json<UtxoTransaction>,
+    ) -> StatusCode {
+        let mut state = state.write().await;
+        let body = BlockBody {
+            utxo: vec![transaction.to_owned()],
+            ..BlockBody::default()
+        };
+
+        if Block::verify_transactions(&body, state.blockchain.spec()).is_err() {
+            return StatusCode::BAD_REQUEST;
+        }
+
+        state.submit_utxo(transaction);
+        StatusCode::ACCEPTED
+    }
+
+    // Queues a signed staking transaction for the blocks this node proposes.
+    async fn stake(
+        State(state): State<SharedState>,
+        Json(transaction): Json<StakingTransaction>,
+    ) -> StatusCode {
+        let mut state = state.write().await;
+        let body = BlockBody {
+            staking: vec![transaction.to_owned()],
+            ..BlockBody::default()
+        };
+
+        if Block::verify_transactions(&body, state.blockchain.spec()).is_err() {
+            return StatusCode::BAD_REQUEST;
+        }
+
+        state.submit_staking(transaction);
+        StatusCode::ACCEPTED
+    }
 }
@@ -0,0 +1,416 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex},
+};
+
+use num_bigint::BigUint;
+use serde_derive::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::{tcp::OwnedWriteHalf, TcpListener, TcpStream},
+    sync::RwLock,
+};
+
+use crate::{
+    blockchain::{block::Block, serde_hex, serde_hex::parse_hex_biguint, target::Target},
+    helpers::get_current_timestamp,
+    AppState,
+};
+
+// Line-delimited JSON-RPC in the spirit of Stratum. Clients call
+// `mining.subscribe`, `mining.authorize` and optionally
+// `mining.suggest_difficulty` as `[difficulty, worker]`, receive
+// `mining.notify` jobs, and send solved shares back with `mining.submit` as
+// `[worker, job_id, nonce]`, optionally followed by the extra nonce the worker
+// put in the header once it ran through every nonce. A share only has to meet
+// its worker's share target; shares that also meet the block target are added
+// to the chain.
+//
+// Every worker starts at the pool's share difficulty and may only suggest a
+// higher one, which the server answers with the difficulty it settled on.
+// Jobs carry the easiest share target among the connection's workers.
+
+pub const DEFAULT_SHARE_DIFFICULTY: u32 = 1000;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct StratumMessage {
+    pub id: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub method: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub params: Vec<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct StratumJob {
+    pub job_id: u64,
+    #[serde(with = "serde_hex::bytes")]
+    pub header: Vec<u8>,
+    #[serde(with = "serde_hex::biguint")]
+    pub target: BigUint,
+    #[serde(with = "serde_hex::biguint")]
+    pub share_target: BigUint,
+    pub clean: bool,
+}
+
+#[derive(Serialize, Debug, Default, Clone)]
+pub struct WorkerStats {
+    pub accepted: u64,
+    pub rejected: u64,
+    pub blocks: u64,
+}
+
+pub struct StratumServer {
+    shared_state: Arc<RwLock<AppState>>,
+    share_difficulty: BigUint,
+    beneficiary: BigUint,
+    workers: Arc<Mutex<HashMap<String, WorkerStats>>>,
+}
+
+struct Session {
+    shared_state: Arc<RwLock<AppState>>,
+    workers: Arc<Mutex<HashMap<String, WorkerStats>>>,
+    // Share difficulty of each worker authorized on this connection.
+    authorized: HashMap<String, BigUint>,
+    subscribed: bool,
+    // The pool's minimum, which new workers start at.
+    share_difficulty: BigUint,
+    beneficiary: BigUint,
+    jobs: HashMap<u64, Block>,
+    submitted: HashSet<(u64, u64, BigUint)>,
+    next_job_id: u64,
+}
+
+impl StratumServer {
+    // Blocks found by workers pay `beneficiary`, the pool's own address.
+    pub fn new(
+        shared_state: Arc<RwLock<AppState>>,
+        share_difficulty: u32,
+        beneficiary: BigUint,
+    ) -> Self {
+        StratumServer {
+            shared_state,
+            share_difficulty: BigUint::from(share_difficulty),
+            beneficiary,
+            workers: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    pub fn workers(&self) -> HashMap<String, WorkerStats> {
+        self.workers.lock().unwrap().clone()
+    }
+
+    pub async fn start(&mut self, addr: &str) {
+        let listener = TcpListener::bind(addr)
+            .await
+            .expect("Failed to bind stratum server");
+        println!("Stratum listening on: {}", addr);
+
+        self.serve(listener).await;
+    }
+
+    pub async fn serve(&self, listener: TcpListener) {
+        while let Ok((stream, addr)) = listener.accept().await {
+            println!("Stratum connection from: {:?}", addr);
+
+            let session = Session {
+                shared_state: Arc::clone(&self.shared_state),
+                workers: Arc::clone(&self.workers),
+                authorized: HashMap::new(),
+                subscribed: false,
+                share_difficulty: self.share_difficulty.to_owned(),
+                beneficiary: self.beneficiary.to_owned(),
+                jobs: HashMap::new(),
+                submitted: HashSet::new(),
+                next_job_id: 0,
+            };
+
+            tokio::spawn(session.run(stream));
+        }
+    }
+}
+
+impl Session {
+    async fn run(mut self, stream: TcpStream) {
+        let (read, mut write) = stream.into_split();
+        let mut lines = BufReader::new(read).lines();
+        let mut tip = self.shared_state.read().await.subscribe_tip();
+
+        loop {
+            tokio::select! {
+                line = lines.next_line() => {
+                    let line = match line {
+                        Ok(Some(line)) => line,
+                        _ => break,
+                    };
+
+                    let request: StratumMessage = match serde_json::from_str(&line) {
+                        Ok(request) => request,
+                        Err(err) => {
+                            eprintln!("Invalid stratum message: {}", err);
+                            break;
+                        }
+                    };
+
+                    let subscribed = self.subscribed;
+                    let response = self.handle(request).await;
+
+                    if send(&mut write, &response).await.is_err() {
+                        break;
+                    }
+
+                    if !subscribed && self.subscribed && self.notify(&mut write, true).await.is_err() {
+                        break;
+                    }
+                }
+                changed = tip.changed() => {
+                    if changed.is_err() {
+                        break;
+                    }
+
+                    if self.subscribed && self.notify(&mut write, true).await.is_err() {
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    async fn handle(&mut self, request: StratumMessage) -> StratumMessage {
+        let result = match request.method.as_deref() {
+            Some("mining.subscribe") => {
+                self.subscribed = true;
+                Ok(json!(true))
+            }
+            Some("mining.authorize") => match request.params.first().and_then(Value::as_str) {
+                Some(worker) => {
+                    self.authorized
+                        .entry(worker.to_string())
+                        .or_insert_with(|| self.share_difficulty.to_owned());
+                    self.workers
+                        .lock()
+                        .unwrap()
+                        .entry(worker.to_string())
+                        .or_default();
+                    Ok(json!(true))
+                }
+                None => Err("missing worker name".to_string()),
+            },
+            Some("mining.suggest_difficulty") => self.suggest_difficulty(&request.params),
+            Some("mining.submit") => self.submit(&request.params).await,
+            _ => Err("unknown method".to_string()),
+        };
+
+        match result {
+            Ok(result) => StratumMessage {
+                id: request.id,
+                method: None,
+                params: Vec::new(),
+                result: Some(result),
+                error: None,
+            },
+            Err(error) => StratumMessage {
+                id: request.id,
+                method: None,
+                params: Vec::new(),
+                result: Some(json!(false)),
+                error: Some(error),
+            },
+        }
+    }
+
+    // Raises a worker's share difficulty; suggestions below the pool's
+    // minimum are clamped to it.
+    fn suggest_difficulty(&mut self, params: &[Value]) -> Result<Value, String> {
+        let difficulty = params
+            .first()
+            .and_then(Value::as_u64)
+            .ok_or("invalid difficulty")?;
+        let worker = params
+            .get(1)
+            .and_then(Value::as_str)
+            .ok_or("missing worker name")?;
+
+        let minimum = u64::try_from(&self.share_difficulty).unwrap_or(u64::MAX);
+        let difficulty = difficulty.max(minimum);
+
+        let share_difficulty = self
+            .authorized
+            .get_mut(worker)
+            .ok_or("unauthorized worker")?;
+        *share_difficulty = BigUint::from(difficulty);
+
+        Ok(json!(difficulty))
+    }
+
+    async fn submit(&mut self, params: &[Value]) -> Result<Value, String> {
+        let worker = params
+            .first()
+            .and_then(Value::as_str)
+            .ok_or("missing worker name")?
+            .to_string();
+
+        let share_difficulty = match self.authorized.get(&worker) {
+            Some(share_difficulty) => share_difficulty.to_owned(),
+            None => return Err("unauthorized worker".to_string()),
+        };
+
+        let result = self.check_share(params, &share_difficulty).await;
+
+        let mut workers = self.workers.lock().unwrap();
+        let stats = workers.entry(worker).or_default();
+
+        match result {
+            Ok(found_block) => {
+                stats.accepted += 1;
+                if found_block {
+                    stats.blocks += 1;
+                }
+                Ok(json!(true))
+            }
+            Err(err) => {
+                stats.rejected += 1;
+                Err(err)
+            }
+        }
+    }
+
+    // Returns whether the share also solved a block.
+    async fn check_share(
+        &mut self,
+        params: &[Value],
+        share_difficulty: &BigUint,
+    ) -> Result<bool, String> {
+        let job_id = params
+            .get(1)
+            .and_then(Value::as_u64)
+            .ok_or("missing job id")?;
+        let nonce = params
+            .get(2)
+            .and_then(Value::as_str)
+            .and_then(parse_hex_biguint)
+            .ok_or("invalid nonce")?;
+        let extra_nonce = match params.get(3) {
+            Some(extra_nonce) => extra_nonce.as_u64().ok_or("invalid extra nonce")?,
+            None => 0,
+        };
+
+        let block = self
+            .jobs
+            .get(&job_id)
+            .ok_or("unknown or stale job")?
+            .to_owned()
+            .with_extra_nonce(extra_nonce);
+
+        if !self
+            .submitted
+            .insert((job_id, extra_nonce, nonce.to_owned()))
+        {
+            return Err("duplicate share".to_string());
+        }
+
+        let header = block.headers().encode().map_err(|err| err.to_string())?;
+        let pow = self
+            .shared_state
+            .read()
+            .await
+            .blockchain
+            .spec()
+            .pow
+            .algorithm();
+        let hash = pow.hash(&header, &nonce).ok_or("invalid nonce")?;
+
+        if !share_target(&block, share_difficulty).is_met_by(&hash) {
+            return Err("share above target".to_string());
+        }
+
+        match block.target() {
+            Some(target) if target.is_met_by(&hash) => {
+                let block = block.with_nonce(nonce);
+
+                match self.shared_state.write().await.add_block(block) {
+                    Ok(_) => Ok(true),
+                    Err(err) => Err(err.to_string()),
+                }
+            }
+            _ => Ok(false),
+        }
+    }
+
+    async fn notify(&mut self, write: &mut OwnedWriteHalf, clean: bool) -> std::io::Result<()> {
+        let block = self.shared_state.read().await.blockchain.block_template(
+            self.beneficiary.to_owned(),
+            get_current_timestamp().unwrap(),
+        );
+
+        let block = match block {
+            Some(block) => block,
+            None => return Ok(()),
+        };
+
+        let (header, target) = match (block.headers().encode(), block.target()) {
+            (Ok(header), Some(target)) => (header, target),
+            _ => return Ok(()),
+        };
+
+        if clean {
+            self.jobs.clear();
+            self.submitted.clear();
+        }
+
+        self.next_job_id += 1;
+
+        let job = StratumJob {
+            job_id: self.next_job_id,
+            header,
+            target: target.value().to_owned(),
+            share_target: share_target(&block, self.easiest_difficulty())
+                .value()
+                .to_owned(),
+            clean,
+        };
+
+        self.jobs.insert(job.job_id, block);
+
+        let notification = StratumMessage {
+            id: None,
+            method: Some("mining.notify".to_string()),
+            params: vec![serde_json::to_value(&job).unwrap()],
+            result: None,
+            error: None,
+        };
+
+        send(write, &notification).await
+    }
+
+    // Lowest share difficulty among the connection's workers, so that jobs
+    // announce a target every worker's shares can meet.
+    fn easiest_difficulty(&self) -> &BigUint {
+        self.authorized
+            .values()
+            .min()
+            .unwrap_or(&self.share_difficulty)
+    }
+}
+
+// Share target for a job, never harder than the block target itself.
+fn share_target(block: &Block, share_difficulty: &BigUint) -> Target {
+    let share_target = Target::from_difficulty(share_difficulty);
+
+    match block.target() {
+        Some(target) if target > share_target => target,
+        _ => share_target,
+    }
+}
+
+async fn send(write: &mut OwnedWriteHalf, message: &StratumMessage) -> std::io::Result<()> {
+    let mut line = serde_json::to_vec(message).unwrap();
+    line.push(b'\n');
+
+    write.write_all(&line).await
+}
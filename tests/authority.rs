@@ -0,0 +1,127 @@
+// Proof-of-authority seals are proven by the producer's signature: naming a
+// signer as beneficiary is not enough to produce its blocks or to vote on
+// who the signers are.
+
+mod common;
+
+use std::collections::BTreeMap;
+
+use num_bigint::BigUint;
+
+use simple_blockchain::blockchain::{
+    block::{Block, BlockBody},
+    blockchain::Blockchain,
+    chain_spec::ChainSpec,
+    consensus::ConsensusSpec,
+    crypto::Signer,
+    error::{SealError, ValidateBlockError},
+};
+
+fn authority(signers: &[&Signer]) -> Blockchain {
+    Blockchain::new(ChainSpec {
+        consensus: ConsensusSpec::Authority {
+            period: 1,
+            signers: signers.iter().map(|signer| signer.address()).collect(),
+        },
+        ..ChainSpec::default()
+    })
+    .unwrap()
+}
+
+// Unsigned block by `producer`, which must be allowed to seal the next one.
+fn template(chain: &Blockchain, producer: &BigUint) -> Block {
+    common::seal(chain, producer, &BlockBody::default())
+}
+
+// Same, voting on one of `proposals`.
+fn vote(chain: &Blockchain, producer: &BigUint, proposals: &BTreeMap<BigUint, bool>) -> Block {
+    common::propose(chain, producer, proposals, &BlockBody::default())
+}
+
+#[test]
+fn seals_need_the_signers_signature() {
+    let alice = Signer::from_bytes(&[1; 32]).unwrap();
+    let bob = Signer::from_bytes(&[2; 32]).unwrap();
+    let mallory = Signer::from_bytes(&[3; 32]).unwrap();
+
+    let mut chain = authority(&[&alice, &bob]);
+
+    // Block 1 is bob's turn, which naming him does not give anyone.
+    let unsigned = template(&chain, &bob.address());
+    assert_eq!(
+        chain.add_block(unsigned.clone()).err(),
+        Some(ValidateBlockError::Seal(SealError::MissingSignature))
+    );
+
+    assert_eq!(
+        chain
+            .add_block(unsigned.clone().signed(&mallory).unwrap())
+            .err(),
+        Some(ValidateBlockError::Seal(SealError::InvalidSignature {
+            beneficiary: bob.address(),
+            signer: Some(mallory.address()),
+        }))
+    );
+
+    chain
+        .add_block(unsigned.signed(&bob).unwrap())
+        .expect("Signed block rejected");
+
+    // Signing its own blocks does not make an outsider a signer.
+    let parent = chain.get_last_block().unwrap().timestamp();
+    let outsider = chain
+        .block_template(mallory.address(), parent + 1)
+        .unwrap()
+        .signed(&mallory)
+        .unwrap();
+    assert_eq!(
+        chain.add_block(outsider).err(),
+        Some(ValidateBlockError::Seal(SealError::UnauthorizedSigner {
+            signer: mallory.address(),
+        }))
+    );
+
+    chain
+        .add_block(template(&chain, &alice.address()).signed(&alice).unwrap())
+        .expect("Signed block rejected");
+    assert_eq!(chain.get_last_block().unwrap().number(), 2);
+}
+
+#[test]
+fn votes_need_the_signers_signature() {
+    let alice = Signer::from_bytes(&[1; 32]).unwrap();
+    let bob = Signer::from_bytes(&[2; 32]).unwrap();
+    let carol = Signer::from_bytes(&[3; 32]).unwrap();
+
+    let mut chain = authority(&[&alice, &bob]);
+    let proposals = BTreeMap::from([(carol.address(), true)]);
+    let signers = |chain: &Blockchain| {
+        let head = chain.get_last_block().unwrap().number();
+        chain.validators_at(head).unwrap().validators.len()
+    };
+
+    // A vote in bob's name that bob did not sign is turned away whole.
+    let forged = vote(&chain, &bob.address(), &proposals);
+    assert!(!forged.headers().extra_data().is_empty());
+    assert_eq!(
+        chain.add_block(forged.clone()).err(),
+        Some(ValidateBlockError::Seal(SealError::MissingSignature))
+    );
+
+    chain.add_block(forged.signed(&bob).unwrap()).unwrap();
+    assert_eq!(signers(&chain), 2);
+
+    // A second signed vote makes a majority.
+    chain
+        .add_block(
+            vote(&chain, &alice.address(), &proposals)
+                .signed(&alice)
+                .unwrap(),
+        )
+        .unwrap();
+    assert_eq!(signers(&chain), 3);
+
+    chain
+        .add_block(template(&chain, &carol.address()).signed(&carol).unwrap())
+        .expect("New signer's block rejected");
+}
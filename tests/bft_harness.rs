@@ -0,0 +1,409 @@
+// Runs four BFT validators in one process under each kind of fault and checks
+// that they never commit different blocks at the same height. Messages go
+// through a router instead of `PeerManager`, so faults can be injected
+// between any two validators.
+
+use std::{collections::HashSet, sync::Arc, time::Duration};
+
+use num_bigint::BigUint;
+use tokio::{
+    sync::{mpsc, RwLock},
+    time::{self, Instant},
+};
+
+use simple_blockchain::{
+    bft::{BftMessage, BftNode, BftProposal, BftVote, VoteKind},
+    blockchain::{
+        blockchain::Blockchain, chain_spec::ChainSpec, consensus::ConsensusSpec, crypto::Signer,
+    },
+    AppState, SharedState,
+};
+
+const VALIDATORS: usize = 4;
+const TARGET_HEIGHT: u32 = 8;
+const TIMEOUT: Duration = Duration::from_millis(100);
+const DEADLINE: Duration = Duration::from_secs(60);
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+#[derive(Debug, Clone, Copy)]
+enum Fault {
+    None,
+    // The validator at this index never sends or receives anything.
+    Crashed(usize),
+    // Cut off from everyone until `heal_after`, then left to catch up.
+    Partitioned {
+        validator: usize,
+        heal_after: Duration,
+    },
+    // Proposes a different block to every other validator.
+    Equivocating(usize),
+    // Equivocates, and backs every variant with votes and commits claiming
+    // to come from the other validators, signed with its own key.
+    ForgingVotes(usize),
+    // Equivocates, and backs every variant with a commit holding precommits
+    // really signed by every validator, but split over two rounds so that
+    // neither round has a quorum.
+    SplittingRounds(usize),
+}
+
+impl Fault {
+    fn validator(&self) -> Option<usize> {
+        match *self {
+            Fault::None => None,
+            Fault::Crashed(validator)
+            | Fault::Partitioned { validator, .. }
+            | Fault::Equivocating(validator)
+            | Fault::ForgingVotes(validator)
+            | Fault::SplittingRounds(validator) => Some(validator),
+        }
+    }
+
+    fn is_cut_off(&self, validator: usize, elapsed: Duration) -> bool {
+        match *self {
+            Fault::Crashed(crashed) => validator == crashed,
+            Fault::Partitioned {
+                validator: partitioned,
+                heal_after,
+            } => validator == partitioned && elapsed < heal_after,
+            _ => false,
+        }
+    }
+
+    fn equivocates(&self, validator: usize) -> bool {
+        matches!(
+            *self,
+            Fault::Equivocating(faulty) | Fault::ForgingVotes(faulty) | Fault::SplittingRounds(faulty)
+                if faulty == validator
+        )
+    }
+
+    fn forges(&self, validator: usize) -> bool {
+        matches!(*self, Fault::ForgingVotes(faulty) if faulty == validator)
+    }
+
+    fn splits_rounds(&self, validator: usize) -> bool {
+        matches!(*self, Fault::SplittingRounds(faulty) if faulty == validator)
+    }
+}
+
+#[derive(Debug)]
+struct HarnessReport {
+    // Height of each validator's head.
+    heights: Vec<u32>,
+    // Heights at which two validators hold different blocks.
+    conflicts: Vec<u32>,
+    // Validator the fault was injected into.
+    faulty: Option<usize>,
+}
+
+impl HarnessReport {
+    // Whether every correct validator got to `height`.
+    fn reached(&self, height: u32) -> bool {
+        self.heights
+            .iter()
+            .enumerate()
+            .filter(|(validator, _)| Some(*validator) != self.faulty)
+            .all(|(_, reached)| *reached >= height)
+    }
+}
+
+fn signers() -> Vec<Signer> {
+    (1..=VALIDATORS as u8)
+        .map(|key| Signer::from_bytes(&[key; 32]).unwrap())
+        .collect()
+}
+
+fn spec(signers: &[Signer]) -> ChainSpec {
+    ChainSpec {
+        name: "bft-harness".to_string(),
+        consensus: ConsensusSpec::Bft {
+            validators: signers.iter().map(Signer::address).collect(),
+            timeout_ms: TIMEOUT.as_millis() as u64,
+        },
+        ..ChainSpec::default()
+    }
+}
+
+// `proposal` with its block swapped for one only validator `to` gets, signed
+// again by the faulty proposer so that it still checks out.
+fn variant(proposal: &BftProposal, to: usize, signer: &Signer) -> Option<BftProposal> {
+    let block = proposal
+        .block
+        .clone()
+        .with_extra_data(vec![to as u8])
+        .signed(signer)?;
+
+    BftProposal::new(
+        proposal.height,
+        proposal.round,
+        proposal.valid_round,
+        block,
+        signer,
+    )
+}
+
+// `vote` in the name of `validator`, signed by someone else.
+fn forged(vote: &BftVote, validator: &BigUint) -> BftVote {
+    BftVote {
+        validator: validator.to_owned(),
+        ..vote.clone()
+    }
+}
+
+// Precommits for `proposal` signed by each of `signers`, the first half in
+// the proposal's round and the rest in the next one.
+fn split(proposal: &BftProposal, signers: &[Signer]) -> Vec<BftVote> {
+    signers
+        .iter()
+        .enumerate()
+        .filter_map(|(index, signer)| {
+            BftVote::new(
+                VoteKind::Precommit,
+                proposal.height,
+                proposal.round + (2 * index >= signers.len()) as u32,
+                proposal.block.hash(),
+                signer,
+            )
+        })
+        .collect()
+}
+
+// What the faulty validator `from` sends `to` in place of `message`.
+fn tamper(
+    message: &BftMessage,
+    from: usize,
+    to: usize,
+    signers: &[Signer],
+    fault: Fault,
+) -> Vec<BftMessage> {
+    let signer = &signers[from];
+    let validators: Vec<_> = signers.iter().map(Signer::address).collect();
+    let forges = fault.forges(from);
+
+    let proposal = match message {
+        BftMessage::Proposal(proposal) => proposal,
+        BftMessage::Vote(vote) if forges => {
+            return validators
+                .iter()
+                .map(|validator| BftMessage::Vote(forged(vote, validator)))
+                .collect();
+        }
+        _ => return vec![message.clone()],
+    };
+
+    let proposal = match variant(proposal, to, signer) {
+        Some(proposal) => proposal,
+        None => return vec![message.clone()],
+    };
+
+    let mut messages = vec![BftMessage::Proposal(proposal.clone())];
+
+    // A commit of the variant with a precommit quorum that only the faulty
+    // validator signed.
+    if forges {
+        let precommit = BftVote::new(
+            VoteKind::Precommit,
+            proposal.height,
+            proposal.round,
+            proposal.block.hash(),
+            signer,
+        );
+
+        if let Some(precommit) = precommit {
+            messages.push(BftMessage::Commit {
+                block: proposal.block.clone(),
+                precommits: validators
+                    .iter()
+                    .map(|validator| forged(&precommit, validator))
+                    .collect(),
+            });
+        }
+    }
+
+    // A commit of the variant whose precommits add up to a quorum only
+    // across rounds.
+    if fault.splits_rounds(from) {
+        messages.push(BftMessage::Commit {
+            precommits: split(&proposal, signers),
+            block: proposal.block,
+        });
+    }
+
+    messages
+}
+
+// Runs the validators with `fault` injected until the correct ones reach
+// `TARGET_HEIGHT` or `DEADLINE` passes, then compares their chains. A
+// partitioned validator must also have caught up.
+async fn run(fault: Fault) -> HarnessReport {
+    let signers = signers();
+    let spec = spec(&signers);
+    let (router_tx, mut router_rx) = mpsc::unbounded_channel::<(usize, BftMessage)>();
+
+    let mut states: Vec<SharedState> = Vec::new();
+    let mut inboxes = Vec::new();
+    let mut tasks = Vec::new();
+
+    for (validator, signer) in signers.iter().enumerate() {
+        let state = Arc::new(RwLock::new(AppState::new(
+            Blockchain::new(spec.clone()).unwrap(),
+        )));
+        let (outbound, mut sent) = mpsc::unbounded_channel();
+        let (inbox, inbound) = mpsc::unbounded_channel();
+
+        let mut node = BftNode::new(
+            Arc::clone(&state),
+            Some(signer.clone()),
+            TIMEOUT,
+            outbound,
+            inbound,
+        );
+
+        let router = router_tx.clone();
+        tasks.push(tokio::spawn(async move {
+            while let Some(message) = sent.recv().await {
+                if router.send((validator, message)).is_err() {
+                    break;
+                }
+            }
+        }));
+        tasks.push(tokio::spawn(async move { node.start().await }));
+
+        states.push(state);
+        inboxes.push(inbox);
+    }
+
+    let started = Instant::now();
+
+    tasks.push(tokio::spawn(async move {
+        while let Some((from, message)) = router_rx.recv().await {
+            let elapsed = started.elapsed();
+
+            if fault.is_cut_off(from, elapsed) {
+                continue;
+            }
+
+            for (to, inbox) in inboxes.iter().enumerate() {
+                if to == from || fault.is_cut_off(to, elapsed) {
+                    continue;
+                }
+
+                let messages = if fault.equivocates(from) {
+                    tamper(&message, from, to, &signers, fault)
+                } else {
+                    vec![message.clone()]
+                };
+
+                for message in messages {
+                    let _ = inbox.send(message);
+                }
+            }
+        }
+    }));
+
+    let heights = loop {
+        let mut heights = Vec::with_capacity(VALIDATORS);
+
+        for state in &states {
+            let state = state.read().await;
+            heights.push(
+                state
+                    .blockchain()
+                    .get_last_block()
+                    .map_or(0, |block| block.number()),
+            );
+        }
+
+        let done = heights.iter().enumerate().all(|(validator, height)| {
+            *height >= TARGET_HEIGHT
+                || matches!(fault, Fault::Crashed(crashed) if crashed == validator)
+        });
+
+        if done || started.elapsed() >= DEADLINE {
+            break heights;
+        }
+
+        time::sleep(POLL_INTERVAL).await;
+    };
+
+    for task in tasks {
+        task.abort();
+    }
+
+    let mut conflicts = Vec::new();
+
+    for height in 1..=heights.iter().copied().max().unwrap_or(0) {
+        let mut hashes = HashSet::new();
+
+        for state in &states {
+            if let Some(hash) = state
+                .read()
+                .await
+                .blockchain()
+                .get_block_by_number(height)
+                .and_then(|block| block.hash())
+            {
+                hashes.insert(hash);
+            }
+        }
+
+        if hashes.len() > 1 {
+            conflicts.push(height);
+        }
+    }
+
+    HarnessReport {
+        heights,
+        conflicts,
+        faulty: fault.validator(),
+    }
+}
+
+async fn check(fault: Fault) {
+    let report = run(fault).await;
+
+    assert!(
+        report.conflicts.is_empty(),
+        "Validators disagree: {:?}",
+        report
+    );
+    assert!(
+        report.reached(TARGET_HEIGHT),
+        "Validators stalled: {:?}",
+        report
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn no_fault() {
+    check(Fault::None).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn crashed_validator() {
+    check(Fault::Crashed(1)).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn partitioned_validator() {
+    check(Fault::Partitioned {
+        validator: 2,
+        heal_after: Duration::from_secs(2),
+    })
+    .await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn equivocating_proposer() {
+    check(Fault::Equivocating(3)).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn forged_votes() {
+    check(Fault::ForgingVotes(3)).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn precommits_split_over_rounds() {
+    check(Fault::SplittingRounds(3)).await;
+}
@@ -0,0 +1,44 @@
+// Fixtures shared by the integration tests. Not every test uses all of them.
+#![allow(dead_code)]
+
+use std::collections::BTreeMap;
+
+use num_bigint::BigUint;
+
+use simple_blockchain::blockchain::{
+    block::{Block, BlockBody},
+    blockchain::Blockchain,
+    consensus::Seal,
+    state::WorldState,
+    utxo::UtxoSet,
+};
+
+// Next block on `chain`, a second after its parent, paid to `beneficiary`,
+// voting on one of `proposals` and holding what it can of `pending`. Panics
+// unless the engine seals it right away.
+pub fn propose(
+    chain: &Blockchain,
+    beneficiary: &BigUint,
+    proposals: &BTreeMap<BigUint, bool>,
+    pending: &BlockBody,
+) -> Block {
+    let parent = chain.get_last_block().unwrap().timestamp();
+
+    match chain.prepare_seal(beneficiary.to_owned(), parent + 1, proposals, pending) {
+        Seal::Ready { block, .. } => block,
+        _ => panic!("Engine did not seal"),
+    }
+}
+
+// Same, without votes.
+pub fn seal(chain: &Blockchain, beneficiary: &BigUint, pending: &BlockBody) -> Block {
+    propose(chain, beneficiary, &BTreeMap::new(), pending)
+}
+
+pub fn state(chain: &Blockchain) -> &WorldState {
+    chain.state().expect("Chain keeps no accounts")
+}
+
+pub fn utxos(chain: &Blockchain) -> &UtxoSet {
+    chain.utxos().expect("Chain keeps no outputs")
+}
@@ -0,0 +1,86 @@
+// Parses node command lines the way `main` does, without the program name.
+
+use std::path::PathBuf;
+
+use num_bigint::BigUint;
+
+use simple_blockchain::config::{Config, ConfigError};
+
+fn parse(args: &[&str]) -> Result<Config, ConfigError> {
+    Config::parse(args.iter().map(|arg| arg.to_string()))
+}
+
+#[test]
+fn flags_override_the_defaults() {
+    assert_eq!(parse(&[]).unwrap(), Config::default());
+
+    let config = parse(&[
+        "--listen",
+        "0.0.0.0:9000",
+        "--rpc=0.0.0.0:9001",
+        "--spec",
+        "specs/bft.json",
+        "--threads",
+        "2",
+        "--miner",
+        "0xbeef",
+        "--peers",
+        "10.0.0.1:8080,,10.0.0.2:8080",
+        "--key-file",
+        "node.key",
+        "--share-difficulty",
+        "64",
+        "--threads",
+        "4",
+    ])
+    .unwrap();
+
+    assert_eq!(
+        config,
+        Config {
+            listen: "0.0.0.0:9000".to_string(),
+            rpc: "0.0.0.0:9001".to_string(),
+            spec: PathBuf::from("specs/bft.json"),
+            threads: Some(4),
+            miner: Some(BigUint::from(0xbeefu32)),
+            peers: vec!["10.0.0.1:8080".to_string(), "10.0.0.2:8080".to_string()],
+            key_file: Some(PathBuf::from("node.key")),
+            share_difficulty: 64,
+            ..Config::default()
+        }
+    );
+}
+
+#[test]
+fn bad_command_lines_are_rejected() {
+    assert_eq!(
+        parse(&["127.0.0.1:8080"]),
+        Err(ConfigError::UnknownFlag("127.0.0.1:8080".to_string()))
+    );
+    assert_eq!(
+        parse(&["--port", "8080"]),
+        Err(ConfigError::UnknownFlag("--port".to_string()))
+    );
+    assert_eq!(
+        parse(&["--spec", "--threads", "2"]),
+        Err(ConfigError::MissingValue("--spec".to_string()))
+    );
+    assert_eq!(
+        parse(&["--rpc"]),
+        Err(ConfigError::MissingValue("--rpc".to_string()))
+    );
+
+    for (flag, value) in [
+        ("--threads", "many"),
+        ("--miner", "0xnothex"),
+        ("--share-difficulty", "0"),
+    ] {
+        assert_eq!(
+            parse(&[flag, value]),
+            Err(ConfigError::InvalidValue {
+                flag: flag.to_string(),
+                value: value.to_string(),
+            })
+        );
+    }
+}
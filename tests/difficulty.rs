@@ -0,0 +1,116 @@
+// Drives each difficulty algorithm over hand-built ancestors, without mining.
+
+use num_bigint::BigUint;
+
+use simple_blockchain::blockchain::{
+    block::Block,
+    difficulty::{DifficultyAlgorithm, DifficultySpec},
+    target::Target,
+};
+
+const TARGET_BLOCK_TIME: u64 = 10;
+const EPOCH_LENGTH: u32 = 4;
+const LWMA_WINDOW: usize = 6;
+
+fn block(number: u32, timestamp: u64, difficulty: u32) -> Block {
+    let bits = Target::from_difficulty(&BigUint::from(difficulty)).to_compact();
+
+    Block::new(
+        number,
+        BigUint::default(),
+        BigUint::default(),
+        bits,
+        timestamp,
+        Vec::new(),
+        BigUint::default(),
+    )
+}
+
+// Blocks 0 to `count - 1` at a steady difficulty, `solve_time` apart.
+fn chain(count: u32, solve_time: u64, difficulty: u32) -> Vec<Block> {
+    (0..count)
+        .map(|number| block(number, number as u64 * solve_time, difficulty))
+        .collect()
+}
+
+fn retarget() -> Box<dyn DifficultyAlgorithm> {
+    DifficultySpec::Retarget {
+        target_block_time: TARGET_BLOCK_TIME,
+        epoch_length: EPOCH_LENGTH,
+    }
+    .algorithm()
+}
+
+// Difficulty of the block after `ancestors` under `algorithm`, one target
+// block time after the parent.
+fn next(algorithm: &dyn DifficultyAlgorithm, ancestors: &[Block]) -> BigUint {
+    let timestamp = ancestors.last().unwrap().timestamp() + TARGET_BLOCK_TIME;
+    algorithm.next_difficulty(ancestors, timestamp)
+}
+
+#[test]
+fn retarget_holds_steady_at_the_target_block_time() {
+    let algorithm = retarget();
+
+    // Two full epochs, so the second one ends on a retarget.
+    let ancestors = chain(2 * EPOCH_LENGTH, TARGET_BLOCK_TIME, 1000);
+    let parent = ancestors.last().unwrap().difficulty();
+
+    assert_eq!((ancestors.len() as u32) % EPOCH_LENGTH, 0);
+    assert_eq!(next(algorithm.as_ref(), &ancestors), parent);
+}
+
+#[test]
+fn retarget_moves_at_most_four_times_per_epoch() {
+    let algorithm = retarget();
+
+    let slow = chain(2 * EPOCH_LENGTH, 100 * TARGET_BLOCK_TIME, 1000);
+    let parent = slow.last().unwrap().difficulty();
+    assert_eq!(next(algorithm.as_ref(), &slow), parent / 4u32);
+
+    let fast = chain(2 * EPOCH_LENGTH, 1, 1000);
+    let parent = fast.last().unwrap().difficulty();
+    assert_eq!(next(algorithm.as_ref(), &fast), parent * 4u32);
+
+    // Off the epoch boundary nothing moves, however slow the blocks are.
+    let mid_epoch = &slow[..slow.len() - 1];
+    let parent = mid_epoch.last().unwrap().difficulty();
+    assert_eq!(next(algorithm.as_ref(), mid_epoch), parent);
+}
+
+#[test]
+fn lwma_holds_steady_at_the_target_block_time() {
+    let algorithm = DifficultySpec::Lwma {
+        target_block_time: TARGET_BLOCK_TIME,
+        window: LWMA_WINDOW,
+    }
+    .algorithm();
+
+    let ancestors = chain(2 * LWMA_WINDOW as u32, TARGET_BLOCK_TIME, 1000);
+    let parent = ancestors.last().unwrap().difficulty();
+
+    assert_eq!(next(algorithm.as_ref(), &ancestors), parent);
+}
+
+#[test]
+fn homestead_drops_by_at_most_ninety_nine_steps() {
+    let algorithm = DifficultySpec::Homestead {
+        target_block_time: TARGET_BLOCK_TIME,
+    }
+    .algorithm();
+
+    let parent = block(0, 0, 2048 * 1000);
+    let difficulty = parent.difficulty();
+    let floor = &difficulty - &difficulty / 2048u32 * 99u32;
+
+    // 1 - 100 is exactly the floor; anything slower is clamped to it.
+    for elapsed in [100, 1000, 1_000_000] {
+        let next =
+            algorithm.next_difficulty(std::slice::from_ref(&parent), elapsed * TARGET_BLOCK_TIME);
+        assert_eq!(next, floor, "elapsed {} block times", elapsed);
+    }
+
+    // One block time short of the floor still drops a step less.
+    let next = algorithm.next_difficulty(std::slice::from_ref(&parent), 99 * TARGET_BLOCK_TIME);
+    assert_eq!(next, &difficulty - &difficulty / 2048u32 * 98u32);
+}
@@ -0,0 +1,62 @@
+// Pins the test vectors published in the `encoding` module docs, so a change
+// to the header layout cannot leave them stale.
+
+use num_bigint::BigUint;
+
+use simple_blockchain::blockchain::{
+    block::{Block, BlockHeaders},
+    pow::PowSpec,
+};
+use simple_blockchain::helpers::keccak256_bytes;
+
+fn word(value: u8) -> [u8; 32] {
+    let mut bytes = [0; 32];
+    bytes[31] = value;
+    bytes
+}
+
+fn hex(value: &BigUint) -> String {
+    format!("{:064x}", value)
+}
+
+#[test]
+fn header_test_vectors() {
+    let block = Block::new(
+        1,
+        BigUint::from(4u32),
+        BigUint::from(5u32),
+        2,
+        3,
+        Vec::new(),
+        BigUint::from(6u32),
+    )
+    .with_extra_nonce(7);
+
+    let mut expected = Vec::new();
+    expected.extend_from_slice(&1u32.to_be_bytes());
+    expected.extend_from_slice(&2u32.to_be_bytes());
+    expected.extend_from_slice(&3u64.to_be_bytes());
+    expected.extend_from_slice(&7u64.to_be_bytes());
+    expected.extend_from_slice(&word(4));
+    expected.extend_from_slice(&word(5));
+    expected.extend_from_slice(&word(0));
+    expected.extend_from_slice(&word(0));
+    expected.push(0);
+
+    let headers = block.headers().encode().unwrap();
+    assert_eq!(headers, expected);
+    assert_eq!(headers.len(), 153);
+    assert_eq!(&BlockHeaders::decode(&headers).unwrap(), block.headers());
+
+    assert_eq!(
+        hex(&keccak256_bytes(&headers)),
+        "c6f82f98d8300697028fdfd7898255c510f758e9c2a213736891bf6a7249caff"
+    );
+    assert_eq!(hex(&block.hash().unwrap()), hex(&keccak256_bytes(&headers)));
+
+    let pow = PowSpec::Keccak.algorithm();
+    assert_eq!(
+        hex(&pow.hash(&headers, &BigUint::from(6u32)).unwrap()),
+        "8a2ed7321951fd21d0e4428e244716b4cacdb7532c34fc2e6722880ad6fad46b"
+    );
+}
@@ -0,0 +1,101 @@
+// Checks that block headers commit to the body through `transactions_root`:
+// a body that does not match is rejected, and every entry comes with a proof
+// that checks out against the headers alone, and only for that entry.
+
+mod common;
+
+use num_bigint::BigUint;
+use num_traits::Zero;
+
+use simple_blockchain::blockchain::{
+    block::{Block, BlockBody, BodyEntry, InclusionProof},
+    blockchain::Blockchain,
+    chain_spec::ChainSpec,
+    consensus::ConsensusSpec,
+    crypto::Signer,
+    error::{BodyError, ValidateBlockError},
+    transaction::Transaction,
+};
+
+const CHAIN_ID: u64 = 24;
+
+fn seal(chain: &Blockchain, pending: &BlockBody) -> Block {
+    common::seal(chain, &BigUint::from(1u32), pending)
+}
+
+#[test]
+fn body_entries_are_proven_against_the_headers() {
+    let alice = Signer::from_bytes(&[1; 32]).unwrap();
+
+    let mut spec = ChainSpec {
+        chain_id: CHAIN_ID,
+        consensus: ConsensusSpec::InstantSeal,
+        ..ChainSpec::default()
+    };
+    spec.genesis
+        .alloc
+        .insert(format!("0x{:x}", alice.address()), 1000);
+
+    let mut chain = Blockchain::new(spec).unwrap();
+
+    let empty = seal(&chain, &BlockBody::default());
+    assert!(empty.headers().transactions_root().is_zero());
+    chain
+        .add_block(empty.clone())
+        .expect("Empty block rejected");
+
+    // An odd number of entries, so one node goes up a level unpaired.
+    let pending = BlockBody {
+        transactions: (0..5)
+            .map(|nonce| {
+                Transaction::new(CHAIN_ID, nonce, BigUint::from(2u32), 10, 1, Vec::new())
+                    .signed(&alice)
+                    .unwrap()
+            })
+            .collect(),
+        ..BlockBody::default()
+    };
+    let block = seal(&chain, &pending);
+    assert_eq!(block.body(), &pending);
+    chain.add_block(block.clone()).expect("Block rejected");
+
+    let headers = block.headers();
+
+    for (index, transaction) in pending.transactions.iter().enumerate() {
+        let proof = block.proof(index).expect("No proof");
+
+        assert_eq!(proof.entry, BodyEntry::Transaction(transaction.to_owned()));
+        assert!(proof.verify(headers));
+        assert!(!proof.verify(empty.headers()));
+
+        // What a light client gets over the wire verifies just the same.
+        let received: InclusionProof =
+            serde_json::from_str(&serde_json::to_string(&proof).unwrap()).unwrap();
+        assert!(received.verify(headers));
+
+        // The path of one entry does not prove another.
+        let other = pending.transactions[(index + 1) % pending.transactions.len()].to_owned();
+        let forged = InclusionProof {
+            entry: BodyEntry::Transaction(other),
+            ..proof
+        };
+        assert!(!forged.verify(headers));
+    }
+
+    assert!(block.proof(pending.transactions.len()).is_none());
+
+    // A body swapped under the same headers no longer matches the root.
+    let mut swapped = serde_json::to_value(seal(&chain, &BlockBody::default())).unwrap();
+    swapped["body"] = serde_json::to_value(&pending).unwrap();
+    let swapped: Block = serde_json::from_value(swapped).unwrap();
+
+    assert_eq!(
+        chain.add_block(swapped).err(),
+        Some(ValidateBlockError::Body(
+            BodyError::InvalidTransactionsRoot {
+                expected: pending.transactions_root().unwrap(),
+                actual: BigUint::zero(),
+            }
+        ))
+    );
+}
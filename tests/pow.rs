@@ -0,0 +1,22 @@
+// Checks the limits on proof-of-work parameters a chain spec may choose.
+
+use simple_blockchain::blockchain::pow::{PowSpec, MAX_SCRYPT_LOG_N, MAX_SCRYPT_P, MAX_SCRYPT_R};
+
+fn scrypt(log_n: u8, r: u32, p: u32) -> Result<(), String> {
+    PowSpec::Scrypt { log_n, r, p }.validate()
+}
+
+#[test]
+fn scrypt_parameters_are_bounded() {
+    // Litecoin's, and the most expensive allowed.
+    assert_eq!(scrypt(10, 1, 1), Ok(()));
+    assert_eq!(scrypt(MAX_SCRYPT_LOG_N, MAX_SCRYPT_R, MAX_SCRYPT_P), Ok(()));
+
+    assert!(scrypt(MAX_SCRYPT_LOG_N + 1, 1, 1).is_err());
+    assert!(scrypt(10, MAX_SCRYPT_R + 1, 1).is_err());
+    assert!(scrypt(10, 1, MAX_SCRYPT_P + 1).is_err());
+
+    // What the scrypt crate itself rejects is still caught.
+    assert!(scrypt(10, 0, 1).is_err());
+    assert!(scrypt(10, 1, 0).is_err());
+}
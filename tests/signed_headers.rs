@@ -0,0 +1,81 @@
+// Checks that producer signatures are binding under every engine: a signed
+// block must be signed with the beneficiary's key, and a proof-of-authority
+// chain turns away unsigned blocks.
+
+mod common;
+
+use simple_blockchain::blockchain::{
+    block::{Block, BlockBody},
+    blockchain::Blockchain,
+    chain_spec::ChainSpec,
+    consensus::ConsensusSpec,
+    crypto::Signer,
+    error::{SealError, ValidateBlockError},
+};
+
+fn template(chain: &Blockchain, producer: &Signer) -> Block {
+    common::seal(chain, &producer.address(), &BlockBody::default())
+}
+
+#[test]
+fn signatures_bind_the_beneficiary() {
+    let producer = Signer::from_bytes(&[1; 32]).unwrap();
+    let impostor = Signer::from_bytes(&[2; 32]).unwrap();
+
+    let mut chain = Blockchain::new(ChainSpec {
+        consensus: ConsensusSpec::InstantSeal,
+        ..ChainSpec::default()
+    })
+    .unwrap();
+
+    // Unsigned blocks stay valid where the engine does not ask for signatures.
+    chain
+        .add_block(template(&chain, &producer))
+        .expect("Unsigned block rejected");
+
+    let signed = template(&chain, &producer).signed(&producer).unwrap();
+    assert_eq!(signed.signer(), Some(producer.address()));
+    chain.add_block(signed).expect("Signed block rejected");
+
+    // Someone else's key cannot vouch for the beneficiary.
+    let forged = template(&chain, &producer).signed(&impostor).unwrap();
+    assert_eq!(
+        chain.add_block(forged).err(),
+        Some(ValidateBlockError::Seal(SealError::InvalidSignature {
+            beneficiary: producer.address(),
+            signer: Some(impostor.address()),
+        }))
+    );
+
+    // Neither can a signature moved over from another block.
+    let moved = template(&chain, &producer)
+        .with_extra_data(vec![1])
+        .with_signature(
+            template(&chain, &producer)
+                .signed(&producer)
+                .unwrap()
+                .signature()
+                .to_vec(),
+        );
+    assert!(matches!(
+        chain.add_block(moved),
+        Err(ValidateBlockError::Seal(SealError::InvalidSignature { .. }))
+    ));
+
+    let mut authority = Blockchain::new(ChainSpec {
+        consensus: ConsensusSpec::Authority {
+            period: 1,
+            signers: vec![producer.address()],
+        },
+        ..ChainSpec::default()
+    })
+    .unwrap();
+
+    assert_eq!(
+        authority.add_block(template(&authority, &producer)).err(),
+        Some(ValidateBlockError::Seal(SealError::MissingSignature))
+    );
+
+    let signed = template(&authority, &producer).signed(&producer).unwrap();
+    authority.add_block(signed).expect("Signed block rejected");
+}
@@ -0,0 +1,354 @@
+// Builds a proof-of-stake chain in one process and checks that bonding and
+// unbonding only move proposer stake at epoch boundaries, that balances pay
+// for bonds and get unbonded coins back when the epoch ends, and that a
+// proposer caught double-signing is slashed and never proposes again, while
+// its delegators can still unbond what is left.
+
+use std::collections::BTreeMap;
+
+use num_bigint::BigUint;
+
+use simple_blockchain::blockchain::{
+    block::{Block, BlockBody},
+    blockchain::Blockchain,
+    chain_spec::ChainSpec,
+    consensus::{ConsensusSpec, Seal},
+    crypto::Signer,
+    error::{BodyError, SealError, StakingError, ValidateBlockError},
+    staking::{Stake, StakeAction, StakingTransaction},
+};
+
+const CHAIN_ID: u64 = 19;
+const EPOCH_LENGTH: u32 = 4;
+const MIN_STAKE: u64 = 100;
+const SLASH_PERCENT: u8 = 50;
+
+fn signer(seed: u8) -> Signer {
+    Signer::from_bytes(&[seed; 32]).expect("Invalid key")
+}
+
+fn spec(validators: &[&Signer], delegator: &Signer, joiner: &Signer) -> ChainSpec {
+    let mut spec = ChainSpec {
+        name: "staking-epochs".to_string(),
+        chain_id: CHAIN_ID,
+        consensus: ConsensusSpec::ProofOfStake {
+            period: 1,
+            epoch_length: EPOCH_LENGTH,
+            min_stake: MIN_STAKE,
+            slash_percent: SLASH_PERCENT,
+            stakes: validators
+                .iter()
+                .map(|validator| (format!("0x{:x}", validator.address()), MIN_STAKE))
+                .collect(),
+        },
+        ..ChainSpec::default()
+    };
+
+    spec.genesis
+        .alloc
+        .insert(format!("0x{:x}", delegator.address()), 1000);
+    spec.genesis
+        .alloc
+        .insert(format!("0x{:x}", joiner.address()), 500);
+
+    spec
+}
+
+// Seals the next block as whichever of `signers` gets the earliest attempt,
+// with `pending` in its body.
+fn propose(chain: &Blockchain, signers: &[&Signer], pending: &BlockBody) -> Block {
+    let parent = chain.get_last_block().unwrap().timestamp();
+
+    signers
+        .iter()
+        .filter_map(|signer| {
+            match chain.prepare_seal(signer.address(), parent, &BTreeMap::new(), pending) {
+                Seal::Ready { block, not_before } => Some((not_before, signer, block)),
+                _ => None,
+            }
+        })
+        .min_by_key(|(not_before, _, _)| *not_before)
+        .and_then(|(_, signer, block)| block.signed(signer))
+        .expect("Nobody may propose")
+}
+
+fn produce(chain: &mut Blockchain, signers: &[&Signer], pending: BlockBody) -> Block {
+    let block = propose(chain, signers, &pending);
+
+    assert_eq!(block.body(), &pending, "Pending body was left out");
+    chain.add_block(block.clone()).expect("Block rejected");
+
+    block
+}
+
+// Produces empty blocks until the head is the first block of an epoch.
+fn finish_epoch(chain: &mut Blockchain, signers: &[&Signer]) {
+    while !chain
+        .get_last_block()
+        .unwrap()
+        .number()
+        .is_multiple_of(EPOCH_LENGTH)
+    {
+        produce(chain, signers, BlockBody::default());
+    }
+}
+
+fn active(chain: &Blockchain, number: u32) -> Vec<Stake> {
+    chain.validators_at(number).unwrap().stake.active.to_owned()
+}
+
+fn stake(validator: &Signer, amount: u64) -> Stake {
+    Stake {
+        validator: validator.address(),
+        amount,
+    }
+}
+
+fn by_address(mut stakes: Vec<Stake>) -> Vec<Stake> {
+    stakes.sort_by(|a, b| a.validator.cmp(&b.validator));
+    stakes
+}
+
+fn transaction(
+    sender: &Signer,
+    action: StakeAction,
+    validator: &Signer,
+    amount: u64,
+    nonce: u64,
+) -> StakingTransaction {
+    StakingTransaction::new(CHAIN_ID, action, validator.address(), amount, nonce)
+        .signed(sender)
+        .unwrap()
+}
+
+#[test]
+fn bonds_move_at_epochs_and_double_signers_are_jailed() {
+    let (alice, bob) = (signer(1), signer(2));
+    let (delegator, joiner) = (signer(3), signer(4));
+    let validators = [&alice, &bob, &joiner];
+
+    let mut chain = Blockchain::new(spec(&[&alice, &bob], &delegator, &joiner)).unwrap();
+    let subsidy = |address: &BigUint, chain: &Blockchain| {
+        (1..=chain.get_last_block().unwrap().number())
+            .filter(|number| chain.get_block_by_number(*number).unwrap().beneficiary() == address)
+            .map(|number| chain.spec().reward.subsidy(number))
+            .sum::<u64>()
+    };
+
+    // Blocks must be signed by their proposer.
+    let unsigned = propose(&chain, &validators, &BlockBody::default()).with_signature(Vec::new());
+    assert!(matches!(
+        chain.add_block(unsigned),
+        Err(ValidateBlockError::Seal(SealError::MissingSignature))
+    ));
+
+    // Block 1 delegates 300 to bob and makes the joiner a validator.
+    let genesis_stake = by_address(vec![stake(&alice, 100), stake(&bob, 100)]);
+    assert_eq!(active(&chain, 0), genesis_stake);
+
+    produce(
+        &mut chain,
+        &validators,
+        BlockBody {
+            staking: vec![
+                transaction(&delegator, StakeAction::Bond, &bob, 300, 0),
+                transaction(&joiner, StakeAction::Bond, &joiner, 150, 0),
+            ],
+            ..BlockBody::default()
+        },
+    );
+
+    assert_eq!(chain.balance(&delegator.address()), 700);
+    assert_eq!(
+        chain.balance(&joiner.address()),
+        350 + subsidy(&joiner.address(), &chain)
+    );
+
+    // Bonded, but not drawn from until the epoch ends at block 4.
+    for _ in 2..EPOCH_LENGTH {
+        produce(&mut chain, &validators, BlockBody::default());
+    }
+
+    assert_eq!(active(&chain, EPOCH_LENGTH - 1), genesis_stake);
+
+    produce(&mut chain, &validators, BlockBody::default());
+
+    let delegated_stake = by_address(vec![
+        stake(&alice, 100),
+        stake(&bob, 400),
+        stake(&joiner, 150),
+    ]);
+    assert_eq!(active(&chain, EPOCH_LENGTH), delegated_stake);
+
+    // Unbonding in block 5 keeps the stake and the coins locked until block 8.
+    produce(
+        &mut chain,
+        &validators,
+        BlockBody {
+            staking: vec![transaction(&delegator, StakeAction::Unbond, &bob, 300, 1)],
+            ..BlockBody::default()
+        },
+    );
+
+    for _ in EPOCH_LENGTH + 2..2 * EPOCH_LENGTH {
+        produce(&mut chain, &validators, BlockBody::default());
+    }
+
+    assert_eq!(active(&chain, 2 * EPOCH_LENGTH - 1), delegated_stake);
+    assert_eq!(chain.balance(&delegator.address()), 700);
+
+    produce(&mut chain, &validators, BlockBody::default());
+
+    assert_eq!(
+        active(&chain, 2 * EPOCH_LENGTH),
+        by_address(vec![
+            stake(&alice, 100),
+            stake(&bob, 100),
+            stake(&joiner, 150),
+        ])
+    );
+    assert_eq!(chain.balance(&delegator.address()), 1000);
+
+    // The delegator backs every validator, including whoever double-signs.
+    produce(
+        &mut chain,
+        &validators,
+        BlockBody {
+            staking: validators
+                .iter()
+                .enumerate()
+                .map(|(nonce, validator)| {
+                    transaction(
+                        &delegator,
+                        StakeAction::Bond,
+                        validator,
+                        200,
+                        2 + nonce as u64,
+                    )
+                })
+                .collect(),
+            ..BlockBody::default()
+        },
+    );
+    assert_eq!(chain.balance(&delegator.address()), 400);
+
+    // The next proposer signs two blocks for the same attempt.
+    let first = propose(&chain, &validators, &BlockBody::default());
+    let offender = validators
+        .iter()
+        .find(|validator| validator.address() == *first.beneficiary())
+        .unwrap();
+    let second = first
+        .clone()
+        .with_timestamp(first.timestamp() + 1)
+        .signed(offender)
+        .unwrap();
+
+    chain.add_block(first).expect("First block rejected");
+    chain
+        .add_block(second.clone())
+        .expect("Second block rejected");
+
+    let evidence = chain.find_double_sign(&second).expect("Double sign missed");
+    assert_eq!(evidence.offender(), Some(offender.address()));
+
+    let bonded = chain
+        .validators_at(chain.get_last_block().unwrap().number())
+        .unwrap()
+        .stake
+        .bonded(&offender.address(), &offender.address());
+
+    produce(
+        &mut chain,
+        &validators,
+        BlockBody {
+            evidence: vec![evidence],
+            ..BlockBody::default()
+        },
+    );
+
+    let head = chain.get_last_block().unwrap().number();
+    let after = chain.validators_at(head).unwrap();
+
+    assert!(after.stake.is_jailed(&offender.address()));
+    assert!(!after.contains(&offender.address()));
+    assert_eq!(
+        after.stake.bonded(&offender.address(), &offender.address()),
+        bonded - bonded * SLASH_PERCENT as u64 / 100
+    );
+    assert_eq!(
+        after
+            .stake
+            .bonded(&delegator.address(), &offender.address()),
+        100
+    );
+
+    // Nobody may bond to a jailed validator, but its delegators may still
+    // take back what the slash left them, once the epoch ends.
+    let mut ledger = after.stake.clone();
+    assert_eq!(
+        ledger.apply(&transaction(
+            &delegator,
+            StakeAction::Bond,
+            offender,
+            100,
+            5
+        )),
+        Err(StakingError::Jailed {
+            validator: offender.address(),
+        })
+    );
+
+    finish_epoch(&mut chain, &validators);
+    produce(
+        &mut chain,
+        &validators,
+        BlockBody {
+            staking: vec![transaction(
+                &delegator,
+                StakeAction::Unbond,
+                offender,
+                100,
+                5,
+            )],
+            ..BlockBody::default()
+        },
+    );
+    assert_eq!(chain.balance(&delegator.address()), 400);
+
+    finish_epoch(&mut chain, &validators);
+    assert_eq!(chain.balance(&delegator.address()), 500);
+
+    // Jailed for good: not drawn this epoch, nor active in the next ones.
+    for _ in 0..3 * EPOCH_LENGTH {
+        let block = produce(&mut chain, &validators, BlockBody::default());
+
+        assert_ne!(block.beneficiary(), &offender.address());
+    }
+
+    assert!(!active(&chain, chain.get_last_block().unwrap().number())
+        .iter()
+        .any(|stake| stake.validator == offender.address()));
+
+    chain.validate_chain().expect("Chain is invalid");
+}
+
+#[test]
+fn staking_transactions_need_a_signature() {
+    let (alice, bob) = (signer(1), signer(2));
+    let chain = Blockchain::new(spec(&[&alice, &bob], &signer(3), &signer(4))).unwrap();
+
+    let unsigned = StakingTransaction::new(CHAIN_ID, StakeAction::Bond, bob.address(), 100, 0);
+    let body = BlockBody {
+        staking: vec![unsigned],
+        ..BlockBody::default()
+    };
+
+    assert_eq!(
+        Block::verify_transactions(&body, chain.spec()),
+        Err(BodyError::Staking {
+            index: 0,
+            error: StakingError::InvalidSignature,
+        })
+    );
+}
@@ -0,0 +1,191 @@
+// Checks that block headers commit to the ledger through `state_root`: a
+// block claiming any other state is rejected, and accounts and outputs come
+// with proofs that check out against the headers alone, including proofs
+// that an account is empty or an output is spent.
+
+mod common;
+
+use num_bigint::BigUint;
+use num_traits::Zero;
+
+use simple_blockchain::blockchain::{
+    block::{Block, BlockBody},
+    blockchain::Blockchain,
+    chain_spec::ChainSpec,
+    consensus::ConsensusSpec,
+    crypto::Signer,
+    error::{BodyError, ValidateBlockError},
+    ledger::LedgerSpec,
+    state::AccountProof,
+    transaction::Transaction,
+    utxo::{Output, UtxoTransaction},
+};
+
+use common::{state, utxos};
+
+const CHAIN_ID: u64 = 25;
+
+fn seal(chain: &Blockchain, pending: &BlockBody) -> Block {
+    common::seal(chain, &BigUint::from(1u32), pending)
+}
+
+fn alice() -> Signer {
+    Signer::from_bytes(&[1; 32]).unwrap()
+}
+
+#[test]
+fn accounts() {
+    let alice = &alice();
+    let bob = Signer::from_bytes(&[2; 32]).unwrap().address();
+    let nobody = BigUint::from(0xdeadu32);
+
+    let mut spec = ChainSpec {
+        chain_id: CHAIN_ID,
+        consensus: ConsensusSpec::InstantSeal,
+        ..ChainSpec::default()
+    };
+    spec.genesis
+        .alloc
+        .insert(format!("0x{:x}", alice.address()), 1000);
+
+    let mut chain = Blockchain::new(spec).unwrap();
+    let genesis = chain.get_last_block().unwrap().clone();
+
+    // The genesis headers already commit to the allocations.
+    assert_eq!(genesis.headers().state_root(), &state(&chain).root());
+    assert!(state(&chain)
+        .proof(&alice.address())
+        .verify(genesis.headers()));
+
+    let pending = BlockBody {
+        transactions: (0..3)
+            .map(|nonce| {
+                Transaction::new(CHAIN_ID, nonce, bob.to_owned(), 100, 1, Vec::new())
+                    .signed(alice)
+                    .unwrap()
+            })
+            .collect(),
+        ..BlockBody::default()
+    };
+    let block = seal(&chain, &pending);
+    assert_eq!(block.body(), &pending);
+
+    // Headers claiming any other state are turned away.
+    let expected = block.headers().state_root().to_owned();
+    assert_eq!(
+        chain
+            .add_block(block.clone().with_state_root(BigUint::zero()))
+            .err(),
+        Some(ValidateBlockError::Body(BodyError::InvalidStateRoot {
+            expected,
+            actual: BigUint::zero(),
+        }))
+    );
+
+    chain.add_block(block.clone()).expect("Block rejected");
+    assert_eq!(block.headers().state_root(), &state(&chain).root());
+
+    let headers = block.headers();
+
+    for address in [alice.address(), bob.to_owned(), BigUint::from(1u32)] {
+        let proof = state(&chain).proof(&address);
+
+        assert_eq!(proof.account.as_ref(), state(&chain).account(&address));
+        assert!(proof.verify(headers));
+
+        // What a light client gets over the wire verifies just the same.
+        let received: AccountProof =
+            serde_json::from_str(&serde_json::to_string(&proof).unwrap()).unwrap();
+        assert!(received.verify(headers));
+
+        // Balances the block did not leave are not proven by it.
+        let mut inflated = proof.clone();
+        inflated.account.as_mut().unwrap().balance += 1;
+        assert!(!inflated.verify(headers));
+
+        let hidden = AccountProof {
+            account: None,
+            ..proof
+        };
+        assert!(!hidden.verify(headers));
+    }
+
+    assert_eq!(state(&chain).balance(&bob), 300);
+    assert!(!state(&chain).proof(&bob).verify(genesis.headers()));
+
+    // An account that was never touched is proven empty, and nothing more.
+    let absent = state(&chain).proof(&nobody);
+    assert!(absent.account.is_none());
+    assert!(absent.verify(headers));
+
+    let mut claimed = absent.clone();
+    claimed.account = state(&chain).account(&bob).cloned();
+    assert!(!claimed.verify(headers));
+
+    // Nor does one address's proof stand in for another's.
+    let moved = AccountProof {
+        address: nobody,
+        ..state(&chain).proof(&bob)
+    };
+    assert!(!moved.verify(headers));
+
+    chain.validate_chain().expect("Chain is invalid");
+}
+
+#[test]
+fn outputs() {
+    let alice = &alice();
+    let mut spec = ChainSpec {
+        chain_id: CHAIN_ID,
+        consensus: ConsensusSpec::InstantSeal,
+        ledger: LedgerSpec::Utxo,
+        ..ChainSpec::default()
+    };
+    spec.genesis
+        .alloc
+        .insert(format!("0x{:x}", alice.address()), 100);
+
+    let mut chain = Blockchain::new(spec).unwrap();
+    let genesis = chain.get_last_block().unwrap().clone();
+    let allocation = utxos(&chain)
+        .outputs_of(&alice.address())
+        .map(|(outpoint, _)| outpoint.to_owned())
+        .next()
+        .expect("Allocation missing");
+
+    assert_eq!(genesis.headers().state_root(), &utxos(&chain).root());
+
+    let spend = UtxoTransaction::new(
+        CHAIN_ID,
+        vec![allocation.clone()],
+        vec![Output {
+            owner: BigUint::from(2u32),
+            value: 90,
+        }],
+    )
+    .signed(alice)
+    .unwrap();
+    let pending = BlockBody {
+        utxo: vec![spend.clone()],
+        ..BlockBody::default()
+    };
+    let block = seal(&chain, &pending);
+    assert_eq!(block.body(), &pending);
+    chain.add_block(block.clone()).expect("Block rejected");
+
+    let headers = block.headers();
+
+    // The new output is proven unspent and the allocation spent, which the
+    // genesis headers say the opposite of.
+    let created = utxos(&chain).proof(&spend.outpoint(0).unwrap()).unwrap();
+    assert!(created.output.is_some());
+    assert!(created.verify(headers));
+    assert!(!created.verify(genesis.headers()));
+
+    let spent = utxos(&chain).proof(&allocation).unwrap();
+    assert!(spent.output.is_none());
+    assert!(spent.verify(headers));
+    assert!(!spent.verify(genesis.headers()));
+
+    chain.validate_chain().expect("Chain is invalid");
+}
@@ -0,0 +1,313 @@
+// Drives the pool server with a minimal client: a worker subscribes, gets a
+// job, has shares accepted or rejected against its share target, a second
+// worker on the same connection is held to the harder target it asked for,
+// and finally the first submits a share that solves the block under a rolled
+// extra nonce.
+
+use std::sync::Arc;
+
+use num_bigint::BigUint;
+use serde_json::{json, Value};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines},
+    net::{
+        tcp::{OwnedReadHalf, OwnedWriteHalf},
+        TcpListener, TcpStream, ToSocketAddrs,
+    },
+    sync::RwLock,
+};
+
+use simple_blockchain::{
+    blockchain::{
+        blockchain::Blockchain, chain_spec::ChainSpec, pow::PowAlgorithm, target::Target,
+    },
+    helpers::keccak256_bytes,
+    stratum::{StratumJob, StratumMessage, StratumServer},
+    AppState,
+};
+
+const WORKER: &str = "worker.1";
+
+const SHARE_DIFFICULTY: u32 = 16;
+
+// Asks for harder shares than `WORKER`, on the same connection.
+const STRONG_WORKER: &str = "worker.2";
+
+const STRONG_DIFFICULTY: u64 = 256;
+
+// Minimal client for the pool protocol.
+struct StratumClient {
+    lines: Lines<BufReader<OwnedReadHalf>>,
+    write: OwnedWriteHalf,
+    next_id: u64,
+    jobs: Vec<StratumJob>,
+}
+
+impl StratumClient {
+    async fn connect(addr: impl ToSocketAddrs) -> std::io::Result<Self> {
+        let (read, write) = TcpStream::connect(addr).await?.into_split();
+
+        Ok(StratumClient {
+            lines: BufReader::new(read).lines(),
+            write,
+            next_id: 0,
+            jobs: Vec::new(),
+        })
+    }
+
+    async fn call(&mut self, method: &str, params: Vec<Value>) -> std::io::Result<StratumMessage> {
+        self.next_id += 1;
+
+        let request = StratumMessage {
+            id: Some(self.next_id),
+            method: Some(method.to_string()),
+            params,
+            result: None,
+            error: None,
+        };
+
+        let mut line = serde_json::to_vec(&request)?;
+        line.push(b'\n');
+        self.write.write_all(&line).await?;
+
+        loop {
+            let message = self.read().await?;
+
+            if message.id == Some(self.next_id) {
+                return Ok(message);
+            }
+        }
+    }
+
+    async fn subscribe(&mut self) -> std::io::Result<StratumMessage> {
+        self.call("mining.subscribe", Vec::new()).await
+    }
+
+    async fn authorize(&mut self, worker: &str) -> std::io::Result<StratumMessage> {
+        self.call("mining.authorize", vec![json!(worker), json!("")])
+            .await
+    }
+
+    async fn suggest_difficulty(
+        &mut self,
+        difficulty: u64,
+        worker: &str,
+    ) -> std::io::Result<StratumMessage> {
+        self.call(
+            "mining.suggest_difficulty",
+            vec![json!(difficulty), json!(worker)],
+        )
+        .await
+    }
+
+    // `extra_nonce` is what the worker put in the header, if not the job's.
+    async fn submit(
+        &mut self,
+        worker: &str,
+        job_id: u64,
+        nonce: &BigUint,
+        extra_nonce: Option<u64>,
+    ) -> std::io::Result<StratumMessage> {
+        let mut params = vec![
+            json!(worker),
+            json!(job_id),
+            json!(format!("0x{:x}", nonce)),
+        ];
+        params.extend(extra_nonce.map(|extra_nonce| json!(extra_nonce)));
+
+        self.call("mining.submit", params).await
+    }
+
+    // Latest job announced by the server, waiting for one if none arrived yet.
+    async fn next_job(&mut self) -> std::io::Result<StratumJob> {
+        while self.jobs.is_empty() {
+            self.read().await?;
+        }
+
+        Ok(self.jobs.remove(0))
+    }
+
+    async fn read(&mut self) -> std::io::Result<StratumMessage> {
+        let line = self.lines.next_line().await?.ok_or_else(|| {
+            std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "stratum server closed")
+        })?;
+
+        let message: StratumMessage = serde_json::from_str(&line)
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))?;
+
+        if message.method.as_deref() == Some("mining.notify") {
+            if let Some(job) = message
+                .params
+                .first()
+                .and_then(|job| serde_json::from_value(job.clone()).ok())
+            {
+                self.jobs.push(job);
+            }
+        }
+
+        Ok(message)
+    }
+}
+
+// Header of `job` with `extra_nonce` at byte 16, where the worker rolls it.
+fn with_extra_nonce(job: &StratumJob, extra_nonce: u64) -> Vec<u8> {
+    let mut header = job.header.clone();
+    header[16..24].copy_from_slice(&extra_nonce.to_be_bytes());
+    header
+}
+
+// First nonce whose hash of `header` `accept`s.
+fn search(pow: &dyn PowAlgorithm, header: &[u8], accept: impl Fn(&BigUint) -> bool) -> BigUint {
+    (0u64..)
+        .map(BigUint::from)
+        .find(|nonce| accept(&pow.hash(header, nonce).unwrap()))
+        .unwrap()
+}
+
+// Error the server answered a share with, if it was not accepted.
+async fn submit(
+    client: &mut StratumClient,
+    worker: &str,
+    job: &StratumJob,
+    nonce: &BigUint,
+    extra_nonce: Option<u64>,
+) -> Option<String> {
+    client
+        .submit(worker, job.job_id, nonce, extra_nonce)
+        .await
+        .unwrap()
+        .error
+}
+
+#[tokio::test]
+async fn shares_and_blocks() {
+    let mut spec = ChainSpec::default();
+    spec.genesis.difficulty = 2000;
+    let pow = spec.pow.algorithm();
+
+    let state = Arc::new(RwLock::new(AppState::new(Blockchain::new(spec).unwrap())));
+    let mut tip = state.read().await.subscribe_tip();
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = Arc::new(StratumServer::new(
+        Arc::clone(&state),
+        SHARE_DIFFICULTY,
+        BigUint::from(0x900du32),
+    ));
+    tokio::spawn({
+        let server = Arc::clone(&server);
+        async move { server.serve(listener).await }
+    });
+
+    let mut client = StratumClient::connect(addr).await.unwrap();
+    assert_eq!(
+        client.subscribe().await.unwrap().result,
+        Some(serde_json::json!(true))
+    );
+
+    let job = client.next_job().await.unwrap();
+    let block_target = Target::new(job.target.to_owned()).unwrap();
+    let share_target = Target::new(job.share_target.to_owned()).unwrap();
+    assert!(job.clean);
+    assert!(share_target > block_target);
+
+    let miss = search(&*pow, &job.header, |hash| !share_target.is_met_by(hash));
+    let share = search(&*pow, &job.header, |hash| {
+        share_target.is_met_by(hash) && !block_target.is_met_by(hash)
+    });
+
+    // Nothing counts before the worker is authorized.
+    assert_eq!(
+        submit(&mut client, WORKER, &job, &share, None)
+            .await
+            .as_deref(),
+        Some("unauthorized worker")
+    );
+    client.authorize(WORKER).await.unwrap();
+
+    assert_eq!(
+        submit(&mut client, WORKER, &job, &miss, None)
+            .await
+            .as_deref(),
+        Some("share above target")
+    );
+    assert_eq!(submit(&mut client, WORKER, &job, &share, None).await, None);
+    assert_eq!(
+        submit(&mut client, WORKER, &job, &share, None)
+            .await
+            .as_deref(),
+        Some("duplicate share")
+    );
+
+    // Suggestions below the pool's minimum are clamped to it, and only
+    // authorized workers get to make them.
+    assert_eq!(
+        client.suggest_difficulty(1, WORKER).await.unwrap().result,
+        Some(json!(SHARE_DIFFICULTY))
+    );
+    assert_eq!(
+        client
+            .suggest_difficulty(STRONG_DIFFICULTY, STRONG_WORKER)
+            .await
+            .unwrap()
+            .error
+            .as_deref(),
+        Some("unauthorized worker")
+    );
+
+    // Each worker is held to its own share target.
+    client.authorize(STRONG_WORKER).await.unwrap();
+    assert_eq!(
+        client
+            .suggest_difficulty(STRONG_DIFFICULTY, STRONG_WORKER)
+            .await
+            .unwrap()
+            .result,
+        Some(json!(STRONG_DIFFICULTY))
+    );
+
+    let strong_target = Target::from_difficulty(&BigUint::from(STRONG_DIFFICULTY));
+    let header = with_extra_nonce(&job, 2);
+    let weak = search(&*pow, &header, |hash| {
+        share_target.is_met_by(hash) && !strong_target.is_met_by(hash)
+    });
+    let strong = search(&*pow, &header, |hash| {
+        strong_target.is_met_by(hash) && !block_target.is_met_by(hash)
+    });
+
+    assert_eq!(
+        submit(&mut client, STRONG_WORKER, &job, &weak, Some(2))
+            .await
+            .as_deref(),
+        Some("share above target")
+    );
+    assert_eq!(
+        submit(&mut client, STRONG_WORKER, &job, &strong, Some(2)).await,
+        None
+    );
+
+    // The worker ran out of nonces and rolled the extra nonce.
+    let header = with_extra_nonce(&job, 1);
+    let solution = search(&*pow, &header, |hash| block_target.is_met_by(hash));
+    assert_eq!(
+        submit(&mut client, WORKER, &job, &solution, Some(1)).await,
+        None
+    );
+
+    let stats = &server.workers()[WORKER];
+    assert_eq!((stats.accepted, stats.rejected, stats.blocks), (2, 2, 1));
+    let stats = &server.workers()[STRONG_WORKER];
+    assert_eq!((stats.accepted, stats.rejected, stats.blocks), (1, 1, 0));
+
+    // The block is the new head, and the worker moves on to its child.
+    tip.changed().await.unwrap();
+    let head = keccak256_bytes(&header);
+    assert_eq!(*tip.borrow(), Some(head.to_owned()));
+
+    let next = client.next_job().await.unwrap();
+    assert!(next.clean);
+    // Jobs keep the easier of the two share targets.
+    assert_eq!(next.share_target, job.share_target);
+    assert_eq!(BigUint::from_bytes_be(&next.header[24..56]), head);
+}
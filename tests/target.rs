@@ -0,0 +1,108 @@
+// Known compact encodings, mostly taken from Bitcoin, so that the header
+// `bits` field stays compatible with the format it borrows.
+
+use num_bigint::BigUint;
+
+use simple_blockchain::blockchain::target::{Target, MAX_TARGET};
+
+fn target(bits: u32) -> BigUint {
+    Target::from_compact(bits)
+        .unwrap_or_else(|| panic!("{:#010x} rejected", bits))
+        .value()
+        .to_owned()
+}
+
+fn shifted(mantissa: u32, bytes: u32) -> BigUint {
+    BigUint::from(mantissa) << (8 * bytes)
+}
+
+#[test]
+fn compact_round_trips() {
+    let vectors = [
+        // Bitcoin's genesis target.
+        (0x1d00ffff, shifted(0xffff, 26)),
+        (0x1b0404cb, shifted(0x0404cb, 24)),
+        (0x03123456, BigUint::from(0x123456u32)),
+        (0x04123456, shifted(0x123456, 1)),
+        (0x20123456, shifted(0x123456, 29)),
+    ];
+
+    for (bits, value) in vectors {
+        assert_eq!(target(bits), value, "{:#010x}", bits);
+        assert_eq!(
+            Target::new(value).unwrap().to_compact(),
+            bits,
+            "{:#010x}",
+            bits
+        );
+    }
+}
+
+#[test]
+fn short_targets_drop_the_low_mantissa_bytes() {
+    assert_eq!(target(0x01123456), BigUint::from(0x12u32));
+    assert_eq!(target(0x02123456), BigUint::from(0x1234u32));
+
+    assert_eq!(
+        Target::new(BigUint::from(0x12u32)).unwrap().to_compact(),
+        0x01120000
+    );
+    assert_eq!(
+        Target::new(BigUint::from(0x1234u32)).unwrap().to_compact(),
+        0x02123400
+    );
+}
+
+#[test]
+fn high_bits_move_into_the_next_byte() {
+    // Each of these would set the sign bit in a three byte mantissa.
+    let vectors = [
+        (BigUint::from(0x80u32), 0x02008000),
+        (BigUint::from(0x92340000u32), 0x05009234),
+        (shifted(0x800000, 26), 0x1e008000),
+        (MAX_TARGET.clone(), 0x2100ffff),
+    ];
+
+    for (value, bits) in vectors {
+        let compact = Target::new(value.clone()).unwrap().to_compact();
+
+        assert_eq!(compact, bits, "{:#x}", value);
+        assert_eq!(compact & 0x0080_0000, 0, "{:#x}", value);
+        // Renormalising may drop low bits, but never high ones.
+        assert!(target(compact) <= value, "{:#x}", value);
+    }
+
+    // The largest target survives the round trip with its top bytes intact.
+    assert_eq!(target(0x2100ffff), shifted(0xffff, 30));
+}
+
+#[test]
+fn negative_zero_and_oversized_encodings_are_rejected() {
+    let negative = [0x04923456, 0x01800000, 0x1d80ffff];
+    let zero = [0x00000000, 0x00123456, 0x1d000000];
+    let oversized = [0x21010000, 0x21123456, 0x22000100, 0x23000001, 0xff123456];
+
+    for bits in negative.into_iter().chain(zero).chain(oversized) {
+        assert!(Target::from_compact(bits).is_none(), "{:#010x}", bits);
+    }
+}
+
+#[test]
+fn work_of_known_targets() {
+    // Bitcoin's chainwork after its genesis block.
+    assert_eq!(
+        Target::from_compact(0x1d00ffff).unwrap().work(),
+        BigUint::from(0x100010001u64)
+    );
+
+    // Every hash meets the easiest target.
+    assert_eq!(
+        Target::new(MAX_TARGET.clone()).unwrap().work(),
+        BigUint::from(1u32)
+    );
+
+    // Halving the target doubles the work.
+    let easy = Target::new((BigUint::from(1u32) << 200) - 1u32).unwrap();
+    let hard = Target::new((BigUint::from(1u32) << 199) - 1u32).unwrap();
+    assert_eq!(easy.work() * 2u32, hard.work());
+}
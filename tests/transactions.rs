@@ -0,0 +1,127 @@
+// Checks that blocks carry transactions signed with either scheme, and that
+// validation turns away unsigned or tampered transactions and transactions
+// signed for another chain.
+
+mod common;
+
+use num_bigint::BigUint;
+
+use simple_blockchain::blockchain::{
+    block::{Block, BlockBody},
+    blockchain::Blockchain,
+    chain_spec::ChainSpec,
+    consensus::ConsensusSpec,
+    crypto::{Ed25519Signer, Signer},
+    error::{BodyError, TransactionError, ValidateBlockError},
+    transaction::Transaction,
+};
+
+use common::seal;
+
+const CHAIN_ID: u64 = 21;
+
+fn with_transactions(block: Block, transactions: Vec<Transaction>) -> Block {
+    block.with_body(BlockBody {
+        transactions,
+        ..BlockBody::default()
+    })
+}
+
+fn rejection(chain: &mut Blockchain, transaction: Transaction) -> Option<TransactionError> {
+    let block = with_transactions(
+        seal(chain, &BigUint::from(1u32), &BlockBody::default()),
+        vec![transaction],
+    );
+
+    match chain.add_block(block) {
+        Err(ValidateBlockError::Body(BodyError::Transaction { index: 0, error })) => Some(error),
+        Err(error) => panic!("Unexpected rejection: {}", error),
+        Ok(_) => None,
+    }
+}
+
+#[test]
+fn transactions_are_signed_for_this_chain() {
+    let alice = Signer::from_bytes(&[1; 32]).unwrap();
+    let bob = Ed25519Signer::from_bytes(&[2; 32]).unwrap();
+
+    let mut spec = ChainSpec {
+        chain_id: CHAIN_ID,
+        consensus: ConsensusSpec::InstantSeal,
+        ..ChainSpec::default()
+    };
+
+    for address in [alice.address(), bob.address()] {
+        spec.genesis.alloc.insert(format!("0x{:x}", address), 100);
+    }
+
+    let mut chain = Blockchain::new(spec).unwrap();
+
+    let from_alice = Transaction::new(CHAIN_ID, 0, bob.address(), 10, 1, b"hi".to_vec())
+        .signed(&alice)
+        .unwrap();
+    let from_bob = Transaction::new(CHAIN_ID, 0, alice.address(), 5, 1, Vec::new())
+        .signed_ed25519(&bob)
+        .unwrap();
+
+    assert_eq!(from_alice.sender(), Some(alice.address()));
+    assert_eq!(from_bob.sender(), Some(bob.address()));
+
+    // Both schemes go into a sealed block and survive the wire encoding.
+    let pending = BlockBody {
+        transactions: vec![from_alice.clone(), from_bob.clone()],
+        ..BlockBody::default()
+    };
+    let block = seal(&chain, &BigUint::from(1u32), &pending);
+
+    assert_eq!(block.body(), &pending);
+    assert_eq!(Block::decode(&block.encode().unwrap()).unwrap(), block);
+    chain.add_block(block).expect("Block rejected");
+
+    // A transaction made for another chain cannot be replayed here.
+    let replayed = Transaction::new(CHAIN_ID + 1, 0, bob.address(), 10, 1, Vec::new())
+        .signed(&alice)
+        .unwrap();
+    assert_eq!(
+        rejection(&mut chain, replayed.clone()),
+        Some(TransactionError::WrongChain {
+            expected: CHAIN_ID,
+            actual: CHAIN_ID + 1,
+        })
+    );
+
+    // Nor is it picked up from the pool.
+    let block = seal(
+        &chain,
+        &BigUint::from(1u32),
+        &BlockBody {
+            transactions: vec![replayed],
+            ..BlockBody::default()
+        },
+    );
+    assert!(block.body().transactions.is_empty());
+
+    assert_eq!(
+        rejection(
+            &mut chain,
+            Transaction::new(CHAIN_ID, 1, bob.address(), 10, 1, Vec::new())
+        ),
+        Some(TransactionError::InvalidSignature)
+    );
+
+    let tampered = Transaction {
+        value: 500,
+        ..from_bob.clone()
+    };
+    assert_eq!(
+        rejection(&mut chain, tampered),
+        Some(TransactionError::InvalidSignature)
+    );
+
+    // A tampered secp256k1 transaction recovers to some other sender.
+    let tampered = Transaction {
+        value: 500,
+        ..from_alice
+    };
+    assert_ne!(tampered.sender(), Some(alice.address()));
+}
@@ -0,0 +1,235 @@
+// Runs a chain on the UTXO ledger and checks that outputs are spent whole,
+// once per branch, by their owners only; that fees and the subsidy are paid
+// to an output of the block; and that a reorg brings back what the
+// disconnected blocks spent.
+
+mod common;
+
+use num_bigint::BigUint;
+
+use simple_blockchain::blockchain::{
+    block::BlockBody,
+    blockchain::Blockchain,
+    chain_spec::ChainSpec,
+    consensus::ConsensusSpec,
+    crypto::{Ed25519Signer, Signer},
+    error::{BodyError, UtxoError, ValidateBlockError},
+    ledger::LedgerSpec,
+    transaction::Transaction,
+    utxo::{coinbase_id, OutPoint, Output, UtxoTransaction},
+};
+
+use common::{seal, utxos};
+
+const CHAIN_ID: u64 = 23;
+
+fn body(utxo: Vec<UtxoTransaction>) -> BlockBody {
+    BlockBody {
+        utxo,
+        ..BlockBody::default()
+    }
+}
+
+fn pay(owner: &BigUint, value: u64) -> Output {
+    Output {
+        owner: owner.to_owned(),
+        value,
+    }
+}
+
+fn rejection(chain: &mut Blockchain, pending: Vec<UtxoTransaction>) -> BodyError {
+    let block = seal(chain, &BigUint::default(), &BlockBody::default()).with_body(body(pending));
+
+    match chain.add_block(block) {
+        Err(ValidateBlockError::Body(error)) => error,
+        Err(error) => panic!("Unexpected rejection: {}", error),
+        Ok(_) => panic!("Block accepted"),
+    }
+}
+
+#[test]
+fn outputs_are_spent_once_per_branch() {
+    let alice = Signer::from_bytes(&[1; 32]).unwrap();
+    let bob = Ed25519Signer::from_bytes(&[2; 32]).unwrap();
+    let carol = Signer::from_bytes(&[3; 32]).unwrap();
+    let (miner, rival) = (BigUint::from(0x111u32), BigUint::from(0x222u32));
+
+    let mut spec = ChainSpec {
+        chain_id: CHAIN_ID,
+        consensus: ConsensusSpec::InstantSeal,
+        ledger: LedgerSpec::Utxo,
+        ..ChainSpec::default()
+    };
+    spec.genesis
+        .alloc
+        .insert(format!("0x{:x}", alice.address()), 100);
+
+    let mut chain = Blockchain::new(spec.clone()).unwrap();
+    let subsidy = spec.reward.subsidy(1);
+    let genesis = utxos(&chain)
+        .outputs_of(&alice.address())
+        .map(|(outpoint, _)| outpoint.to_owned())
+        .next()
+        .expect("Allocation missing");
+
+    // Alice splits her allocation, leaving 10 as the fee.
+    let split = UtxoTransaction::new(
+        CHAIN_ID,
+        vec![genesis.clone()],
+        vec![pay(&bob.address(), 60), pay(&alice.address(), 30)],
+    )
+    .signed(&alice)
+    .unwrap();
+
+    let block = seal(&chain, &miner, &body(vec![split.clone()]));
+    chain.add_block(block.clone()).expect("Block rejected");
+
+    assert_eq!(chain.balance(&alice.address()), 30);
+    assert_eq!(chain.balance(&bob.address()), 60);
+    assert_eq!(
+        utxos(&chain).get(&OutPoint {
+            txid: coinbase_id(&block).unwrap(),
+            index: 0,
+        }),
+        Some(&pay(&miner, subsidy + 10))
+    );
+    assert!(utxos(&chain).get(&genesis).is_none());
+
+    // Outputs can be spent in the block that creates them.
+    let to_carol = UtxoTransaction::new(
+        CHAIN_ID,
+        vec![split.outpoint(0).unwrap()],
+        vec![pay(&carol.address(), 60)],
+    )
+    .signed_ed25519(&bob)
+    .unwrap();
+    let back_to_alice = UtxoTransaction::new(
+        CHAIN_ID,
+        vec![to_carol.outpoint(0).unwrap()],
+        vec![pay(&alice.address(), 55)],
+    )
+    .signed(&carol)
+    .unwrap();
+
+    let pending = body(vec![to_carol.clone(), back_to_alice]);
+    let block = seal(&chain, &miner, &pending);
+    assert_eq!(block.body(), &pending);
+    chain.add_block(block).expect("Chained spends rejected");
+
+    assert_eq!(chain.balance(&alice.address()), 85);
+    assert_eq!(chain.balance(&carol.address()), 0);
+
+    // An output already spent on this branch is gone from the set.
+    let respend = UtxoTransaction::new(
+        CHAIN_ID,
+        vec![split.outpoint(0).unwrap()],
+        vec![pay(&bob.address(), 60)],
+    )
+    .signed_ed25519(&bob)
+    .unwrap();
+    assert_eq!(
+        rejection(&mut chain, vec![respend.clone()]),
+        BodyError::Utxo {
+            index: 0,
+            error: UtxoError::UnknownInput {
+                outpoint: split.outpoint(0).unwrap(),
+            },
+        }
+    );
+
+    // Nor twice in one block.
+    let spend_change = |value| {
+        UtxoTransaction::new(
+            CHAIN_ID,
+            vec![split.outpoint(1).unwrap()],
+            vec![pay(&carol.address(), value)],
+        )
+        .signed(&alice)
+        .unwrap()
+    };
+    assert_eq!(
+        rejection(&mut chain, vec![spend_change(30), spend_change(29)]),
+        BodyError::Utxo {
+            index: 1,
+            error: UtxoError::DoubleSpend {
+                outpoint: split.outpoint(1).unwrap(),
+            },
+        }
+    );
+
+    // Only the owner can spend, and only what the output holds.
+    let stolen = UtxoTransaction::new(
+        CHAIN_ID,
+        vec![split.outpoint(1).unwrap()],
+        vec![pay(&carol.address(), 30)],
+    )
+    .signed(&carol)
+    .unwrap();
+    assert_eq!(
+        rejection(&mut chain, vec![stolen]),
+        BodyError::Utxo {
+            index: 0,
+            error: UtxoError::InvalidSignature { input: 0 },
+        }
+    );
+    assert_eq!(
+        rejection(&mut chain, vec![spend_change(31)]),
+        BodyError::Utxo {
+            index: 0,
+            error: UtxoError::InsufficientInput {
+                available: 30,
+                required: 31,
+            },
+        }
+    );
+
+    let missing = OutPoint {
+        txid: BigUint::from(7u32),
+        index: 0,
+    };
+    let unknown = UtxoTransaction::new(CHAIN_ID, vec![missing.clone()], Vec::new())
+        .signed(&alice)
+        .unwrap();
+    assert_eq!(
+        rejection(&mut chain, vec![unknown]),
+        BodyError::Utxo {
+            index: 0,
+            error: UtxoError::UnknownInput { outpoint: missing },
+        }
+    );
+
+    // Account transactions have no place on this ledger.
+    let account = Transaction::new(CHAIN_ID, 0, carol.address(), 1, 0, Vec::new())
+        .signed(&alice)
+        .unwrap();
+    let block = seal(&chain, &miner, &BlockBody::default()).with_body(BlockBody {
+        transactions: vec![account],
+        ..BlockBody::default()
+    });
+    assert_eq!(
+        chain.add_block(block).err(),
+        Some(ValidateBlockError::Body(BodyError::WrongLedger))
+    );
+
+    // A longer branch from block 1 takes over and gives bob his output back,
+    // which he may then spend on the new branch.
+    let mut fork = Blockchain::new(spec.clone()).unwrap();
+    fork.add_block(chain.get_block_by_number(1).unwrap().clone())
+        .unwrap();
+
+    for pending in [body(vec![respend]), BlockBody::default()] {
+        let block = seal(&fork, &rival, &pending);
+        assert_eq!(block.body(), &pending);
+        fork.add_block(block.clone()).unwrap();
+
+        chain.add_block(block).expect("Fork block rejected");
+    }
+
+    assert_eq!(chain.get_last_block(), fork.get_last_block());
+    assert_eq!(chain.balance(&alice.address()), 30);
+    assert_eq!(chain.balance(&bob.address()), 60);
+    assert!(utxos(&chain).get(&to_carol.outpoint(0).unwrap()).is_none());
+    assert_eq!(utxos(&chain), utxos(&fork));
+
+    chain.validate_chain().expect("Chain is invalid");
+}
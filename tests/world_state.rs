@@ -0,0 +1,152 @@
+// Checks that blocks run their transactions against the world state in
+// order: nonces and balances move together, fees and the subsidy go to the
+// beneficiary, a block with one bad transaction changes nothing, and a reorg
+// leaves the same accounts as replaying the new chain from genesis.
+
+mod common;
+
+use num_bigint::BigUint;
+use num_traits::Zero;
+
+use simple_blockchain::blockchain::{
+    block::BlockBody,
+    blockchain::Blockchain,
+    chain_spec::ChainSpec,
+    consensus::ConsensusSpec,
+    crypto::{self, Signer},
+    error::{BodyError, TransactionError, ValidateBlockError},
+    transaction::Transaction,
+};
+
+use common::{seal, state};
+
+const CHAIN_ID: u64 = 22;
+
+fn body(transactions: Vec<Transaction>) -> BlockBody {
+    BlockBody {
+        transactions,
+        ..BlockBody::default()
+    }
+}
+
+fn transfer(sender: &Signer, nonce: u64, recipient: &BigUint, value: u64, fee: u64) -> Transaction {
+    Transaction::new(
+        CHAIN_ID,
+        nonce,
+        recipient.to_owned(),
+        value,
+        fee,
+        Vec::new(),
+    )
+    .signed(sender)
+    .unwrap()
+}
+
+#[test]
+fn transactions_run_against_the_world_state() {
+    let (alice, bob) = (
+        Signer::from_bytes(&[1; 32]).unwrap(),
+        BigUint::from(0xb0bu32),
+    );
+    let (miner, rival) = (BigUint::from(0x111u32), BigUint::from(0x222u32));
+
+    let mut spec = ChainSpec {
+        chain_id: CHAIN_ID,
+        consensus: ConsensusSpec::InstantSeal,
+        ..ChainSpec::default()
+    };
+    spec.genesis
+        .alloc
+        .insert(format!("0x{:x}", alice.address()), 1000);
+
+    let mut chain = Blockchain::new(spec.clone()).unwrap();
+    let subsidy = spec.reward.subsidy(1);
+
+    let pending = body(vec![
+        transfer(&alice, 0, &bob, 100, 2),
+        transfer(&alice, 1, &bob, 50, 1),
+    ]);
+    let block = seal(&chain, &miner, &pending);
+    assert_eq!(block.body(), &pending);
+    chain.add_block(block).expect("Block rejected");
+
+    assert_eq!(chain.balance(&alice.address()), 1000 - 153);
+    assert_eq!(state(&chain).nonce(&alice.address()), 2);
+    assert_eq!(chain.balance(&bob), 150);
+    assert_eq!(chain.balance(&miner), subsidy + 3);
+
+    // Replayed or skipped nonces are turned away.
+    for nonce in [1, 3] {
+        let block = seal(&chain, &miner, &BlockBody::default())
+            .with_body(body(vec![transfer(&alice, nonce, &bob, 1, 0)]));
+
+        assert_eq!(
+            chain.add_block(block).err(),
+            Some(ValidateBlockError::Body(BodyError::Transaction {
+                index: 0,
+                error: TransactionError::InvalidNonce {
+                    expected: 2,
+                    actual: nonce,
+                },
+            }))
+        );
+    }
+
+    // An overdraft rejects the whole block, the valid transfer before it too.
+    let before = state(&chain).clone();
+    let block = seal(&chain, &miner, &BlockBody::default()).with_body(body(vec![
+        transfer(&alice, 2, &bob, 10, 0),
+        transfer(&alice, 3, &bob, 10_000, 0),
+    ]));
+
+    assert_eq!(
+        chain.add_block(block).err(),
+        Some(ValidateBlockError::Body(BodyError::Transaction {
+            index: 1,
+            error: TransactionError::InsufficientBalance {
+                balance: 1000 - 153 - 10,
+                required: 10_000,
+            },
+        }))
+    );
+    assert_eq!(state(&chain), &before);
+
+    // Nor does the overdraft get picked up from the pool.
+    let pending = body(vec![
+        transfer(&alice, 2, &bob, 10_000, 0),
+        transfer(&alice, 2, &bob, 10, 0),
+    ]);
+    let block = seal(&chain, &miner, &pending);
+    assert_eq!(block.body(), &body(vec![pending.transactions[1].clone()]));
+
+    // A transaction to the zero address with data deploys the data as code.
+    let code = vec![0x60, 0x00, 0x60, 0x00, 0xf3];
+    let deploy = Transaction::new(CHAIN_ID, 2, BigUint::zero(), 5, 0, code.clone())
+        .signed(&alice)
+        .unwrap();
+    let contract = crypto::contract_address(&alice.address(), 2).unwrap();
+
+    chain
+        .add_block(seal(&chain, &miner, &body(vec![deploy])))
+        .expect("Deployment rejected");
+    assert_eq!(state(&chain).code(&contract), code.as_slice());
+    assert_eq!(chain.balance(&contract), 5);
+
+    // A longer branch from block 1 without the deployment takes over.
+    let mut fork = Blockchain::new(spec.clone()).unwrap();
+    fork.add_block(chain.get_block_by_number(1).unwrap().clone())
+        .unwrap();
+
+    for _ in 0..2 {
+        let block = seal(&fork, &rival, &BlockBody::default());
+        fork.add_block(block.clone()).unwrap();
+
+        chain.add_block(block).expect("Fork block rejected");
+    }
+
+    assert_eq!(chain.get_last_block(), fork.get_last_block());
+    assert!(state(&chain).account(&contract).is_none());
+    assert_eq!(state(&chain).nonce(&alice.address()), 2);
+    assert_eq!(chain.balance(&rival), 2 * subsidy);
+    assert_eq!(state(&chain), state(&fork));
+}